@@ -0,0 +1,116 @@
+//! Benchmark mode
+//!
+//! `--benchmark N` converts the input N times in memory, without writing the output, and
+//! prints how long parsing, transforming and writing took in every run, so that the speed
+//! of two releases can be compared on the same file.
+
+use crate::conversion::{convert, format_bytes, ConversionInput, ConversionProgress, StageTimings};
+use crate::settings::Settings;
+use anyhow::{bail, Result};
+use std::fmt::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Stage timings and total time of one run
+struct Run {
+    timings: StageTimings,
+    total: Duration,
+}
+
+/// Timings of every run of a benchmark
+pub struct BenchmarkReport {
+    runs: Vec<Run>,
+    /// Number of data rows written by each run
+    rows: usize,
+    /// Size of the output of each run
+    output_bytes: usize,
+}
+
+/// Converts the input `runs` times and measures every run
+pub fn run(
+    input: &ConversionInput,
+    settings: &Settings,
+    selected_columns: &[String],
+    runs: usize,
+) -> Result<BenchmarkReport> {
+    let mut report = BenchmarkReport {
+        runs: Vec::with_capacity(runs),
+        rows: 0,
+        output_bytes: 0,
+    };
+    for _ in 0..runs {
+        let progress = Mutex::new(ConversionProgress::default());
+        let started = Instant::now();
+        let Some(result) = convert(
+            input,
+            settings,
+            selected_columns.to_vec(),
+            &progress,
+            &AtomicBool::new(false),
+        )?
+        else {
+            bail!("Conversion cancelled");
+        };
+        report.runs.push(Run {
+            timings: result.timings,
+            total: started.elapsed(),
+        });
+        report.rows = result.rows;
+        report.output_bytes = result.csv_content.len();
+    }
+    Ok(report)
+}
+
+impl BenchmarkReport {
+    /// Table with a line per run, followed by the mean and the fastest run
+    pub fn render(&self) -> String {
+        let mut table = format!(
+            "{} rows, {} of output per run\n\n{:<6}{:>12}{:>12}{:>12}{:>12}{:>14}\n",
+            self.rows,
+            format_bytes(self.output_bytes),
+            "Run",
+            "Parse",
+            "Transform",
+            "Write",
+            "Total",
+            "Rows/s"
+        );
+        for (index, run) in self.runs.iter().enumerate() {
+            self.render_line(&mut table, &(index + 1).to_string(), run);
+        }
+        if self.runs.len() > 1 {
+            let count = self.runs.len() as u32;
+            let sum = |stage: fn(&Run) -> Duration| self.runs.iter().map(stage).sum::<Duration>() / count;
+            let mean = Run {
+                timings: StageTimings {
+                    parse: sum(|run| run.timings.parse),
+                    transform: sum(|run| run.timings.transform),
+                    write: sum(|run| run.timings.write),
+                },
+                total: sum(|run| run.total),
+            };
+            self.render_line(&mut table, "Mean", &mean);
+            if let Some(best) = self.runs.iter().min_by_key(|run| run.total) {
+                self.render_line(&mut table, "Best", best);
+            }
+        }
+        table
+    }
+
+    fn render_line(&self, table: &mut String, label: &str, run: &Run) {
+        let millis = |duration: Duration| format!("{:.1} ms", duration.as_secs_f64() * 1000.0);
+        let rows_per_second = self.rows as f64 / run.total.as_secs_f64().max(f64::EPSILON);
+        // Writing to a String cannot fail
+        let _ = writeln!(
+            table,
+            "{:<6}{:>12}{:>12}{:>12}{:>12}{:>14.0}",
+            label,
+            millis(run.timings.parse),
+            millis(run.timings.transform),
+            millis(run.timings.write),
+            millis(run.total),
+            rows_per_second
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Checkpoints of streamed conversions
+//!
+//! A streamed JSON file converted to CSV with checkpoints enabled saves its progress every
+//! `INTERVAL`: the CSV written so far is appended to a partial file in the temporary
+//! directory, and a small JSON file records how far the input was read. When the same
+//! file is converted again with the same settings after a crash or a cancel, the
+//! conversion continues from there instead of starting over. A finished conversion removes
+//! its checkpoint.
+//!
+//! Checkpoints are only taken while the records of the top-level array are read, so input
+//! made of concatenated documents is checkpointed up to the end of the first one.
+
+use crate::settings::Settings;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Minimum time between two checkpoints
+pub const INTERVAL: Duration = Duration::from_secs(30);
+
+/// Progress of a conversion, saved as JSON next to the partial output
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Size and modification time of the input, to notice when it changed
+    pub input_len: u64,
+    pub input_modified: Option<SystemTime>,
+    /// Hash of the output settings and selected columns the conversion ran with, see
+    /// `output_fingerprint`
+    pub fingerprint: u64,
+    /// Input bytes read up to the end of the last record converted
+    pub input_offset: usize,
+    /// Number of input records converted
+    pub records: usize,
+    /// Number of rows rendered, including rejected ones
+    pub rendered_rows: usize,
+    /// Number of data rows written
+    pub rows: usize,
+    /// Number of truncated cells
+    pub truncated_cells: usize,
+    /// Number of cells changed by the text cleanup
+    pub cleaned_cells: usize,
+    /// Columns of the output, in order
+    pub columns: Vec<String>,
+    /// Byte offset where the data rows start in the output
+    pub body_offset: usize,
+    /// Size of the output written so far
+    pub output_len: usize,
+}
+
+/// Hash of the settings and selected columns, so a checkpoint is only resumed by a
+/// conversion that would produce the same output
+pub fn fingerprint(settings: &impl Serialize, selected_columns: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(settings).unwrap_or_default().hash(&mut hasher);
+    selected_columns.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Fingerprint of the settings that change the rows of CSV output, and of the selected
+/// columns
+///
+/// Settings of the application, of the other formats and of how the conversion runs, such
+/// as the number of threads, are left out, so changing them keeps the checkpoint. The
+/// input is told apart by its path, size and modification time instead.
+pub fn output_fingerprint(settings: &Settings, selected_columns: &[String]) -> u64 {
+    let csv = (
+        &settings.delimiter,
+        settings.include_headers,
+        settings.quote_fields,
+        settings.decimal_comma,
+        &settings.date_format,
+        &settings.encoding,
+        &settings.line_endings,
+    );
+    let parsing = (
+        settings.json5_fallback,
+        &settings.duplicate_keys,
+        &settings.records_path,
+        &settings.key_column,
+        &settings.geometry_columns,
+        &settings.csv_input,
+        &settings.ragged_rows,
+        settings.two_pass,
+    );
+    let cells = (
+        &settings.max_cell_length,
+        &settings.newline_handling,
+        &settings.newline_token,
+        &settings.text_cleanup,
+        &settings.transform_script,
+        &settings.column_defaults,
+        &settings.exclude_columns,
+        &settings.number_columns,
+        &settings.json_columns,
+        settings.pretty_json,
+        &settings.metadata_columns,
+        &settings.value_mappings,
+        &settings.anonymized_columns,
+        &settings.anonymization_mask,
+        &settings.anonymization_salt,
+    );
+    let validation = (&settings.validation_rules, &settings.invalid_rows, &settings.json_schema);
+    fingerprint(&(csv, parsing, cells, validation), selected_columns)
+}
+
+/// Paths of the checkpoint and the partial output of an input file
+fn paths(input: &Path) -> (PathBuf, PathBuf) {
+    let mut hasher = DefaultHasher::new();
+    fs::canonicalize(input)
+        .unwrap_or_else(|_| input.to_path_buf())
+        .hash(&mut hasher);
+    let dir = std::env::temp_dir().join("json_to_csv_converter-checkpoints");
+    let key = format!("{:016x}", hasher.finish());
+    (dir.join(format!("{}.json", key)), dir.join(format!("{}.partial", key)))
+}
+
+/// Size and modification time of an input file
+fn input_identity(input: &Path) -> Result<(u64, Option<SystemTime>)> {
+    let metadata = fs::metadata(input).context("Failed to read input file")?;
+    Ok((metadata.len(), metadata.modified().ok()))
+}
+
+/// Whether a checkpoint was saved for an input file; it may still not match the settings
+pub fn exists(input: &Path) -> bool {
+    paths(input).0.exists()
+}
+
+/// Removes the checkpoint of an input file, if there is one
+pub fn discard(input: &Path) {
+    let (checkpoint, partial) = paths(input);
+    let _ = fs::remove_file(checkpoint);
+    let _ = fs::remove_file(partial);
+}
+
+/// Loads the checkpoint of an input file with the output written up to it
+///
+/// `None` when there is no checkpoint, or it was taken of a different version of the file
+/// or with different settings.
+pub fn load(input: &Path, fingerprint: u64) -> Option<(Checkpoint, Vec<u8>)> {
+    let (checkpoint_path, partial_path) = paths(input);
+    let checkpoint: Checkpoint = serde_json::from_str(&fs::read_to_string(checkpoint_path).ok()?).ok()?;
+    let (input_len, input_modified) = input_identity(input).ok()?;
+    if checkpoint.fingerprint != fingerprint
+        || checkpoint.input_len != input_len
+        || checkpoint.input_modified != input_modified
+    {
+        return None;
+    }
+    // The partial output may have grown past the checkpoint before a crash
+    let mut output = fs::read(partial_path).ok()?;
+    if output.len() < checkpoint.output_len {
+        return None;
+    }
+    output.truncate(checkpoint.output_len);
+    Some((checkpoint, output))
+}
+
+/// Saves checkpoints of one conversion
+pub struct CheckpointWriter {
+    checkpoint_path: PathBuf,
+    partial: File,
+    /// Output bytes already in the partial file
+    saved: usize,
+    input_len: u64,
+    input_modified: Option<SystemTime>,
+    fingerprint: u64,
+}
+
+impl CheckpointWriter {
+    /// Starts saving checkpoints of an input file, continuing after the first
+    /// `resumed_len` bytes of the partial output when resuming and replacing any previous
+    /// checkpoint otherwise
+    pub fn create(input: &Path, fingerprint: u64, resumed_len: usize) -> Result<Self> {
+        let (checkpoint_path, partial_path) = paths(input);
+        if let Some(dir) = checkpoint_path.parent() {
+            fs::create_dir_all(dir).context("Failed to create checkpoint directory")?;
+        }
+        if resumed_len == 0 {
+            let _ = fs::remove_file(&checkpoint_path);
+        }
+        let mut partial = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&partial_path)
+            .context("Failed to create checkpoint")?;
+        partial
+            .set_len(resumed_len as u64)
+            .and_then(|()| partial.seek(SeekFrom::End(0)))
+            .context("Failed to create checkpoint")?;
+        let (input_len, input_modified) = input_identity(input)?;
+        Ok(Self {
+            checkpoint_path,
+            partial,
+            saved: resumed_len,
+            input_len,
+            input_modified,
+            fingerprint,
+        })
+    }
+
+    /// Appends the output written since the last checkpoint and records the progress
+    ///
+    /// `output` is everything written by this run, which followed the output of the
+    /// checkpoint it resumed from, if any; `checkpoint.output_len` is filled in.
+    pub fn save(&mut self, mut checkpoint: Checkpoint, resumed_len: usize, output: &[u8]) -> Result<()> {
+        let total = resumed_len + output.len();
+        self.partial
+            .write_all(&output[self.saved - resumed_len..])
+            .and_then(|()| self.partial.sync_data())
+            .context("Failed to write checkpoint")?;
+        self.saved = total;
+
+        checkpoint.input_len = self.input_len;
+        checkpoint.input_modified = self.input_modified;
+        checkpoint.fingerprint = self.fingerprint;
+        checkpoint.output_len = total;
+        // Written aside and renamed, so a crash never leaves a half-written checkpoint
+        let temporary = self.checkpoint_path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_string(&checkpoint)?)
+            .and_then(|()| fs::rename(&temporary, &self.checkpoint_path))
+            .context("Failed to write checkpoint")
+    }
+}
@@ -0,0 +1,1148 @@
+//! Command-line mode
+//!
+//! Passing any option runs a single conversion without opening the window, so the
+//! converter can be used from scripts and shell pipelines:
+//!
+//! ```text
+//! cat data.json | json_to_csv_converter --stdin > out.csv
+//! ```
+//!
+//! With `--serve` the same conversion is offered over HTTP instead, see `server`.
+//!
+//! The exit code is 0 after a complete conversion, 2 when records were skipped or rows
+//! rejected, and 1 on failure; `--report` also writes the outcome as JSON, so pipelines can
+//! gate on the quality of a conversion.
+//!
+//! Progress notes and warnings go to standard error: `--quiet` keeps only errors and
+//! `--verbose` adds debug details, while `--log-file` appends everything to a file.
+//!
+//! Plain file paths without options, as passed by a file manager's "Open with", open the
+//! window with those files loaded instead; `--convert` and `--portable` may accompany them.
+
+use crate::benchmark;
+use crate::cloud::{self, is_s3_uri};
+use crate::conversion::{analyze, convert, format_bytes, Analysis, ConversionInput, ConversionProgress};
+use crate::database::{self, Table};
+use crate::dictionary;
+use crate::input::InputFormat;
+use crate::locale::TargetLocale;
+use crate::logging;
+use crate::output::{append_to_csv_file, write_atomically};
+use crate::schema::{parse_avro_fields, JsonSchema};
+use crate::preset;
+use crate::server;
+use crate::settings::{
+    Anonymization, ColumnAnonymization, ColumnDefault, ColumnWidth, ControlCharacters, CsvDialect, DateFormat,
+    DictionaryFormat, DuplicateKeys, GeometryColumns, LineEndings, MappedValue, MetadataColumns, NewlineHandling,
+    OutputKind, RaggedRows, Settings, TextCleanup, TextEncoding, ValueMapping,
+};
+use crate::sheets;
+use crate::summary::ConversionSummary;
+use crate::validation::{self, InvalidRows};
+use anyhow::{bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+use tracing::{error, info, warn, Level};
+
+/// Convert JSON, YAML, TOML or CSV input to CSV
+#[derive(Parser)]
+#[command(name = "json_to_csv_converter", version)]
+#[command(group(ArgGroup::new("validation").multiple(true)))]
+struct Args {
+    /// Input file or `s3://bucket/key` URI
+    #[arg(required_unless_present_any = ["stdin", "serve"], conflicts_with_all = ["stdin", "serve"])]
+    input: Option<PathBuf>,
+    /// Read the input from standard input
+    #[arg(long)]
+    stdin: bool,
+    /// Output file or `s3://bucket/key` URI; standard output when omitted
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Input format; detected from the file extension when omitted, JSON for stdin
+    #[arg(short, long, value_enum)]
+    format: Option<FormatArg>,
+    /// Output format; `--template` selects templated text
+    #[arg(long, value_enum, default_value_t = OutputArg::Csv, conflicts_with = "template")]
+    output_format: OutputArg,
+    /// Avro record schema file for `--output-format avro`; generated from the values when
+    /// omitted
+    #[arg(long, value_name = "FILE")]
+    avro_schema: Option<PathBuf>,
+    /// Width of a column of `--output-format fixed-width`, as `COLUMN=WIDTH`; may be
+    /// repeated. Other columns are as wide as their longest value
+    #[arg(long = "width", value_name = "COLUMN=WIDTH", value_parser = parse_width)]
+    widths: Vec<(String, usize)>,
+    /// Comma-separated columns of `--output-format fixed-width` padded on the left
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    right_align: Vec<String>,
+    /// Column of `--output-format xlsx` whose values each get a sheet of their own
+    #[arg(long, value_name = "COLUMN")]
+    sheet_by: Option<String>,
+    /// CSV delimiter character; `,` unless `--locale` sets another
+    #[arg(short, long)]
+    delimiter: Option<String>,
+    /// Target locale setting the delimiter, decimal separator, date format, encoding and
+    /// line endings of the CSV output; the options below override single conventions
+    #[arg(long, value_enum)]
+    locale: Option<LocaleArg>,
+    /// Write JSON numbers with a fraction with a decimal comma, as `1,5`
+    #[arg(long)]
+    decimal_comma: bool,
+    /// How ISO 8601 dates such as `2024-03-05` are written
+    #[arg(long, value_enum)]
+    date_format: Option<DateFormatArg>,
+    /// Character encoding of the CSV output
+    #[arg(long, value_enum)]
+    encoding: Option<EncodingArg>,
+    /// End rows with `\r\n` instead of `\n`
+    #[arg(long)]
+    crlf: bool,
+    /// Columns to export, in order, separated by commas
+    #[arg(short, long, value_delimiter = ',')]
+    columns: Vec<String>,
+    /// Preset file saved in the window; options given along with it override its settings,
+    /// and its column selection applies unless `--columns` is given
+    #[arg(long, value_name = "FILE")]
+    preset: Option<PathBuf>,
+    /// Leave out the header row
+    #[arg(long)]
+    no_headers: bool,
+    /// Never quote fields
+    #[arg(long)]
+    no_quote: bool,
+    /// Truncate cells longer than this many characters
+    #[arg(long)]
+    max_cell_length: Option<usize>,
+    /// How line breaks inside fields are written
+    #[arg(long, value_enum, default_value_t = NewlinesArg::Keep)]
+    newlines: NewlinesArg,
+    /// Replacement for line breaks with `--newlines custom`
+    #[arg(long, default_value = " | ")]
+    newline_token: String,
+    /// Bring the text of cells to Unicode normalization form C
+    #[arg(long)]
+    normalize_unicode: bool,
+    /// Remove or escape control characters other than tab and line breaks, and zero-width
+    /// characters
+    #[arg(long, value_enum)]
+    control_chars: Option<ControlCharsArg>,
+    /// Only accept strict JSON, without the JSON5 fallback
+    #[arg(long)]
+    strict: bool,
+    /// Delimiter of CSV input, instead of sniffing it
+    #[arg(long, value_name = "CHAR")]
+    input_delimiter: Option<char>,
+    /// Quote character of CSV input, instead of sniffing it
+    #[arg(long, value_name = "CHAR")]
+    input_quote: Option<char>,
+    /// Whether the first row of CSV input is a header, instead of sniffing it
+    #[arg(long, value_enum)]
+    input_header: Option<HeaderArg>,
+    /// What happens to array records, e.g. CSV lines, with more or fewer fields than there
+    /// are columns
+    #[arg(long, value_enum, default_value_t = RaggedArg::Fix)]
+    ragged_rows: RaggedArg,
+    /// JSON Pointer of the array holding the records, such as `/data/items`; the file is read
+    /// whole rather than streamed
+    #[arg(long, value_name = "POINTER")]
+    records: Option<String>,
+    /// Column holding the keys of input that maps keys to records, like
+    /// `{"user1": {...}, "user2": {...}}`; empty to read such input as a single record
+    #[arg(long, value_name = "NAME", default_value = "id")]
+    key_column: String,
+    /// How the geometry of GeoJSON features is written
+    #[arg(long, value_enum, default_value_t = GeometryArg::Wkt)]
+    geometry: GeometryArg,
+    /// Report JSON objects that repeat a key and resolve them this way
+    #[arg(long, value_enum)]
+    duplicate_keys: Option<DuplicateKeysArg>,
+    /// Save the progress of a streamed conversion every 30 seconds and resume from the saved
+    /// progress when the same file is converted again with the same options
+    #[arg(long)]
+    checkpoint: bool,
+    /// Number of threads the conversion runs on; 0 for one per core
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    threads: usize,
+    /// Run the conversion threads at the lowest priority, so other programs stay responsive
+    #[arg(long)]
+    low_priority: bool,
+    /// Read the input twice, collecting the columns of all records before writing rows,
+    /// instead of taking them from the first record
+    #[arg(long)]
+    two_pass: bool,
+    /// Append the rows to an existing output file with a matching header
+    #[arg(long, requires = "output")]
+    append: bool,
+    /// Handlebars row template file; writes templated text instead of CSV
+    #[arg(long, value_name = "FILE")]
+    template: Option<PathBuf>,
+    /// Handlebars template file written before the rows, with `--template`
+    #[arg(long, value_name = "FILE", requires = "template")]
+    header_template: Option<PathBuf>,
+    /// Handlebars template file written after the rows, with `--template`
+    #[arg(long, value_name = "FILE", requires = "template")]
+    footer_template: Option<PathBuf>,
+    /// Rhai script file run on every record before it is written
+    #[arg(long, value_name = "FILE")]
+    script: Option<PathBuf>,
+    /// Value written for a column when a record lacks its key, as `COLUMN=VALUE`; may be
+    /// repeated
+    #[arg(long = "default", value_name = "COLUMN=VALUE", value_parser = parse_default)]
+    defaults: Vec<ColumnDefault>,
+    /// Comma-separated patterns of columns to leave out, with `*` and `?` as wildcards;
+    /// ignored with `--columns`
+    #[arg(long, value_name = "PATTERNS", value_delimiter = ',')]
+    exclude: Vec<String>,
+    /// Leave out columns that are empty or `null` in every row; holds the rows in memory
+    /// until the end
+    #[arg(long)]
+    drop_empty_columns: bool,
+    /// Comma-separated patterns of columns whose text is stripped down to the number, e.g.
+    /// `$1,234.50` to `1234.50`
+    #[arg(long, value_name = "PATTERNS", value_delimiter = ',')]
+    strip_units: Vec<String>,
+    /// Comma-separated patterns of columns written as the JSON text of their values, with
+    /// strings in quotes and no cleanup
+    #[arg(long, value_name = "PATTERNS", value_delimiter = ',')]
+    keep_json: Vec<String>,
+    /// Indent the JSON text of `--keep-json` columns over several lines
+    #[arg(long)]
+    pretty_json: bool,
+    /// Cell of a column written as another value, as `COLUMN:FROM=TO`, e.g. `status:1=active`;
+    /// may be repeated
+    #[arg(long = "map", value_name = "COLUMN:FROM=TO", value_parser = parse_mapping)]
+    mappings: Vec<(String, MappedValue)>,
+    /// Comma-separated patterns of columns whose cells are replaced with their SHA-256 hash
+    #[arg(long, value_name = "PATTERNS", value_delimiter = ',')]
+    hash: Vec<String>,
+    /// Text hashed along with every cell of `--hash` columns
+    #[arg(long, env = "JSON_TO_CSV_HASH_SALT", hide_env_values = true, default_value = "")]
+    hash_salt: String,
+    /// Comma-separated patterns of columns whose cells are replaced with `--mask-text`
+    #[arg(long, value_name = "PATTERNS", value_delimiter = ',')]
+    mask: Vec<String>,
+    /// Text replacing the cells of `--mask` columns
+    #[arg(long, default_value = "****")]
+    mask_text: String,
+    /// Comma-separated patterns of columns left out of the output, even when selected
+    #[arg(long, value_name = "PATTERNS", value_delimiter = ',')]
+    drop: Vec<String>,
+    /// Comma-separated metadata columns written before the others
+    #[arg(long, value_enum, value_name = "COLUMNS", value_delimiter = ',')]
+    metadata: Vec<MetadataArg>,
+    /// JSON file with validation rules, an array of objects with `column`, `required`,
+    /// `non_empty`, `column_type` (`Integer`, `Float` or `Boolean`), `min`, `max` and
+    /// `pattern`
+    #[arg(long, value_name = "FILE", group = "validation")]
+    rules: Option<PathBuf>,
+    /// JSON Schema of the records; its properties are the columns unless `--columns` is
+    /// given, declare their types and are validated like rules
+    #[arg(long, value_name = "FILE", group = "validation")]
+    json_schema: Option<PathBuf>,
+    /// Leave rows breaking a rule out of the output and write them to `rejected.csv` next
+    /// to it, instead of only reporting them
+    #[arg(long, requires = "validation")]
+    reject: bool,
+    /// Also write a data dictionary of the output columns next to the output, or to the
+    /// current directory
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    data_dictionary: Option<DictionaryArg>,
+    /// AWS region of S3 URIs; credentials are taken from the environment
+    #[arg(long, default_value = "us-east-1")]
+    s3_region: String,
+    /// Endpoint of an S3-compatible service
+    #[arg(long, default_value = "")]
+    s3_endpoint: String,
+    /// Insert the rows into a PostgreSQL or MySQL database instead of writing CSV
+    #[arg(long, value_name = "URL", requires = "db_table", conflicts_with = "output")]
+    db_url: Option<String>,
+    /// Table created or appended to with `--db-url`
+    #[arg(long, value_name = "NAME")]
+    db_table: Option<String>,
+    /// Print the DDL with `--db-url` without touching the database
+    #[arg(long, requires = "db_url")]
+    db_dry_run: bool,
+    /// Push the rows to a new Google Sheet with this title instead of writing CSV
+    #[arg(long, value_name = "TITLE", conflicts_with_all = ["output", "db_url"])]
+    google_sheet: Option<String>,
+    /// Client ID of the Google OAuth client used with `--google-sheet`
+    #[arg(long, env = "GOOGLE_CLIENT_ID", hide_env_values = true, default_value = "")]
+    google_client_id: String,
+    /// Client secret of the Google OAuth client used with `--google-sheet`
+    #[arg(long, env = "GOOGLE_CLIENT_SECRET", hide_env_values = true, default_value = "")]
+    google_client_secret: String,
+    /// Only report the row and column counts, column types and output size, converting a
+    /// sample of large inputs, without writing anything
+    #[arg(long, conflicts_with_all = ["output", "db_url", "google_sheet"])]
+    analyze: bool,
+    /// Convert the input this many times without writing anything and print the time
+    /// spent parsing, transforming and writing, with the rows per second
+    #[arg(long, value_name = "RUNS", value_parser = clap::value_parser!(u32).range(1..),
+          conflicts_with_all = ["output", "db_url", "google_sheet", "analyze"])]
+    benchmark: Option<u32>,
+    /// Serve `POST /convert` over HTTP on this address instead of converting once
+    #[arg(long, value_name = "ADDR", num_args = 0..=1, default_missing_value = server::DEFAULT_ADDR)]
+    serve: Option<String>,
+    /// Largest request body `--serve` accepts, in megabytes; larger ones are answered with
+    /// `413 Payload Too Large`
+    #[arg(long, value_name = "MB", default_value_t = server::DEFAULT_MAX_BODY_MB, requires = "serve")]
+    max_body_mb: u64,
+    /// Write the outcome of the conversion to this JSON file: status, exit code, rows
+    /// written, skipped records, warnings and lossy values
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["analyze", "benchmark", "serve"])]
+    report: Option<PathBuf>,
+    /// Also print debug details such as the stages of the conversion and their timings
+    #[arg(short, long, conflicts_with = "quiet")]
+    verbose: bool,
+    /// Only print errors
+    #[arg(short, long)]
+    quiet: bool,
+    /// Append all messages, debug details included, to this file
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+}
+
+/// Exit code of a conversion that skipped records or rejected rows
+const EXIT_PARTIAL: i32 = 2;
+
+/// Outcome of a command-line conversion, written by `--report`
+#[derive(Default, Serialize)]
+struct RunReport {
+    /// `success`, `partial` or `failure`
+    status: &'static str,
+    exit_code: i32,
+    records: usize,
+    rows: usize,
+    columns: Vec<String>,
+    skipped_records: usize,
+    invalid_rows: usize,
+    rejected_rows: usize,
+    output_bytes: usize,
+    duration_seconds: f64,
+    warnings: Vec<String>,
+    lossy_values: Vec<LossyValues>,
+    error: Option<String>,
+}
+
+/// Lossy cells of one column and issue in a `RunReport`
+#[derive(Serialize)]
+struct LossyValues {
+    column: String,
+    issue: String,
+    cells: usize,
+    /// Numbers of the first affected rows
+    rows: Vec<usize>,
+}
+
+impl RunReport {
+    fn new(exit_code: i32, summary: Option<&ConversionSummary>, error: Option<&anyhow::Error>) -> Self {
+        let status = match exit_code {
+            0 => "success",
+            EXIT_PARTIAL => "partial",
+            _ => "failure",
+        };
+        let mut report = Self {
+            status,
+            exit_code,
+            error: error.map(|e| format!("{:#}", e)),
+            ..Default::default()
+        };
+        if let Some(summary) = summary {
+            report.records = summary.records;
+            report.rows = summary.rows;
+            report.columns = summary.columns.clone();
+            report.skipped_records = summary.skipped_records;
+            report.invalid_rows = summary.invalid_rows;
+            report.rejected_rows = if summary.rejected { summary.invalid_rows } else { 0 };
+            report.output_bytes = summary.output_bytes;
+            report.duration_seconds = summary.duration.as_secs_f64();
+            report.warnings = summary.warnings.clone();
+            report.lossy_values = summary
+                .losses
+                .iter()
+                .map(|loss| LossyValues {
+                    column: loss.column.clone(),
+                    issue: loss.loss.describe(),
+                    cells: loss.cells,
+                    rows: loss.rows.clone(),
+                })
+                .collect();
+        }
+        report
+    }
+}
+
+/// Input format given on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    Json,
+    Yaml,
+    Toml,
+    Csv,
+}
+
+impl From<FormatArg> for InputFormat {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Json => InputFormat::Json,
+            FormatArg::Yaml => InputFormat::Yaml,
+            FormatArg::Toml => InputFormat::Toml,
+            FormatArg::Csv => InputFormat::Csv,
+        }
+    }
+}
+
+/// Output format given on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputArg {
+    Csv,
+    /// Arrow IPC file (Feather v2) with typed columns
+    Arrow,
+    /// Avro container file; see `--avro-schema`
+    Avro,
+    /// OpenDocument spreadsheet
+    Ods,
+    /// Columns padded to fixed widths; see `--width`
+    FixedWidth,
+    /// Excel workbook; see `--sheet-by`
+    Xlsx,
+}
+
+impl From<OutputArg> for OutputKind {
+    fn from(format: OutputArg) -> Self {
+        match format {
+            OutputArg::Csv => OutputKind::Csv,
+            OutputArg::Arrow => OutputKind::Arrow,
+            OutputArg::Avro => OutputKind::Avro,
+            OutputArg::Ods => OutputKind::Ods,
+            OutputArg::FixedWidth => OutputKind::FixedWidth,
+            OutputArg::Xlsx => OutputKind::Xlsx,
+        }
+    }
+}
+
+/// Newline handling given on the command line
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum NewlinesArg {
+    Keep,
+    Space,
+    Escape,
+    Custom,
+}
+
+impl From<NewlinesArg> for NewlineHandling {
+    fn from(newlines: NewlinesArg) -> Self {
+        match newlines {
+            NewlinesArg::Keep => NewlineHandling::Keep,
+            NewlinesArg::Space => NewlineHandling::Space,
+            NewlinesArg::Escape => NewlineHandling::Escape,
+            NewlinesArg::Custom => NewlineHandling::Custom,
+        }
+    }
+}
+
+/// GeoJSON geometry columns given on the command line
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum GeometryArg {
+    /// Well-Known Text in a `geometry` column
+    Wkt,
+    /// `longitude` and `latitude` columns of the point, or the center of other geometries
+    Lonlat,
+}
+
+impl From<GeometryArg> for GeometryColumns {
+    fn from(geometry: GeometryArg) -> Self {
+        match geometry {
+            GeometryArg::Wkt => GeometryColumns::Wkt,
+            GeometryArg::Lonlat => GeometryColumns::LonLat,
+        }
+    }
+}
+
+/// Target locale of the CSV output given on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum LocaleArg {
+    /// Commas, decimal points, ISO dates, UTF-8 and `\n`
+    Standard,
+    /// Excel in the US: commas, `03/05/2024`, UTF-8 with byte order mark and `\r\n`
+    ExcelUs,
+    /// Excel in the UK: commas, `05/03/2024`, UTF-8 with byte order mark and `\r\n`
+    ExcelUk,
+    /// Excel in Germany: semicolons, decimal commas, `05.03.2024`, Windows-1252 and `\r\n`
+    ExcelDe,
+    /// Excel in France: semicolons, decimal commas, `05/03/2024`, Windows-1252 and `\r\n`
+    ExcelFr,
+}
+
+impl From<LocaleArg> for TargetLocale {
+    fn from(locale: LocaleArg) -> Self {
+        match locale {
+            LocaleArg::Standard => TargetLocale::Standard,
+            LocaleArg::ExcelUs => TargetLocale::ExcelUs,
+            LocaleArg::ExcelUk => TargetLocale::ExcelUk,
+            LocaleArg::ExcelDe => TargetLocale::ExcelGermany,
+            LocaleArg::ExcelFr => TargetLocale::ExcelFrance,
+        }
+    }
+}
+
+/// Date format given on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum DateFormatArg {
+    /// `2024-03-05`, unchanged
+    Iso,
+    /// `03/05/2024`
+    Mdy,
+    /// `05/03/2024`
+    Dmy,
+    /// `05.03.2024`
+    DmyDots,
+}
+
+impl From<DateFormatArg> for DateFormat {
+    fn from(format: DateFormatArg) -> Self {
+        match format {
+            DateFormatArg::Iso => DateFormat::Iso,
+            DateFormatArg::Mdy => DateFormat::MonthDayYear,
+            DateFormatArg::Dmy => DateFormat::DayMonthYear,
+            DateFormatArg::DmyDots => DateFormat::DayMonthYearDots,
+        }
+    }
+}
+
+/// Output encoding given on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum EncodingArg {
+    Utf8,
+    /// UTF-8 starting with a byte order mark
+    Utf8Bom,
+    /// Windows-1252; characters it lacks are written as `?`
+    Windows1252,
+}
+
+impl From<EncodingArg> for TextEncoding {
+    fn from(encoding: EncodingArg) -> Self {
+        match encoding {
+            EncodingArg::Utf8 => TextEncoding::Utf8,
+            EncodingArg::Utf8Bom => TextEncoding::Utf8Bom,
+            EncodingArg::Windows1252 => TextEncoding::Windows1252,
+        }
+    }
+}
+
+/// Handling of ragged records given on the command line
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum RaggedArg {
+    /// Pad short records with empty cells and cut long ones
+    Fix,
+    /// Leave them out, which makes the conversion partial
+    Skip,
+    /// Fail at the first one
+    Fail,
+}
+
+impl From<RaggedArg> for RaggedRows {
+    fn from(ragged: RaggedArg) -> Self {
+        match ragged {
+            RaggedArg::Fix => RaggedRows::Fix,
+            RaggedArg::Skip => RaggedRows::Skip,
+            RaggedArg::Fail => RaggedRows::Fail,
+        }
+    }
+}
+
+/// Data dictionary format given on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum DictionaryArg {
+    /// `<name>_dictionary.csv`
+    Csv,
+    /// `<name>_dictionary.md`, a Markdown table
+    Md,
+}
+
+impl From<DictionaryArg> for DictionaryFormat {
+    fn from(format: DictionaryArg) -> Self {
+        match format {
+            DictionaryArg::Csv => DictionaryFormat::Csv,
+            DictionaryArg::Md => DictionaryFormat::Markdown,
+        }
+    }
+}
+
+/// Control character cleanup given on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum ControlCharsArg {
+    Strip,
+    /// Write them as `\u001B`, `\u200B` and so on
+    Escape,
+}
+
+impl From<ControlCharsArg> for ControlCharacters {
+    fn from(cleanup: ControlCharsArg) -> Self {
+        match cleanup {
+            ControlCharsArg::Strip => ControlCharacters::Strip,
+            ControlCharsArg::Escape => ControlCharacters::Escape,
+        }
+    }
+}
+
+/// Duplicate key strategy given on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum DuplicateKeysArg {
+    /// Keep the first value
+    First,
+    /// Keep the last value
+    Last,
+    /// Keep every value, in columns named `key_2`, `key_3`, …
+    Suffix,
+}
+
+impl From<DuplicateKeysArg> for DuplicateKeys {
+    fn from(strategy: DuplicateKeysArg) -> Self {
+        match strategy {
+            DuplicateKeysArg::First => DuplicateKeys::KeepFirst,
+            DuplicateKeysArg::Last => DuplicateKeys::KeepLast,
+            DuplicateKeysArg::Suffix => DuplicateKeys::Suffix,
+        }
+    }
+}
+
+/// Header presence of CSV input given on the command line
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum HeaderArg {
+    Yes,
+    /// Name the columns `col_0`, `col_1`, …
+    No,
+}
+
+/// Metadata column given on the command line
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum MetadataArg {
+    /// `_row_number`, the number of the data row
+    RowNumber,
+    /// `_source_file`, the path of the input
+    SourceFile,
+    /// `_json_pointer`, the JSON Pointer of the input record
+    JsonPointer,
+}
+
+/// Window option that converts the files given at startup right away
+const CONVERT_FLAG: &str = "--convert";
+
+/// Window option that keeps the configuration beside the executable, see `config`
+const PORTABLE_FLAG: &str = "--portable";
+
+/// Arguments for the window
+pub struct Startup {
+    /// Files to open, each in its own tab
+    pub files: Vec<PathBuf>,
+    /// Whether to convert them right away
+    pub convert: bool,
+    /// Whether to use portable mode
+    pub portable: bool,
+}
+
+/// Whether the arguments ask for command-line mode rather than the window
+pub fn is_requested() -> bool {
+    std::env::args_os().skip(1).any(|arg| {
+        let arg = arg.to_string_lossy();
+        arg.starts_with('-') && arg != CONVERT_FLAG && arg != PORTABLE_FLAG
+    })
+}
+
+/// Reads the arguments for the window
+///
+/// Only called when `is_requested` is false, so every argument but the window options is
+/// a path.
+pub fn startup() -> Startup {
+    let mut startup = Startup {
+        files: Vec::new(),
+        convert: false,
+        portable: false,
+    };
+    for arg in std::env::args_os().skip(1) {
+        if arg == CONVERT_FLAG {
+            startup.convert = true;
+        } else if arg == PORTABLE_FLAG {
+            startup.portable = true;
+        } else {
+            startup.files.push(PathBuf::from(arg));
+        }
+    }
+    startup
+}
+
+/// Runs command-line mode and returns the process exit code
+pub fn run() -> i32 {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let log_file = match &args.log_file {
+        Some(path) => match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                eprintln!("Error: Failed to open log file {}: {}", path.display(), e);
+                return 1;
+            }
+        },
+        None => None,
+    };
+    let level = if args.quiet {
+        Level::ERROR
+    } else if args.verbose {
+        Level::DEBUG
+    } else {
+        Level::INFO
+    };
+    logging::init_cli(level, log_file);
+    let report_path = args.report.clone();
+    let mut summary = None;
+    let outcome = match args.serve.clone() {
+        Some(addr) => server::serve(&addr, args.max_body_mb.saturating_mul(1024 * 1024)),
+        None => convert_args(args, &matches, &mut summary),
+    };
+    let exit_code = match (&outcome, &summary) {
+        (Err(e), _) => {
+            error!("{:#}", e);
+            1
+        }
+        (Ok(()), Some(summary)) if summary.skipped_records > 0 || (summary.rejected && summary.invalid_rows > 0) => {
+            EXIT_PARTIAL
+        }
+        (Ok(()), _) => 0,
+    };
+
+    let Some(path) = report_path else {
+        return exit_code;
+    };
+    let report = RunReport::new(exit_code, summary.as_ref(), outcome.as_ref().err());
+    let written = serde_json::to_string_pretty(&report)
+        .context("Failed to write report")
+        .and_then(|json| write_atomically(&path, json.as_bytes()));
+    match written {
+        Ok(()) => exit_code,
+        Err(e) => {
+            error!("{:#}", e);
+            1
+        }
+    }
+}
+
+/// Settings set by each option, copied from the options over those of a preset when the
+/// option is given on the command line
+///
+/// Options for lists, such as `--hash` or `--map`, replace the whole list of the preset.
+/// The thread limits and credentials are kept from the command line anyway, see
+/// `Preset::settings_over`.
+const PRESET_OVERRIDES: &[(&str, fn(&mut Settings, &Settings))] = &[
+    ("output_format", |to, from| to.output_kind = from.output_kind),
+    ("avro_schema", |to, from| to.avro_fields = from.avro_fields.clone()),
+    ("widths", |to, from| to.fixed_widths = from.fixed_widths.clone()),
+    ("right_align", |to, from| to.fixed_widths = from.fixed_widths.clone()),
+    ("sheet_by", |to, from| to.sheet_column = from.sheet_column.clone()),
+    ("locale", |to, from| {
+        to.delimiter = from.delimiter.clone();
+        to.decimal_comma = from.decimal_comma;
+        to.date_format = from.date_format;
+        to.encoding = from.encoding;
+        to.line_endings = from.line_endings;
+    }),
+    ("delimiter", |to, from| to.delimiter = from.delimiter.clone()),
+    ("decimal_comma", |to, from| to.decimal_comma = from.decimal_comma),
+    ("date_format", |to, from| to.date_format = from.date_format),
+    ("encoding", |to, from| to.encoding = from.encoding),
+    ("crlf", |to, from| to.line_endings = from.line_endings),
+    ("no_headers", |to, from| to.include_headers = from.include_headers),
+    ("no_quote", |to, from| to.quote_fields = from.quote_fields),
+    ("max_cell_length", |to, from| to.max_cell_length = from.max_cell_length),
+    ("newlines", |to, from| to.newline_handling = from.newline_handling),
+    ("newline_token", |to, from| to.newline_token = from.newline_token.clone()),
+    ("normalize_unicode", |to, from| to.text_cleanup.normalize = from.text_cleanup.normalize),
+    ("control_chars", |to, from| {
+        to.text_cleanup.control_characters = from.text_cleanup.control_characters
+    }),
+    ("strict", |to, from| to.json5_fallback = from.json5_fallback),
+    ("input_delimiter", |to, from| to.csv_input.delimiter = from.csv_input.delimiter),
+    ("input_quote", |to, from| to.csv_input.quote = from.csv_input.quote),
+    ("input_header", |to, from| to.csv_input.has_header = from.csv_input.has_header),
+    ("ragged_rows", |to, from| to.ragged_rows = from.ragged_rows),
+    ("records", |to, from| to.records_path = from.records_path.clone()),
+    ("key_column", |to, from| to.key_column = from.key_column.clone()),
+    ("geometry", |to, from| to.geometry_columns = from.geometry_columns),
+    ("duplicate_keys", |to, from| to.duplicate_keys = from.duplicate_keys),
+    ("checkpoint", |to, from| to.checkpoints = from.checkpoints),
+    ("two_pass", |to, from| to.two_pass = from.two_pass),
+    ("append", |to, from| to.append_mode = from.append_mode),
+    ("template", |to, from| {
+        to.output_kind = from.output_kind;
+        to.template_row = from.template_row.clone();
+    }),
+    ("header_template", |to, from| to.template_header = from.template_header.clone()),
+    ("footer_template", |to, from| to.template_footer = from.template_footer.clone()),
+    ("script", |to, from| to.transform_script = from.transform_script.clone()),
+    ("defaults", |to, from| to.column_defaults = from.column_defaults.clone()),
+    ("exclude", |to, from| to.exclude_columns = from.exclude_columns.clone()),
+    ("drop_empty_columns", |to, from| to.drop_empty_columns = from.drop_empty_columns),
+    ("strip_units", |to, from| to.number_columns = from.number_columns.clone()),
+    ("keep_json", |to, from| to.json_columns = from.json_columns.clone()),
+    ("pretty_json", |to, from| to.pretty_json = from.pretty_json),
+    ("mappings", |to, from| to.value_mappings = from.value_mappings.clone()),
+    ("hash", |to, from| to.anonymized_columns = from.anonymized_columns.clone()),
+    ("mask", |to, from| to.anonymized_columns = from.anonymized_columns.clone()),
+    ("drop", |to, from| to.anonymized_columns = from.anonymized_columns.clone()),
+    ("hash_salt", |to, from| to.anonymization_salt = from.anonymization_salt.clone()),
+    ("mask_text", |to, from| to.anonymization_mask = from.anonymization_mask.clone()),
+    ("metadata", |to, from| to.metadata_columns = from.metadata_columns.clone()),
+    ("rules", |to, from| to.validation_rules = from.validation_rules.clone()),
+    ("json_schema", |to, from| to.json_schema = from.json_schema.clone()),
+    ("reject", |to, from| to.invalid_rows = from.invalid_rows),
+    ("data_dictionary", |to, from| to.data_dictionary = from.data_dictionary),
+    ("s3_region", |to, from| to.s3_region = from.s3_region.clone()),
+    ("s3_endpoint", |to, from| to.s3_endpoint = from.s3_endpoint.clone()),
+];
+
+/// Converts the input named by the arguments and writes the CSV output
+///
+/// `summary` is filled in once the conversion finished, even when writing its output fails
+/// afterwards.
+fn convert_args(args: Args, matches: &ArgMatches, summary: &mut Option<ConversionSummary>) -> Result<()> {
+    let mut settings = Settings {
+        include_headers: !args.no_headers,
+        quote_fields: !args.no_quote,
+        max_cell_length: args.max_cell_length,
+        newline_handling: args.newlines.into(),
+        newline_token: args.newline_token,
+        text_cleanup: TextCleanup {
+            normalize: args.normalize_unicode,
+            control_characters: args.control_chars.map_or(ControlCharacters::Keep, Into::into),
+        },
+        json5_fallback: !args.strict,
+        duplicate_keys: args.duplicate_keys.map_or(DuplicateKeys::Off, Into::into),
+        records_path: args.records.unwrap_or_default(),
+        key_column: args.key_column,
+        geometry_columns: args.geometry.into(),
+        csv_input: CsvDialect {
+            delimiter: args.input_delimiter,
+            quote: args.input_quote,
+            has_header: args.input_header.map(|header| header == HeaderArg::Yes),
+        },
+        ragged_rows: args.ragged_rows.into(),
+        two_pass: args.two_pass,
+        checkpoints: args.checkpoint,
+        worker_threads: args.threads,
+        low_priority: args.low_priority,
+        append_mode: args.append,
+        transform_script: read_optional(&args.script, "script")?,
+        column_defaults: args.defaults,
+        exclude_columns: args.exclude,
+        drop_empty_columns: args.drop_empty_columns,
+        number_columns: args.strip_units,
+        json_columns: args.keep_json,
+        pretty_json: args.pretty_json,
+        value_mappings: args.mappings.into_iter().fold(Vec::new(), |mut mappings, (column, value)| {
+            match mappings.iter_mut().find(|mapping: &&mut ValueMapping| mapping.column == column) {
+                Some(mapping) => mapping.values.push(value),
+                None => mappings.push(ValueMapping {
+                    column,
+                    values: vec![value],
+                }),
+            }
+            mappings
+        }),
+        anonymized_columns: [
+            (args.hash, Anonymization::Hash),
+            (args.mask, Anonymization::Mask),
+            (args.drop, Anonymization::Drop),
+        ]
+        .into_iter()
+        .flat_map(|(columns, method)| {
+            columns.into_iter().map(move |column| ColumnAnonymization { column, method })
+        })
+        .collect(),
+        anonymization_mask: args.mask_text,
+        anonymization_salt: args.hash_salt,
+        metadata_columns: MetadataColumns {
+            row_number: args.metadata.contains(&MetadataArg::RowNumber),
+            source_file: args.metadata.contains(&MetadataArg::SourceFile),
+            json_pointer: args.metadata.contains(&MetadataArg::JsonPointer),
+        },
+        validation_rules: match &args.rules {
+            Some(_) => serde_json::from_str(&read_optional(&args.rules, "rules")?)
+                .context("Invalid validation rules")?,
+            None => Vec::new(),
+        },
+        json_schema: match &args.json_schema {
+            Some(path) => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                Some(JsonSchema::parse(&name, &read_optional(&args.json_schema, "JSON Schema")?)?)
+            }
+            None => None,
+        },
+        invalid_rows: if args.reject {
+            InvalidRows::Reject
+        } else {
+            InvalidRows::Report
+        },
+        data_dictionary: args.data_dictionary.map_or(DictionaryFormat::Off, Into::into),
+        output_kind: if args.template.is_some() {
+            OutputKind::Template
+        } else {
+            args.output_format.into()
+        },
+        template_header: read_optional(&args.header_template, "header template")?,
+        template_row: read_optional(&args.template, "template")?,
+        template_footer: read_optional(&args.footer_template, "footer template")?,
+        avro_fields: match &args.avro_schema {
+            Some(_) => parse_avro_fields(&read_optional(&args.avro_schema, "Avro schema")?)?,
+            None => Vec::new(),
+        },
+        fixed_widths: fixed_widths(&args.widths, &args.right_align),
+        sheet_column: args.sheet_by.unwrap_or_default(),
+        s3_region: args.s3_region,
+        s3_endpoint: args.s3_endpoint,
+        google_client_id: args.google_client_id,
+        google_client_secret: args.google_client_secret,
+        ..Default::default()
+    };
+    if let Some(locale) = args.locale {
+        TargetLocale::from(locale).apply(&mut settings);
+    }
+    if let Some(delimiter) = args.delimiter {
+        settings.delimiter = delimiter;
+    }
+    settings.decimal_comma |= args.decimal_comma;
+    if let Some(format) = args.date_format {
+        settings.date_format = format.into();
+    }
+    if let Some(encoding) = args.encoding {
+        settings.encoding = encoding.into();
+    }
+    if args.crlf {
+        settings.line_endings = LineEndings::CrLf;
+    }
+    let (settings, columns) = match &args.preset {
+        Some(path) => {
+            let preset = preset::load(path)?;
+            let columns = if args.columns.is_empty() {
+                preset.columns.clone()
+            } else {
+                args.columns.clone()
+            };
+            let mut over = preset.settings_over(&settings);
+            for (option, apply) in PRESET_OVERRIDES {
+                if matches.value_source(option) == Some(ValueSource::CommandLine) {
+                    apply(&mut over, &settings);
+                }
+            }
+            (over, columns)
+        }
+        None => (settings, args.columns.clone()),
+    };
+
+    // Files are streamed where possible; stdin and explicit formats are read as text
+    let input = match (&args.input, args.format) {
+        (Some(path), _) if is_s3_uri(&path.to_string_lossy()) => {
+            ConversionInput::S3(path.to_string_lossy().into_owned())
+        }
+        (Some(path), None) => ConversionInput::File(path.clone()),
+        (Some(path), Some(format)) => ConversionInput::Text(
+            fs::read_to_string(path).context("Failed to read input file")?,
+            format.into(),
+            path.display().to_string(),
+        ),
+        (None, format) => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .context("Failed to read standard input")?;
+            ConversionInput::Text(text, format.map_or(InputFormat::Json, Into::into), String::new())
+        }
+    };
+
+    if args.analyze {
+        print_analysis(&analyze(&input, &settings, columns)?);
+        return Ok(());
+    }
+    if let Some(runs) = args.benchmark {
+        let report = benchmark::run(&input, &settings, &columns, runs as usize)?;
+        print!("{}", report.render());
+        return Ok(());
+    }
+
+    let progress = Mutex::new(ConversionProgress::default());
+    let Some(result) = convert(&input, &settings, columns, &progress, &AtomicBool::new(false))?
+    else {
+        bail!("Conversion cancelled");
+    };
+    *summary = Some(ConversionSummary::new(&result));
+    if let Some(rows) = result.resumed_rows {
+        info!("Resumed from a checkpoint after {} rows", rows);
+    }
+    if result.cleaned_cells > 0 {
+        info!("Cleaned {} cells", result.cleaned_cells);
+    }
+    if let Some(summary) = result.duplicate_keys.summary() {
+        warn!("{}", summary);
+    }
+    if let Some(summary) = result.ragged.summary() {
+        warn!("{}", summary);
+    }
+    if let Some(summary) = result.validation.summary() {
+        warn!("{}", summary);
+    }
+    for loss in &result.losses.columns {
+        let rows: Vec<String> = loss.rows.iter().map(usize::to_string).collect();
+        let more = if loss.cells > loss.rows.len() { ", ..." } else { "" };
+        warn!(
+            "column {}: {} cells with {} (rows {}{})",
+            loss.column,
+            loss.cells,
+            loss.loss.describe(),
+            rows.join(", "),
+            more
+        );
+    }
+    if let Some(rejected) = &result.validation.rejected_csv {
+        // Next to a local output file, in the current directory otherwise
+        let output = match &args.output {
+            Some(path) if !is_s3_uri(&path.to_string_lossy()) => path.clone(),
+            _ => PathBuf::from(validation::REJECTED_FILE_NAME),
+        };
+        let path = validation::save_rejected(&output, rejected)?;
+        info!("Rejected rows written to {}", path.display());
+    }
+    if let Some(dictionary) = &result.dictionary {
+        // Next to a local output file, in the current directory otherwise
+        let output = match &args.output {
+            Some(path) if !is_s3_uri(&path.to_string_lossy()) => path.clone(),
+            _ => PathBuf::new(),
+        };
+        let path = dictionary::save(&output, dictionary)?;
+        info!("Data dictionary written to {}", path.display());
+    }
+
+    if args.db_url.is_some() || args.google_sheet.is_some() {
+        let rows = Table::from_csv(
+            &result.csv_content,
+            &result.columns,
+            result.body_offset,
+            &settings,
+        )?;
+        if let Some(url) = &args.db_url {
+            let table = args.db_table.as_deref().unwrap_or_default();
+            println!("{}", database::export(url, table, &rows, args.db_dry_run)?);
+        } else if let Some(title) = &args.google_sheet {
+            let token = sheets::authorize(&settings)?;
+            println!("{}", sheets::export(&token, title, &rows, &settings)?);
+        }
+        return Ok(());
+    }
+
+    match &args.output {
+        Some(path) if is_s3_uri(&path.to_string_lossy()) => cloud::upload(
+            &path.to_string_lossy(),
+            &result.csv_content,
+            settings.output_kind.content_type(),
+            &settings,
+        ),
+        Some(path) if settings.appends_to_existing() && path.exists() => append_to_csv_file(
+            path,
+            &result.csv_content,
+            &result.columns,
+            result.body_offset,
+            &settings,
+        ),
+        Some(path) => write_atomically(path, &result.csv_content),
+        None => io::stdout()
+            .lock()
+            .write_all(&result.csv_content)
+            .context("Failed to write to standard output"),
+    }
+}
+
+/// Prints the outcome of `--analyze`
+fn print_analysis(analysis: &Analysis) {
+    let about = if analysis.estimated { "about " } else { "" };
+    println!("Records:     {}{}", about, analysis.records);
+    println!("Rows:        {}{}", about, analysis.rows);
+    println!("Columns:     {}", analysis.columns.len());
+    println!("Output size: {}{}", about, format_bytes(analysis.output_bytes));
+    let types = analysis.types.iter().zip(&analysis.widths);
+    for (column, (column_type, width)) in analysis.columns.iter().zip(types) {
+        println!("  {}: {}, up to {} chars", column, column_type.label(), width);
+    }
+}
+
+/// Parses a `--default COLUMN=VALUE` argument
+fn parse_default(arg: &str) -> Result<ColumnDefault, String> {
+    match arg.split_once('=') {
+        Some((column, value)) if !column.is_empty() => Ok(ColumnDefault {
+            column: column.to_string(),
+            value: value.to_string(),
+        }),
+        _ => Err("expected COLUMN=VALUE".to_string()),
+    }
+}
+
+/// Parses a `--width COLUMN=WIDTH` argument
+fn parse_width(arg: &str) -> Result<(String, usize), String> {
+    match arg.split_once('=') {
+        Some((column, width)) if !column.is_empty() => match width.parse() {
+            Ok(width) if width > 0 => Ok((column.to_string(), width)),
+            _ => Err(format!("invalid width {:?}; expected a number above 0", width)),
+        },
+        _ => Err("expected COLUMN=WIDTH".to_string()),
+    }
+}
+
+/// Fixed widths of the `--width` and `--right-align` arguments
+fn fixed_widths(widths: &[(String, usize)], right_align: &[String]) -> Vec<ColumnWidth> {
+    let mut fixed: Vec<ColumnWidth> = widths
+        .iter()
+        .map(|(column, width)| ColumnWidth {
+            column: column.clone(),
+            width: Some(*width),
+            align_right: right_align.contains(column),
+        })
+        .collect();
+    for column in right_align {
+        if !fixed.iter().any(|width| &width.column == column) {
+            fixed.push(ColumnWidth {
+                column: column.clone(),
+                width: None,
+                align_right: true,
+            });
+        }
+    }
+    fixed
+}
+
+/// Parses a `--map COLUMN:FROM=TO` argument
+fn parse_mapping(arg: &str) -> Result<(String, MappedValue), String> {
+    let parsed = arg
+        .split_once(':')
+        .and_then(|(column, value)| Some((column, value.split_once('=')?)));
+    match parsed {
+        Some((column, (from, to))) if !column.is_empty() => Ok((
+            column.to_string(),
+            MappedValue {
+                from: from.to_string(),
+                to: to.to_string(),
+            },
+        )),
+        _ => Err("expected COLUMN:FROM=TO".to_string()),
+    }
+}
+
+/// Reads the file given for an optional argument; empty when it was not given
+fn read_optional(path: &Option<PathBuf>, what: &str) -> Result<String> {
+    match path {
+        Some(path) => fs::read_to_string(path).with_context(|| format!("Failed to read {} file", what)),
+        None => Ok(String::new()),
+    }
+}
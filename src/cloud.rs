@@ -0,0 +1,113 @@
+//! S3 input and output
+//!
+//! Inputs and outputs can be given as `s3://bucket/key` URIs. Credentials come from the
+//! settings when an access key is set there, and otherwise from the usual AWS sources:
+//! the `AWS_ACCESS_KEY_ID`/`AWS_SECRET_ACCESS_KEY` environment variables, the shared
+//! credentials file or the instance metadata. The keys in the settings may be references
+//! to environment variables or keychain secrets, see `secrets`.
+
+use crate::secrets;
+use crate::settings::Settings;
+use anyhow::{anyhow, bail, Context, Result};
+use s3::creds::Credentials;
+use s3::{Bucket, Region};
+
+/// Location of an object in S3
+pub struct S3Location {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl S3Location {
+    /// Parses an `s3://bucket/key` URI; returns `None` for anything else
+    pub fn parse(uri: &str) -> Option<Self> {
+        let (bucket, key) = uri.strip_prefix("s3://")?.split_once('/')?;
+        if bucket.is_empty() || key.is_empty() {
+            return None;
+        }
+        Some(Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+}
+
+/// Whether a path or URI names an S3 object
+pub fn is_s3_uri(uri: &str) -> bool {
+    uri.starts_with("s3://")
+}
+
+/// Downloads an object as text
+pub fn download(uri: &str, settings: &Settings) -> Result<String> {
+    let (bucket, location) = open_bucket(uri, settings)?;
+    let response = bucket
+        .get_object(&location.key)
+        .with_context(|| format!("Failed to download {}", uri))?;
+    if response.status_code() != 200 {
+        bail!(
+            "Failed to download {}: HTTP {} {}",
+            uri,
+            response.status_code(),
+            response.as_str().unwrap_or_default()
+        );
+    }
+    String::from_utf8(response.bytes().to_vec())
+        .with_context(|| format!("{} is not valid UTF-8 text", uri))
+}
+
+/// Uploads converted output to an object, replacing it if it exists
+pub fn upload(uri: &str, content: &[u8], content_type: &str, settings: &Settings) -> Result<()> {
+    let (bucket, location) = open_bucket(uri, settings)?;
+    let response = bucket
+        .put_object_with_content_type(&location.key, content, content_type)
+        .with_context(|| format!("Failed to upload to {}", uri))?;
+    if response.status_code() != 200 {
+        bail!(
+            "Failed to upload to {}: HTTP {} {}",
+            uri,
+            response.status_code(),
+            response.as_str().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Connects to the bucket of a URI with the region, endpoint and credentials of the settings
+fn open_bucket(uri: &str, settings: &Settings) -> Result<(Box<Bucket>, S3Location)> {
+    let location = S3Location::parse(uri)
+        .ok_or_else(|| anyhow!("Invalid S3 URI {:?}; expected s3://bucket/key", uri))?;
+
+    let access_key = secrets::resolve(&settings.s3_access_key).context("Failed to read the S3 access key")?;
+    let region = if settings.s3_endpoint.is_empty() {
+        settings
+            .s3_region
+            .parse()
+            .with_context(|| format!("Invalid S3 region {:?}", settings.s3_region))?
+    } else {
+        Region::Custom {
+            region: settings.s3_region.clone(),
+            endpoint: settings.s3_endpoint.clone(),
+        }
+    };
+    let credentials = if access_key.is_empty() {
+        Credentials::default()
+    } else {
+        let secret_key = secrets::resolve(&settings.s3_secret_key).context("Failed to read the S3 secret key")?;
+        Credentials::new(
+            Some(&access_key),
+            Some(&secret_key),
+            None,
+            None,
+            None,
+        )
+    }
+    .context("No S3 credentials found")?;
+
+    let mut bucket = Bucket::new(&location.bucket, region, credentials)
+        .context("Failed to set up S3 bucket")?;
+    // S3-compatible services behind a custom endpoint usually expect path-style URLs
+    if !settings.s3_endpoint.is_empty() {
+        bucket = bucket.with_path_style();
+    }
+    Ok((bucket, location))
+}
@@ -0,0 +1,126 @@
+//! Saved configuration
+//!
+//! The preferences of the window, the recent files and the settings of new tabs are kept in
+//! `config.json`. It lives in the OS config directory, e.g. `~/.config/json_to_csv_converter`
+//! on Linux, or beside the executable in portable mode, so the app can run from a USB stick
+//! without leaving anything on the machine. Portable mode is chosen with `--portable` or by
+//! placing a `portable.flag` file next to the executable.
+
+use crate::jobs::JobsDock;
+use crate::settings::Settings;
+use crate::theme::Theme;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Name of the file whose presence next to the executable turns on portable mode
+const PORTABLE_FLAG_FILE: &str = "portable.flag";
+
+/// Name of the configuration directory, inside the OS config directory or beside the
+/// executable
+const CONFIG_DIR_NAME: &str = "json_to_csv_converter";
+
+const CONFIG_FILE_NAME: &str = "config.json";
+
+/// Everything remembered between sessions
+///
+/// Fields missing from a saved configuration keep their defaults.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub dark_mode: bool,
+    pub theme: Theme,
+    pub notifications: bool,
+    pub jobs_dock: JobsDock,
+    pub check_for_updates: bool,
+    /// Whether events are also written to the log file beside the configuration
+    pub log_to_file: bool,
+    pub panel_sizes: PanelSizes,
+    /// Most recently opened first
+    pub recent_files: Vec<PathBuf>,
+    /// Settings of new tabs, taken from the active tab at exit
+    pub settings: Settings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dark_mode: false,
+            theme: Theme::default(),
+            notifications: true,
+            jobs_dock: JobsDock::Right,
+            check_for_updates: false,
+            log_to_file: false,
+            panel_sizes: PanelSizes::default(),
+            recent_files: Vec::new(),
+            settings: Settings::default(),
+        }
+    }
+}
+
+/// Sizes of the resizable panels in points, as last dragged by the user
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PanelSizes {
+    pub settings_width: f32,
+    pub preview_height: f32,
+    pub jobs_width: f32,
+    pub jobs_height: f32,
+}
+
+impl Default for PanelSizes {
+    fn default() -> Self {
+        Self {
+            settings_width: 320.0,
+            preview_height: 250.0,
+            jobs_width: 250.0,
+            jobs_height: 150.0,
+        }
+    }
+}
+
+/// Directory holding the configuration, or `None` when there is nowhere to keep it
+pub fn config_dir(portable: bool) -> Option<PathBuf> {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    match exe_dir {
+        Some(exe_dir) if portable || exe_dir.join(PORTABLE_FLAG_FILE).exists() => {
+            Some(exe_dir.join(CONFIG_DIR_NAME))
+        }
+        _ => dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME)),
+    }
+}
+
+/// Loads the configuration of a directory
+///
+/// A missing file gives the defaults; an unreadable one is reported and replaced by the
+/// defaults, so a damaged file never keeps the app from starting.
+pub fn load(dir: &Path) -> Config {
+    let path = dir.join(CONFIG_FILE_NAME);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => return Config::default(),
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        warn!("Ignoring invalid configuration {}: {}", path.display(), e);
+        Config::default()
+    })
+}
+
+/// Saves the configuration to a directory, creating it if needed
+///
+/// The file is written under a temporary name first, so an interrupted save leaves the
+/// previous configuration intact.
+pub fn save(dir: &Path, config: &Config) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create config directory {}", dir.display()))?;
+    let path = dir.join(CONFIG_FILE_NAME);
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, serde_json::to_string_pretty(config)?)
+        .with_context(|| format!("Failed to write {}", temporary.display()))?;
+    fs::rename(&temporary, &path)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
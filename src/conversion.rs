@@ -0,0 +1,2206 @@
+//! Conversion of JSON content to CSV
+//!
+//! Conversions run on a background thread and report their progress and final output
+//! through a shared `ConversionProgress`, which the UI polls every frame.
+
+use crate::checkpoint::{self, Checkpoint, CheckpointWriter};
+use crate::dictionary::{Dictionary, DictionaryColumn};
+use crate::cloud;
+use crate::duplicates::{DuplicateReport, RawValue, Resolver};
+use crate::formats::{output_format, CellError, OutputFormat};
+use crate::geojson;
+use crate::input::{self, document_records, InputFormat, RecordPointers};
+use crate::locale;
+use crate::lossy::{self, LossReport, LossTracker};
+use crate::schema::ColumnType;
+use crate::script::RecordScript;
+use crate::settings::{
+    Anonymization, ControlCharacters, DictionaryFormat, DuplicateKeys, NewlineHandling, OutputKind, RaggedRows,
+    Settings,
+};
+use crate::transforms::{anonymize, clean_text, strip_number};
+use crate::validation::{ColumnRule, ValidationReport, Validator};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer, MapAccess, SeqAccess, Visitor};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use regex::Regex;
+use serde_json::Value;
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use thread_priority::{set_current_thread_priority, ThreadPriority};
+use tracing::{debug, info};
+
+/// Tracks the progress and status of the conversion process
+#[derive(Default)]
+pub struct ConversionProgress {
+    /// Current status message
+    pub status: String,
+    /// Progress value between 0.0 and 1.0
+    pub progress: f32,
+    /// Whether a conversion is currently in progress
+    pub is_converting: bool,
+    /// Whether the last conversion stopped with an error
+    pub failed: bool,
+    /// Output of the last finished conversion, waiting to be picked up by the UI
+    pub result: Option<ConversionResult>,
+    /// When the worker started converting
+    pub started_at: Option<Instant>,
+    /// Number of records converted so far
+    pub rows_processed: usize,
+    /// Total number of records, once known
+    pub total_rows: Option<usize>,
+    /// Bytes of CSV output produced so far
+    pub bytes_processed: usize,
+    /// Bytes of JSON input consumed so far, tracked when streaming from a file
+    pub bytes_read: usize,
+    /// Size of the JSON input in bytes
+    pub input_bytes: usize,
+}
+
+impl ConversionProgress {
+    /// Time since the worker started
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.map(|started| started.elapsed()).unwrap_or_default()
+    }
+
+    /// Average number of records converted per second
+    pub fn rows_per_second(&self) -> f64 {
+        let seconds = self.elapsed().as_secs_f64();
+        if seconds > 0.0 {
+            self.rows_processed as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Estimated time until all records are converted, based on the average rate so far
+    ///
+    /// Uses the record count when it is known, otherwise the share of input bytes read.
+    pub fn eta(&self) -> Option<Duration> {
+        let done = match self.total_rows {
+            Some(total) if total > 0 => self.rows_processed as f64 / total as f64,
+            _ if self.bytes_read > 0 && self.input_bytes > 0 => {
+                self.bytes_read as f64 / self.input_bytes as f64
+            }
+            _ => return None,
+        };
+        if done <= 0.0 {
+            return None;
+        }
+        let elapsed = self.elapsed().as_secs_f64();
+        Some(Duration::from_secs_f64(elapsed * (1.0 - done).max(0.0) / done))
+    }
+
+    /// One-line summary of rows, throughput, output size, elapsed time and ETA
+    pub fn throughput_summary(&self) -> String {
+        let rows = match self.total_rows {
+            Some(total) => format!("{} / {} rows", self.rows_processed, total),
+            None => format!("{} rows", self.rows_processed),
+        };
+        let mut summary = format!(
+            "{} · {:.0} rows/s · {} written from {} input · elapsed {}",
+            rows,
+            self.rows_per_second(),
+            format_bytes(self.bytes_processed),
+            format_bytes(self.input_bytes),
+            format_duration(self.elapsed()),
+        );
+        if let Some(eta) = self.eta() {
+            summary.push_str(&format!(" · ETA {}", format_duration(eta)));
+        }
+        summary
+    }
+}
+
+/// Formats a byte count with a binary unit, e.g. `3.2 MB`
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Formats a duration as `mm:ss`, or `h:mm:ss` from one hour on
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Time spent in the stages of a conversion
+#[derive(Default, Clone, Copy)]
+pub struct StageTimings {
+    /// Reading, downloading and parsing the input: everything but the other two stages
+    pub parse: Duration,
+    /// Running the transform script and rendering the cells
+    pub transform: Duration,
+    /// Validating the rows and writing them in the output format
+    pub write: Duration,
+}
+
+/// Output produced by a finished conversion
+pub struct ConversionResult {
+    /// Generated output; text, except for binary formats such as Arrow
+    pub csv_content: Vec<u8>,
+    /// Preview data for the grid view
+    pub preview_data: Vec<Vec<String>>,
+    /// Values of the input records behind the data rows of the preview, taken before the
+    /// transform script ran; `None` where a record lacks the column
+    pub raw_preview: Vec<Vec<Option<Value>>>,
+    /// Number of cells shortened because they exceeded the maximum cell length
+    pub truncated_cells: usize,
+    /// Number of cells changed by the text cleanup
+    pub cleaned_cells: usize,
+    /// Columns written to the CSV, in output order
+    pub columns: Vec<String>,
+    /// Byte offset in `csv_content` where the data rows start, after the header
+    pub body_offset: usize,
+    /// Objects with repeated keys, when checking for them is enabled
+    pub duplicate_keys: DuplicateReport,
+    /// Array records with more or fewer fields than there are columns
+    pub ragged: RaggedReport,
+    /// Rows that broke a validation rule
+    pub validation: ValidationReport,
+    /// Cells that lost information on their way to the output
+    pub losses: LossReport,
+    /// Descriptions of the output columns, when a data dictionary was requested
+    pub dictionary: Option<Dictionary>,
+    /// Number of data rows written
+    pub rows: usize,
+    /// Number of input records read
+    pub records: usize,
+    /// Number of records left out for having another shape than the first, e.g. scalars
+    /// among objects, or with `RaggedRows::Skip` another number of fields
+    pub skipped_records: usize,
+    /// Time spent in each stage
+    pub timings: StageTimings,
+    /// Number of rows taken over from a checkpoint, when the conversion resumed from one
+    pub resumed_rows: Option<usize>,
+}
+
+/// Renders a JSON value as a CSV cell
+///
+/// Strings are written without their JSON quotes, and embedded line breaks are replaced
+/// according to the newline setting. The text cleanup runs next, incrementing `cleaned`
+/// when it changes the cell. When a maximum cell length is set, longer values are cut to
+/// that many characters with the last one replaced by `…`, and `truncated` is incremented.
+pub fn format_cell(value: &Value, settings: &Settings, truncated: &mut usize, cleaned: &mut usize) -> String {
+    let cell = match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+
+    let replacement = match settings.newline_handling {
+        NewlineHandling::Keep => None,
+        NewlineHandling::Space => Some(" "),
+        NewlineHandling::Escape => Some("\\n"),
+        NewlineHandling::Custom => Some(settings.newline_token.as_str()),
+    };
+    let cell = match replacement {
+        Some(token) if cell.contains(['\n', '\r']) => cell
+            .replace("\r\n", "\n")
+            .replace('\r', "\n")
+            .replace('\n', token),
+        _ => cell,
+    };
+    let cell = match clean_text(&cell, &settings.text_cleanup) {
+        Some(clean) => {
+            *cleaned += 1;
+            clean
+        }
+        None => cell,
+    };
+
+    match settings.max_cell_length {
+        Some(max) if cell.chars().count() > max => {
+            *truncated += 1;
+            let mut shortened: String = cell.chars().take(max.saturating_sub(1)).collect();
+            shortened.push('…');
+            shortened
+        }
+        _ => cell,
+    }
+}
+
+/// Number of records rendered in parallel before they are written in order
+const BATCH_SIZE: usize = 4096;
+
+/// Minimum time between two progress updates published by a worker
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Publishes worker progress at most once per `PROGRESS_INTERVAL`
+///
+/// The progress mutex is shared with the UI thread, so updating it for every batch would
+/// make conversion speed depend on lock traffic rather than on the input size.
+struct ProgressReporter<'a> {
+    progress: &'a Mutex<ConversionProgress>,
+    last_update: Option<Instant>,
+}
+
+impl<'a> ProgressReporter<'a> {
+    fn new(progress: &'a Mutex<ConversionProgress>) -> Self {
+        Self {
+            progress,
+            last_update: None,
+        }
+    }
+
+    /// Applies `update` if the previous update is long enough ago
+    fn update(&mut self, update: impl FnOnce(&mut ConversionProgress)) {
+        let due = self
+            .last_update
+            .is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL);
+        if due {
+            self.last_update = Some(Instant::now());
+            update(&mut self.progress.lock().unwrap());
+        }
+    }
+}
+
+/// Input of a conversion
+pub enum ConversionInput {
+    /// Text already loaded into memory, in the given format, with the path of the file it
+    /// was read from; empty for text of other origin
+    Text(String, InputFormat, String),
+    /// File read while converting, for inputs too large to load at once
+    ///
+    /// JSON files are streamed record by record; other formats cannot be streamed and are
+    /// read as a whole by the worker.
+    File(PathBuf),
+    /// `s3://bucket/key` object, downloaded by the worker; the format follows the key
+    S3(String),
+}
+
+impl ConversionInput {
+    /// Path or URI the input comes from, written in the `_source_file` column
+    fn source(&self) -> String {
+        match self {
+            ConversionInput::Text(_, _, source) | ConversionInput::S3(source) => source.clone(),
+            ConversionInput::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+/// Incrementally builds the CSV output from records
+struct CsvBuilder<'a> {
+    /// Format the rows are written in
+    output: Box<dyn OutputFormat>,
+    /// Settings of the conversion
+    settings: &'a Settings,
+    /// Columns chosen by the user; empty means all keys of the first record
+    selected_columns: Vec<String>,
+    /// Names of the elements of array records, e.g. the header of a CSV input; empty
+    /// means positional `col_N` names
+    field_names: Vec<String>,
+    /// Output columns, known once the first record was written
+    headers: Option<Vec<String>>,
+    /// How the cells of every output column are produced
+    plans: Vec<ColumnPlan<'a>>,
+    /// Patterns of columns left out of those taken from the records
+    exclude: Vec<Regex>,
+    /// Output column and kind of every metadata column
+    metadata: Vec<(usize, Metadata)>,
+    /// Path or URI of the input, for the `_source_file` column
+    source: String,
+    /// Where the records are in the input, for the `_json_pointer` column and messages
+    pointers: RecordPointers,
+    /// Rows held back until the end when empty columns are dropped
+    held_rows: Vec<Vec<String>>,
+    /// Whether a column had a non-empty cell in any held row
+    filled: Vec<bool>,
+    /// Transform script run on every record before it is written
+    script: Option<RecordScript>,
+    /// Number of input records handed to the builder
+    input_records: usize,
+    /// Shape of the records, fixed by the first one
+    shape: RecordShape,
+    /// Number of fields every array record should have: as many as there are field names,
+    /// or `col_N` columns taken from the records; `None` when not known
+    record_width: Option<usize>,
+    /// Array records with another number of fields
+    ragged: RaggedReport,
+    /// Preview data for the grid view
+    preview_data: Vec<Vec<String>>,
+    /// Input values of the preview rows
+    raw_preview: Vec<Vec<Option<Value>>>,
+    /// Number of truncated cells
+    truncated_cells: usize,
+    /// Number of cells changed by the text cleanup
+    cleaned_cells: usize,
+    /// Byte offset where the data rows start
+    body_offset: usize,
+    /// Number of data rows written
+    rows: usize,
+    /// Objects with repeated keys found while reading the input
+    duplicate_keys: DuplicateReport,
+    /// Checks rows against the validation rules, once the columns are known
+    validator: Option<Validator>,
+    /// Lossy cells of the rows written; rows taken over from a checkpoint are not included
+    losses: LossTracker,
+    /// Descriptions of the output columns, when a data dictionary is requested; rows taken
+    /// over from a checkpoint are not counted
+    dictionary: Option<Dictionary>,
+    /// Number of rows rendered, including rejected ones
+    rendered_rows: usize,
+    /// Number of records of another shape than the first
+    skipped_records: usize,
+    /// Time spent transforming and writing so far
+    timings: StageTimings,
+    /// Columns of the records seen by the first pass of a two-pass conversion
+    scan: ColumnScan,
+    /// Output of the checkpoint the conversion resumed from, followed by `output`
+    resumed: Option<Vec<u8>>,
+    /// Number of rows of that output
+    resumed_rows: usize,
+}
+
+impl<'a> CsvBuilder<'a> {
+    /// Creates a builder writing into memory
+    ///
+    /// Fails if the output format or the transform script cannot be set up.
+    fn new(
+        settings: &'a Settings,
+        selected_columns: Vec<String>,
+        field_names: Vec<String>,
+    ) -> Result<Self> {
+        let script = if settings.transform_script.trim().is_empty() {
+            None
+        } else {
+            Some(RecordScript::compile(&settings.transform_script)?)
+        };
+
+        let mut builder = Self {
+            output: output_format(settings)?,
+            settings,
+            selected_columns,
+            field_names,
+            headers: None,
+            plans: Vec::new(),
+            exclude: settings
+                .exclude_columns
+                .iter()
+                .filter(|pattern| !pattern.is_empty())
+                .map(|pattern| glob_regex(pattern))
+                .collect(),
+            metadata: Vec::new(),
+            source: String::new(),
+            pointers: RecordPointers::default(),
+            held_rows: Vec::new(),
+            filled: Vec::new(),
+            script,
+            input_records: 0,
+            shape: RecordShape::Object,
+            record_width: None,
+            ragged: RaggedReport {
+                skipped: settings.ragged_rows == RaggedRows::Skip,
+                ..Default::default()
+            },
+            preview_data: Vec::new(),
+            raw_preview: Vec::new(),
+            truncated_cells: 0,
+            cleaned_cells: 0,
+            body_offset: 0,
+            rows: 0,
+            duplicate_keys: DuplicateReport::default(),
+            validator: None,
+            losses: LossTracker::default(),
+            dictionary: None,
+            rendered_rows: 0,
+            skipped_records: 0,
+            timings: StageTimings::default(),
+            scan: ColumnScan::default(),
+            resumed: None,
+            resumed_rows: 0,
+        };
+        // The columns of a JSON Schema stand in for a selection
+        if let Some(schema) = settings.json_schema.as_ref().filter(|_| builder.selected_columns.is_empty()) {
+            builder.selected_columns = builder.without_excluded(schema.column_names());
+        }
+        Ok(builder)
+    }
+
+    /// Writes a batch of records as CSV rows, rendering the cells on all cores
+    ///
+    /// The first record fixes the shape and the columns: the user selection if there is
+    /// one, otherwise the keys of an object, the field names or `col_0`, `col_1`, … for
+    /// arrays (as many as the longest array of the first batch) or a single `value` column
+    /// for scalars, preceded by the enabled metadata columns that are not selected
+    /// elsewhere. Rows are written in input order; records of a different shape are
+    /// skipped. The transform script, if any, runs first and may change or drop records.
+    fn write_batch(&mut self, records: &[Value]) -> Result<()> {
+        let transform_started = Instant::now();
+        let first_record = self.input_records;
+        self.input_records += records.len();
+        let input = records;
+        let (records, origins) = self.transform(first_record, records)?;
+        let records = &records[..];
+
+        if self.headers.is_none() {
+            let Some(first) = records.first() else {
+                return Ok(());
+            };
+            let shape = RecordShape::of(first);
+            let mut headers: Vec<String> = if !self.selected_columns.is_empty() {
+                self.selected_columns.clone()
+            } else {
+                let columns = match first {
+                    Value::Object(obj) => obj.keys().cloned().collect(),
+                    Value::Array(_) if !self.field_names.is_empty() => self.field_names.clone(),
+                    Value::Array(_) => {
+                        let width = records
+                            .iter()
+                            .filter_map(Value::as_array)
+                            .map(Vec::len)
+                            .max()
+                            .unwrap_or_default();
+                        self.record_width = Some(width);
+                        (0..width).map(|i| format!("col_{}", i)).collect()
+                    }
+                    _ => vec!["value".to_string()],
+                };
+                self.without_excluded(columns)
+            };
+            if shape == RecordShape::Array && !self.field_names.is_empty() {
+                self.record_width = Some(self.field_names.len());
+            }
+            let settings = self.settings;
+            let anonymizations: Vec<(Regex, Anonymization)> = settings
+                .anonymized_columns
+                .iter()
+                .filter(|rule| !rule.column.is_empty())
+                .map(|rule| (glob_regex(&rule.column), rule.method))
+                .collect();
+            let anonymization = |column: &str| {
+                anonymizations
+                    .iter()
+                    .find(|(pattern, _)| pattern.is_match(column))
+                    .map(|(_, method)| *method)
+            };
+            headers.retain(|column| anonymization(column) != Some(Anonymization::Drop));
+            let enabled: Vec<Metadata> = Metadata::ALL
+                .into_iter()
+                .filter(|metadata| metadata.enabled(self.settings))
+                .collect();
+            let missing: Vec<String> = enabled
+                .iter()
+                .map(|metadata| metadata.column().to_string())
+                .filter(|column| !headers.contains(column))
+                .collect();
+            headers.splice(0..0, missing);
+            self.metadata = enabled
+                .into_iter()
+                .filter_map(|metadata| {
+                    let column = headers.iter().position(|c| c == metadata.column())?;
+                    Some((column, metadata))
+                })
+                .collect();
+            self.shape = shape;
+            let number_patterns: Vec<Regex> = settings
+                .number_columns
+                .iter()
+                .filter(|pattern| !pattern.is_empty())
+                .map(|pattern| glob_regex(pattern))
+                .collect();
+            let json_patterns: Vec<Regex> = settings
+                .json_columns
+                .iter()
+                .filter(|pattern| !pattern.is_empty())
+                .map(|pattern| glob_regex(pattern))
+                .collect();
+            self.plans = headers
+                .iter()
+                .map(|column| ColumnPlan {
+                    position: if self.field_names.is_empty() {
+                        column.strip_prefix("col_").and_then(|index| index.parse().ok())
+                    } else {
+                        self.field_names.iter().position(|name| name == column)
+                    },
+                    default: settings
+                        .column_defaults
+                        .iter()
+                        .find(|default| default.column == *column)
+                        .map(|default| default.value.as_str()),
+                    numeric: number_patterns.iter().any(|pattern| pattern.is_match(column)),
+                    json: json_patterns.iter().any(|pattern| pattern.is_match(column)),
+                    mapping: settings
+                        .value_mappings
+                        .iter()
+                        .filter(|mapping| mapping.column == *column)
+                        .flat_map(|mapping| &mapping.values)
+                        .map(|value| (value.from.as_str(), value.to.as_str()))
+                        .collect(),
+                    anonymization: anonymization(column),
+                })
+                .collect();
+
+            let schema_rules = settings.json_schema.iter().flat_map(|schema| &schema.rules);
+            let rules: Vec<ColumnRule> =
+                settings.validation_rules.iter().chain(schema_rules).cloned().collect();
+            self.validator = Validator::new(
+                &rules,
+                &headers,
+                self.settings.invalid_rows,
+            )?;
+            if settings.data_dictionary != DictionaryFormat::Off {
+                let columns = headers
+                    .iter()
+                    .zip(&self.plans)
+                    .enumerate()
+                    .map(|(index, (column, plan))| {
+                        let metadata = self
+                            .metadata
+                            .iter()
+                            .find(|(position, _)| *position == index)
+                            .map(|&(_, metadata)| metadata);
+                        dictionary_column(column, plan, shape, metadata, settings)
+                    })
+                    .collect();
+                self.dictionary = Some(Dictionary {
+                    format: settings.data_dictionary,
+                    columns,
+                });
+            }
+
+            // The format writes the headers if it has them; a resumed output has them, and
+            // they wait for the end when empty columns are dropped
+            self.filled = vec![false; headers.len()];
+            self.losses = LossTracker::new(headers.len());
+            if self.resumed.is_none() && !self.settings.drop_empty_columns {
+                self.output.write_header(&headers)?;
+                self.body_offset = self.output.len();
+            }
+            if self.settings.include_headers {
+                self.preview_data.push(headers.clone());
+            }
+            self.headers = Some(headers);
+        }
+        let Some(headers) = &self.headers else {
+            return Ok(());
+        };
+
+        let (settings, shape, record_width) = (self.settings, self.shape, self.record_width);
+        let (plans, pointers) = (&self.plans, &self.pointers);
+        let rows: Vec<RenderedRow> = records
+            .par_iter()
+            .enumerate()
+            .filter(|(_, record)| RecordShape::of(record) == shape)
+            .map(|(index, record)| {
+                let (values, (truncated, cleaned)) = render_row(record, headers, plans, settings);
+                let ragged = match (record, record_width) {
+                    (Value::Array(items), Some(width)) if items.len() != width => Some(items.len()),
+                    _ => None,
+                };
+                let kinds = lossy::classify(
+                    headers
+                        .iter()
+                        .zip(plans)
+                        .map(|(key, plan)| record_field(record, key, plan.position)),
+                );
+                RenderedRow {
+                    index,
+                    values,
+                    truncated,
+                    cleaned,
+                    kinds,
+                    ragged,
+                }
+            })
+            .collect();
+        self.skipped_records += records.len() - rows.len();
+        let write_started = Instant::now();
+        self.timings.transform += write_started - transform_started;
+
+        for RenderedRow {
+            index,
+            mut values,
+            truncated,
+            cleaned,
+            kinds,
+            ragged,
+        } in rows
+        {
+            let origin = origins.get(index).copied().unwrap_or(index);
+            if let Some(fields) = ragged {
+                let record = first_record + origin + 1;
+                let width = record_width.unwrap_or_default();
+                match settings.ragged_rows {
+                    RaggedRows::Fix => self.ragged.add(record, fields, width),
+                    RaggedRows::Skip => {
+                        self.ragged.add(record, fields, width);
+                        self.skipped_records += 1;
+                        continue;
+                    }
+                    RaggedRows::Fail => {
+                        bail!("Record {} has {} fields, but there are {} columns", record, fields, width)
+                    }
+                }
+            }
+            for &(column, metadata) in &self.metadata {
+                values[column] = match metadata {
+                    Metadata::RowNumber => (self.rows + 1).to_string(),
+                    Metadata::SourceFile => self.source.clone(),
+                    Metadata::JsonPointer => pointers.pointer(first_record + origin),
+                };
+            }
+            self.rendered_rows += 1;
+            let (record, number) = (&records[index], first_record + origin);
+            let origin_of = |column: usize| cell_origin(record, number, pointers, &headers[column], &plans[column]);
+            if let Some(validator) = &mut self.validator {
+                if !validator.check(self.rendered_rows, number + 1, &values, origin_of)? {
+                    continue;
+                }
+            }
+            self.truncated_cells += truncated;
+            self.cleaned_cells += cleaned;
+            self.losses.add(self.rows + 1, &kinds);
+            if let Some(dictionary) = &mut self.dictionary {
+                dictionary.add(&values);
+            }
+            if self.rows - self.resumed_rows < self.settings.max_preview_rows {
+                self.preview_data.push(values.clone());
+                let record = &input[origin];
+                self.raw_preview.push(raw_cells(record, headers, &self.plans));
+            }
+            // After the checks and the preview, which read the numbers with a point
+            if settings.decimal_comma && settings.output_kind == OutputKind::Csv {
+                locale::decimal_comma(&mut values, &kinds);
+            }
+            if self.settings.drop_empty_columns {
+                for (filled, value) in self.filled.iter_mut().zip(&values) {
+                    *filled |= !value.is_empty() && value != "null";
+                }
+                self.held_rows.push(values);
+            } else {
+                self.output
+                    .write_row(&values)
+                    .map_err(|e| match e.downcast_ref::<CellError>() {
+                        Some(cell) => {
+                            let origin = origin_of(cell.column);
+                            e.context(format!("Record {}, {}", number + 1, origin))
+                        }
+                        None => e,
+                    })
+                    .with_context(|| format!("Failed to write row {}", self.rows + 1))?;
+            }
+            self.rows += 1;
+        }
+        self.timings.write += write_started.elapsed();
+        Ok(())
+    }
+
+    /// Runs the transform script, if any, on records numbered from `first_record` on
+    ///
+    /// Also returns the index of the input record every transformed record came from; it
+    /// is empty when there is no script and the records are unchanged.
+    fn transform<'r>(
+        &self,
+        first_record: usize,
+        records: &'r [Value],
+    ) -> Result<(Cow<'r, [Value]>, Vec<usize>)> {
+        let Some(script) = &self.script else {
+            return Ok((Cow::Borrowed(records), Vec::new()));
+        };
+        let pointers = &self.pointers;
+        let (origins, transformed) = records
+            .par_iter()
+            .enumerate()
+            .map(|(index, record)| {
+                script.apply(record).with_context(|| {
+                    let number = first_record + index;
+                    let pointer = pointers.pointer(number);
+                    format!("Script error in record {} (at {}, raw value {})", number + 1, pointer, excerpt(record))
+                })
+                    .map(|outputs| (index, outputs))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flat_map(|(index, outputs)| outputs.into_iter().map(move |output| (index, output)))
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+        Ok((Cow::Owned(transformed), origins))
+    }
+
+    /// Collects the columns of a batch in the first pass of a two-pass conversion, after
+    /// running the transform script on it
+    fn scan_batch(&mut self, records: &[Value]) -> Result<()> {
+        let first_record = self.scan.records;
+        self.scan.records += records.len();
+        let (records, _) = self.transform(first_record, records)?;
+        self.scan.add(&records);
+        Ok(())
+    }
+
+    /// Ends the first pass; the columns of all records scanned are written unless the
+    /// user selected columns
+    fn use_scanned_columns(&mut self) {
+        if !self.selected_columns.is_empty() {
+            return;
+        }
+        let scan = std::mem::take(&mut self.scan);
+        let columns = match scan.shape {
+            Some(RecordShape::Object) => scan.keys,
+            Some(RecordShape::Array) if self.field_names.is_empty() => {
+                self.record_width = Some(scan.width);
+                (0..scan.width).map(|i| format!("col_{}", i)).collect()
+            }
+            _ => return,
+        };
+        self.selected_columns = self.without_excluded(columns);
+    }
+
+    /// Leaves out the columns matching an exclude pattern
+    fn without_excluded(&self, mut columns: Vec<String>) -> Vec<String> {
+        columns.retain(|column| !self.exclude.iter().any(|pattern| pattern.is_match(column)));
+        columns
+    }
+
+    /// Writes the rows held back to drop empty columns, without those columns
+    ///
+    /// Columns without a single non-empty cell are removed from the output and from the
+    /// preview; without any rows, all columns are kept.
+    fn write_held_rows(&mut self) -> Result<()> {
+        let Some(headers) = &mut self.headers else {
+            return Ok(());
+        };
+        let keep: Vec<bool> = if self.held_rows.is_empty() {
+            vec![true; headers.len()]
+        } else {
+            self.filled.clone()
+        };
+        fn retain<T>(row: &mut Vec<T>, keep: &[bool]) {
+            let mut index = 0;
+            row.retain(|_| {
+                index += 1;
+                keep.get(index - 1).copied().unwrap_or(true)
+            });
+        }
+
+        retain(headers, &keep);
+        self.output.write_header(headers)?;
+        self.body_offset = self.output.len();
+        for (number, mut values) in std::mem::take(&mut self.held_rows).into_iter().enumerate() {
+            retain(&mut values, &keep);
+            self.output
+                .write_row(&values)
+                .with_context(|| format!("Failed to write row {}", number + 1))?;
+        }
+        self.preview_data.iter_mut().for_each(|row| retain(row, &keep));
+        self.raw_preview.iter_mut().for_each(|row| retain(row, &keep));
+        Ok(())
+    }
+
+    /// Continues the output of a checkpoint; the preview starts at the resumed rows
+    fn resume(&mut self, checkpoint: Checkpoint, output: Vec<u8>) {
+        self.selected_columns = checkpoint.columns;
+        self.input_records = checkpoint.records;
+        self.rendered_rows = checkpoint.rendered_rows;
+        self.rows = checkpoint.rows;
+        self.resumed_rows = checkpoint.rows;
+        self.truncated_cells = checkpoint.truncated_cells;
+        self.cleaned_cells = checkpoint.cleaned_cells;
+        self.body_offset = checkpoint.body_offset;
+        self.resumed = Some(output);
+    }
+
+    /// Saves a checkpoint after the rows written so far, if the output format allows it
+    fn save_checkpoint(&mut self, writer: &mut CheckpointWriter, input_offset: usize) -> Result<()> {
+        let Some(headers) = &self.headers else {
+            return Ok(());
+        };
+        let checkpoint = Checkpoint {
+            input_len: 0,
+            input_modified: None,
+            fingerprint: 0,
+            input_offset,
+            records: self.input_records,
+            rendered_rows: self.rendered_rows,
+            rows: self.rows,
+            truncated_cells: self.truncated_cells,
+            cleaned_cells: self.cleaned_cells,
+            columns: headers.clone(),
+            body_offset: self.body_offset,
+            output_len: 0,
+        };
+        let resumed_len = self.resumed.as_ref().map_or(0, Vec::len);
+        match self.output.written() {
+            Some(output) => writer.save(checkpoint, resumed_len, output),
+            None => Ok(()),
+        }
+    }
+
+    /// Number of output bytes produced so far
+    fn output_len(&self) -> usize {
+        self.resumed.as_ref().map_or(0, Vec::len) + self.output.len()
+    }
+
+    /// Finishes the CSV output
+    fn finish(mut self) -> Result<ConversionResult> {
+        let write_started = Instant::now();
+        // Named by the columns before any are dropped, which are empty and lost nothing
+        let losses = std::mem::take(&mut self.losses).finish(self.headers.as_deref().unwrap_or_default());
+        if self.settings.drop_empty_columns {
+            self.write_held_rows()?;
+        }
+        if let (Some(dictionary), Some(headers)) = (&mut self.dictionary, &self.headers) {
+            dictionary.retain(headers);
+        }
+        let csv_content = match self.resumed {
+            Some(mut resumed) => {
+                resumed.extend(self.output.finish()?);
+                resumed
+            }
+            None => self.output.finish()?,
+        };
+        self.timings.write += write_started.elapsed();
+        Ok(ConversionResult {
+            csv_content,
+            preview_data: self.preview_data,
+            raw_preview: self.raw_preview,
+            truncated_cells: self.truncated_cells,
+            cleaned_cells: self.cleaned_cells,
+            columns: self.headers.unwrap_or_default(),
+            body_offset: self.body_offset,
+            duplicate_keys: self.duplicate_keys,
+            ragged: self.ragged,
+            validation: match self.validator {
+                Some(validator) => validator.finish()?,
+                None => ValidationReport::default(),
+            },
+            losses,
+            dictionary: self.dictionary,
+            rows: self.rows,
+            records: self.input_records,
+            skipped_records: self.skipped_records,
+            timings: self.timings,
+            resumed_rows: (self.resumed_rows > 0).then_some(self.resumed_rows),
+        })
+    }
+}
+
+/// Columns of all records, collected by the first pass of a two-pass conversion
+///
+/// Like a single pass, only records of the shape of the first one count: objects add
+/// their new keys in order of appearance, arrays widen the `col_N` columns.
+#[derive(Default)]
+struct ColumnScan {
+    shape: Option<RecordShape>,
+    keys: Vec<String>,
+    seen: HashSet<String>,
+    width: usize,
+    /// Number of input records scanned, to number script errors
+    records: usize,
+}
+
+impl ColumnScan {
+    fn add(&mut self, records: &[Value]) {
+        for record in records {
+            let shape = *self.shape.get_or_insert_with(|| RecordShape::of(record));
+            match record {
+                Value::Object(obj) if shape == RecordShape::Object => {
+                    for key in obj.keys() {
+                        if self.seen.insert(key.clone()) {
+                            self.keys.push(key.clone());
+                        }
+                    }
+                }
+                Value::Array(items) if shape == RecordShape::Array => {
+                    self.width = self.width.max(items.len());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Number of ragged records listed in the summary
+const MAX_RAGGED_EXAMPLES: usize = 5;
+
+/// Array records with more or fewer fields than there are columns, such as ragged lines of
+/// CSV input, which strict CSV parsers downstream would refuse
+#[derive(Default)]
+pub struct RaggedReport {
+    pub records: usize,
+    /// Number of fields the records should have
+    pub width: usize,
+    /// Whether they were left out rather than padded or cut
+    pub skipped: bool,
+    /// Number and field count of the first ragged records
+    pub examples: Vec<(usize, usize)>,
+}
+
+impl RaggedReport {
+    fn add(&mut self, record: usize, fields: usize, width: usize) {
+        self.records += 1;
+        self.width = width;
+        if self.examples.len() < MAX_RAGGED_EXAMPLES {
+            self.examples.push((record, fields));
+        }
+    }
+
+    /// Warning listing the ragged records, if there are any
+    pub fn summary(&self) -> Option<String> {
+        if self.records == 0 {
+            return None;
+        }
+        let examples: Vec<String> = self
+            .examples
+            .iter()
+            .map(|(record, fields)| format!("record {}: {} fields", record, fields))
+            .collect();
+        let more = if self.records > self.examples.len() { "; ..." } else { "" };
+        let outcome = if self.skipped { "skipped" } else { "padded or cut" };
+        Some(format!(
+            "{} records with another number of fields than {}, {} ({}{})",
+            self.records,
+            self.width,
+            outcome,
+            examples.join("; "),
+            more
+        ))
+    }
+}
+
+/// A record rendered on a worker thread, waiting to be written in order
+struct RenderedRow {
+    /// Index of the record within its batch
+    index: usize,
+    values: Vec<String>,
+    /// Number of cells truncated and changed by the text cleanup
+    truncated: usize,
+    cleaned: usize,
+    /// Kind flags of the cells, see `lossy::classify`
+    kinds: Vec<u8>,
+    /// Number of fields of an array record with more or fewer than `CsvBuilder::record_width`
+    ragged: Option<usize>,
+}
+
+/// Longest excerpt of a raw value in messages, in characters
+const MAX_EXCERPT_CHARS: usize = 60;
+
+/// A value as JSON for messages, cut short when it is long
+fn excerpt(value: &Value) -> String {
+    let text = value.to_string();
+    match text.char_indices().nth(MAX_EXCERPT_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+/// Where the cell of a column came from, for messages about it: the JSON Pointer of its
+/// value in the input and that value as read, e.g. `at /41/price, raw value "12 EUR"`
+///
+/// `number` is the position of the record among the input records, counted from 0, and
+/// `pointers` lead to it in the input.
+fn cell_origin(
+    record: &Value,
+    number: usize,
+    pointers: &RecordPointers,
+    column: &str,
+    plan: &ColumnPlan,
+) -> String {
+    let pointer = match (record, plan.position) {
+        (Value::Object(_), _) => pointers.field(number, column),
+        (Value::Array(_), Some(position)) => format!("{}/{}", pointers.pointer(number), position),
+        _ => pointers.pointer(number),
+    };
+    let raw = record_field(record, column, plan.position).map_or_else(|| "missing".to_string(), excerpt);
+    format!("at {}, raw value {}", pointer, raw)
+}
+
+/// How the cells of one output column are produced
+struct ColumnPlan<'a> {
+    /// Index of the column within array records
+    position: Option<usize>,
+    /// Value for records lacking the column, if one is set
+    default: Option<&'a str>,
+    /// Whether text is stripped down to the number it holds
+    numeric: bool,
+    /// Whether the value is written as its JSON text
+    json: bool,
+    /// Replacements of cells, empty for none
+    mapping: HashMap<&'a str, &'a str>,
+    /// How the cells are hidden, if they are
+    anonymization: Option<Anonymization>,
+}
+
+/// Dictionary entry of an output column, before any row is counted
+///
+/// The source is the JSON Pointer of the cell within a record, or a description of what
+/// fills a metadata column; the transforms are listed in the order they run on a cell.
+fn dictionary_column(
+    column: &str,
+    plan: &ColumnPlan,
+    shape: RecordShape,
+    metadata: Option<Metadata>,
+    settings: &Settings,
+) -> DictionaryColumn {
+    let declared_type = settings.json_schema.as_ref().and_then(|schema| schema.column_type(column));
+    let source = match (metadata, shape) {
+        (Some(Metadata::RowNumber), _) => "(row number)".to_string(),
+        (Some(Metadata::SourceFile), _) => "(input file)".to_string(),
+        (Some(Metadata::JsonPointer), _) => "(position of the record)".to_string(),
+        (None, RecordShape::Object) => format!("/{}", column.replace('~', "~0").replace('/', "~1")),
+        (None, RecordShape::Array) => plan.position.map_or_else(String::new, |index| format!("/{}", index)),
+        (None, RecordShape::Scalar) => "(whole record)".to_string(),
+    };
+    if metadata.is_some() {
+        return DictionaryColumn::new(column, source, declared_type, Vec::new());
+    }
+
+    let mut transforms = Vec::new();
+    if !settings.transform_script.trim().is_empty() {
+        transforms.push("record script".to_string());
+    }
+    if let Some(default) = plan.default {
+        transforms.push(format!("default {:?} when missing", default));
+    }
+    if plan.json {
+        let layout = if settings.pretty_json { "indented" } else { "compact" };
+        transforms.push(format!("kept as {} JSON", layout));
+    } else {
+        if plan.numeric {
+            transforms.push("units stripped".to_string());
+        }
+        if settings.newline_handling != NewlineHandling::Keep {
+            transforms.push("line breaks replaced".to_string());
+        }
+        if settings.text_cleanup.normalize {
+            transforms.push("Unicode normalized (NFC)".to_string());
+        }
+        match settings.text_cleanup.control_characters {
+            ControlCharacters::Keep => {}
+            ControlCharacters::Strip => transforms.push("control characters removed".to_string()),
+            ControlCharacters::Escape => transforms.push("control characters escaped".to_string()),
+        }
+        if let Some(length) = settings.max_cell_length {
+            transforms.push(format!("truncated to {} characters", length));
+        }
+        if !plan.mapping.is_empty() {
+            transforms.push(format!("{} values mapped", plan.mapping.len()));
+        }
+    }
+    match plan.anonymization {
+        Some(Anonymization::Hash) => transforms.push("hashed (SHA-256)".to_string()),
+        Some(Anonymization::Mask) => transforms.push("masked".to_string()),
+        Some(Anonymization::Drop) | None => {}
+    }
+    DictionaryColumn::new(column, source, declared_type, transforms)
+}
+
+/// Synthetic column filled in by the builder rather than taken from the records
+#[derive(Clone, Copy)]
+enum Metadata {
+    RowNumber,
+    SourceFile,
+    JsonPointer,
+}
+
+impl Metadata {
+    /// Every metadata column, in output order
+    const ALL: [Metadata; 3] = [Metadata::RowNumber, Metadata::SourceFile, Metadata::JsonPointer];
+
+    fn column(self) -> &'static str {
+        match self {
+            Metadata::RowNumber => "_row_number",
+            Metadata::SourceFile => "_source_file",
+            Metadata::JsonPointer => "_json_pointer",
+        }
+    }
+
+    fn enabled(self, settings: &Settings) -> bool {
+        let columns = &settings.metadata_columns;
+        match self {
+            Metadata::RowNumber => columns.row_number,
+            Metadata::SourceFile => columns.source_file,
+            Metadata::JsonPointer => columns.json_pointer,
+        }
+    }
+}
+
+/// Kind of top-level records a conversion handles
+#[derive(Clone, Copy, PartialEq)]
+enum RecordShape {
+    /// Objects, one column per key
+    Object,
+    /// Arrays, one column per element, named by position (`col_N`) or by field names
+    Array,
+    /// Strings, numbers, booleans and nulls in a single `value` column
+    Scalar,
+}
+
+impl RecordShape {
+    fn of(record: &Value) -> Self {
+        match record {
+            Value::Object(_) => RecordShape::Object,
+            Value::Array(_) => RecordShape::Array,
+            _ => RecordShape::Scalar,
+        }
+    }
+}
+
+/// Looks up the value of a column in a record of any shape
+///
+/// `position` is the index of the column within array records.
+fn record_field<'v>(record: &'v Value, column: &str, position: Option<usize>) -> Option<&'v Value> {
+    match record {
+        Value::Object(obj) => obj.get(column),
+        Value::Array(items) => position.and_then(|index| items.get(index)),
+        scalar => (column == "value").then_some(scalar),
+    }
+}
+
+/// Renders the cells of one record in column order
+///
+/// Columns the record lacks get their default value, or an empty cell without one. Text
+/// in numeric columns is stripped down to its number where it holds one, cells found in
+/// the value mapping of their column are replaced, and the cells of anonymized columns are
+/// hidden last. Returns the cells with the number of them that were truncated and the
+/// number changed by the text cleanup.
+fn render_row(
+    record: &Value,
+    headers: &[String],
+    plans: &[ColumnPlan],
+    settings: &Settings,
+) -> (Vec<String>, (usize, usize)) {
+    let (mut truncated, mut cleaned) = (0, 0);
+    let values = headers.iter()
+        .zip(plans)
+        .map(|(key, plan)| {
+            let cell = match record_field(record, key, plan.position) {
+                Some(v) if plan.json => json_text(v, settings),
+                Some(v) => match v.as_str().filter(|_| plan.numeric).and_then(strip_number) {
+                    Some(number) => number,
+                    None => format_cell(v, settings, &mut truncated, &mut cleaned),
+                },
+                None => plan.default.unwrap_or_default().to_string(),
+            };
+            let cell = match plan.mapping.get(cell.as_str()).filter(|_| !plan.json) {
+                Some(mapped) => mapped.to_string(),
+                None => cell,
+            };
+            match plan.anonymization {
+                Some(method) => anonymize(&cell, method, settings),
+                None => cell,
+            }
+        })
+        .collect();
+    (values, (truncated, cleaned))
+}
+
+/// JSON text of a value kept as it is, compact or indented as set
+fn json_text(value: &Value, settings: &Settings) -> String {
+    let text = if settings.pretty_json {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    };
+    // Values parsed from JSON always serialize
+    text.unwrap_or_default()
+}
+
+/// Regular expression matching the column names a pattern with `*` and `?` wildcards
+/// stands for
+fn glob_regex(pattern: &str) -> Regex {
+    let pattern = regex::escape(pattern).replace(r"\*", ".*").replace(r"\?", ".");
+    // Escaped text with wildcards is always a valid expression
+    Regex::new(&format!("^{}$", pattern)).unwrap()
+}
+
+/// Values of a record for the output columns, before any rendering
+fn raw_cells(record: &Value, headers: &[String], plans: &[ColumnPlan]) -> Vec<Option<Value>> {
+    headers
+        .iter()
+        .zip(plans)
+        .map(|(key, plan)| record_field(record, key, plan.position).cloned())
+        .collect()
+}
+
+/// Serde visitor that hands every record of a JSON document to a callback as soon as it
+/// is parsed
+///
+/// A top-level array yields its elements one by one, so the document never has to be in
+/// memory as a whole; a top-level object is a single record. Records are read as `T`,
+/// which is `Value` or, to keep repeated keys, `RawValue`.
+struct RecordVisitor<T, F>(F, PhantomData<T>);
+
+impl<'de, T, F> Visitor<'de> for RecordVisitor<T, F>
+where
+    T: Deserialize<'de>,
+    F: FnMut(T) -> Result<(), String>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON array or object")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(value) = seq.next_element::<T>()? {
+            (self.0)(value).map_err(de::Error::custom)?;
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, map: A) -> Result<(), A::Error> {
+        let value = T::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        (self.0)(value).map_err(de::Error::custom)
+    }
+}
+
+/// Streams the records of a JSON reader to a callback
+///
+/// The first document is streamed record by record; documents concatenated to it are
+/// read one at a time and split into records by `records`. A callback error stops the
+/// stream; it fails the result only within the first document, so the caller has to keep
+/// track of why it stopped. `in_first_document` is cleared once the first document ends.
+fn stream_records<T, F>(
+    reader: impl Read,
+    mut on_record: F,
+    records: fn(T) -> Vec<T>,
+    in_first_document: &Cell<bool>,
+) -> Result<()>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<(), String>,
+{
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer
+        .deserialize_any(RecordVisitor(&mut on_record, PhantomData))
+        .context("JSON parsing error")?;
+    in_first_document.set(false);
+    for document in deserializer.into_iter::<T>() {
+        let document = document.context("JSON parsing error")?;
+        if records(document).into_iter().try_for_each(&mut on_record).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Reader wrapper counting the bytes read so far
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<usize>>,
+}
+
+impl<'a> CountingReader<&'a [u8]> {
+    /// Reads file contents from `offset` on, counting the bytes before it as read
+    fn new(contents: &'a [u8], offset: usize) -> Self {
+        CountingReader {
+            inner: contents.get(offset..).unwrap_or_default(),
+            count: Rc::new(Cell::new(offset)),
+        }
+    }
+}
+
+/// Offset of the next record after `offset`, the end of a record of the top-level array:
+/// past the blanks and the comma separating the two
+fn next_record(contents: &[u8], offset: usize) -> usize {
+    let rest = contents.get(offset..).unwrap_or_default();
+    let blank = rest.iter().take_while(|b| b.is_ascii_whitespace()).count();
+    match rest.get(blank) {
+        Some(b',') => offset + blank + 1,
+        _ => offset + blank,
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + read);
+        Ok(read)
+    }
+}
+
+/// Converts text held in memory
+///
+/// Returns `None` when the conversion was cancelled.
+fn convert_text(
+    content: &str,
+    format: InputFormat,
+    source: String,
+    settings: &Settings,
+    selected_columns: Vec<String>,
+    progress: &Mutex<ConversionProgress>,
+    cancel: &AtomicBool,
+) -> Result<Option<ConversionResult>> {
+    let mut progress_guard = progress.lock().unwrap();
+    progress_guard.progress = 0.2;
+    progress_guard.status = format!("Parsing {}...", format.name());
+    drop(progress_guard);
+
+    let parsed = input::parse(content, format, settings)?;
+
+    let mut progress_guard = progress.lock().unwrap();
+    progress_guard.progress = 0.4;
+    progress_guard.status = "Converting to CSV...".to_string();
+    drop(progress_guard);
+
+    let mut builder = CsvBuilder::new(settings, selected_columns, parsed.field_names)?;
+    builder.duplicate_keys = parsed.duplicate_keys;
+    builder.source = source;
+    builder.pointers = parsed.pointers;
+
+    match parsed.value {
+        Value::Array(arr) => {
+            progress.lock().unwrap().total_rows = Some(arr.len());
+
+            if settings.two_pass {
+                progress.lock().unwrap().status = "Scanning columns...".to_string();
+                for chunk in arr.chunks(BATCH_SIZE) {
+                    if cancel.load(Ordering::Relaxed) {
+                        return Ok(None);
+                    }
+                    builder.scan_batch(chunk)?;
+                }
+                builder.use_scanned_columns();
+                progress.lock().unwrap().status = "Converting to CSV...".to_string();
+            }
+
+            // Write data rows, one parallel batch at a time
+            let mut reporter = ProgressReporter::new(progress);
+            let mut done = 0;
+            for chunk in arr.chunks(BATCH_SIZE) {
+                if cancel.load(Ordering::Relaxed) {
+                    return Ok(None);
+                }
+
+                builder.write_batch(chunk)?;
+                done += chunk.len();
+
+                // Update progress
+                let output_len = builder.output_len();
+                reporter.update(|progress| {
+                    progress.progress = 0.4 + (done as f32 / arr.len() as f32) * 0.5;
+                    progress.rows_processed = done;
+                    progress.bytes_processed = output_len;
+                });
+            }
+        }
+        // Handle single object case
+        obj @ Value::Object(_) => builder.write_batch(&[obj])?,
+        _ => bail!("Unsupported JSON structure"),
+    }
+
+    let mut progress_guard = progress.lock().unwrap();
+    progress_guard.progress = 0.9;
+    progress_guard.status = "Finalizing...".to_string();
+    drop(progress_guard);
+
+    builder.finish().map(Some)
+}
+
+/// Streams the records of JSON input, resolving repeated keys as the settings say
+///
+/// Concatenated documents add their records to the same stream. Returns the outcome of
+/// `stream_records` with the objects that repeated a key.
+fn stream_json<F>(
+    reader: impl Read,
+    settings: &Settings,
+    mut on_record: F,
+    in_first_document: &Cell<bool>,
+) -> (Result<()>, DuplicateReport)
+where
+    F: FnMut(Value) -> Result<(), String>,
+{
+    match settings.duplicate_keys {
+        DuplicateKeys::Off => {
+            let parsed = stream_records(reader, &mut on_record, document_records, in_first_document);
+            (parsed, DuplicateReport::default())
+        }
+        strategy => {
+            let mut resolver = Resolver::new(strategy);
+            let parsed = stream_records(
+                reader,
+                |record| on_record(resolver.record(record)),
+                RawValue::into_records,
+                in_first_document,
+            );
+            (parsed, resolver.report)
+        }
+    }
+}
+
+/// First pass of a two-pass conversion of a JSON file: reads the whole file and collects
+/// the columns of all records
+///
+/// Progress runs up to one half. Returns `false` when the conversion was cancelled.
+fn scan_file(
+    path: &Path,
+    builder: &mut CsvBuilder,
+    progress: &Mutex<ConversionProgress>,
+    cancel: &AtomicBool,
+) -> Result<bool> {
+    let contents = input::read_file(path)?;
+    let input_bytes = contents.len();
+    let reader = CountingReader::new(&contents, 0);
+    let bytes_read = Rc::clone(&reader.count);
+    progress.lock().unwrap().status = "Scanning columns...".to_string();
+
+    let mut reporter = ProgressReporter::new(progress);
+    let mut cancelled = false;
+    let mut scan_error = None;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let settings = builder.settings;
+    let on_record = |record| {
+        batch.push(record);
+        if batch.len() < BATCH_SIZE {
+            return Ok(());
+        }
+        if cancel.load(Ordering::Relaxed) {
+            cancelled = true;
+            return Err("conversion cancelled".to_string());
+        }
+        if let Err(e) = builder.scan_batch(&batch) {
+            let message = e.to_string();
+            scan_error = Some(e);
+            return Err(message);
+        }
+        batch.clear();
+
+        let read = bytes_read.get();
+        reporter.update(|progress| {
+            progress.progress = 0.05 + 0.45 * (read as f32 / input_bytes.max(1) as f32);
+            progress.bytes_read = read;
+        });
+        Ok(())
+    };
+    let (parsed, _) = stream_json(reader, settings, on_record, &Cell::new(true));
+
+    if cancelled {
+        return Ok(false);
+    }
+    if let Some(e) = scan_error {
+        return Err(e);
+    }
+    parsed?;
+    builder.scan_batch(&batch)?;
+    builder.use_scanned_columns();
+    Ok(true)
+}
+
+/// Converts a JSON file while reading it, without loading the whole document
+///
+/// Progress is derived from the number of input bytes consumed. With two passes the file
+/// is read twice, first to collect the columns. With checkpoints, a matching checkpoint is
+/// resumed from, and new ones are saved as the conversion goes. Returns `None` when the
+/// conversion was cancelled.
+fn convert_file(
+    path: &Path,
+    settings: &Settings,
+    selected_columns: Vec<String>,
+    progress: &Mutex<ConversionProgress>,
+    cancel: &AtomicBool,
+) -> Result<Option<ConversionResult>> {
+    // Dropping empty columns holds the rows back, so there is no output to checkpoint
+    let checkpoints = settings.checkpoints
+        && settings.output_kind == OutputKind::Csv
+        && !settings.drop_empty_columns;
+    let fingerprint = checkpoint::output_fingerprint(settings, &selected_columns);
+    let mut builder = CsvBuilder::new(settings, selected_columns, Vec::new())?;
+    builder.source = path.display().to_string();
+    let mut resume_offset = None;
+    if let Some((saved, output)) = checkpoints.then(|| checkpoint::load(path, fingerprint)).flatten() {
+        debug!(rows = saved.rows, offset = saved.input_offset, "Resuming from a checkpoint");
+        resume_offset = Some(saved.input_offset);
+        builder.resume(saved, output);
+    }
+    let two_pass = settings.two_pass && builder.selected_columns.is_empty();
+    if two_pass && !scan_file(path, &mut builder, progress, cancel)? {
+        return Ok(None);
+    }
+    // Share of the progress bar taken by the pass writing the rows
+    let (start, span) = if two_pass { (0.5, 0.45) } else { (0.05, 0.9) };
+    let mut checkpoint_writer = if checkpoints {
+        Some(CheckpointWriter::create(path, fingerprint, builder.output_len())?)
+    } else {
+        None
+    };
+    let mut last_checkpoint = Instant::now();
+    let in_first_document = Cell::new(true);
+
+    let contents = input::read_file(path)?;
+    let input_bytes = contents.len();
+    // NDJSON is split at line boundaries and parsed on all cores; checkpoints and the
+    // duplicate key report need the records in one stream
+    if !checkpoints && settings.duplicate_keys == DuplicateKeys::Off && is_ndjson(&contents) {
+        debug!(bytes = input_bytes, threads = rayon::current_num_threads(), "Converting NDJSON partitions");
+        let mut progress_guard = progress.lock().unwrap();
+        progress_guard.input_bytes = input_bytes;
+        progress_guard.status = "Converting NDJSON partitions...".to_string();
+        drop(progress_guard);
+        if !convert_partitions(&contents, &mut builder, progress, cancel, (start, span))? {
+            return Ok(None);
+        }
+        let mut progress_guard = progress.lock().unwrap();
+        progress_guard.progress = 0.95;
+        progress_guard.status = "Finalizing...".to_string();
+        drop(progress_guard);
+        return builder.finish().map(Some);
+    }
+    // The rest of the top-level array reads as an array of its own
+    let (reader, prefix): (_, &[u8]) = match resume_offset {
+        Some(offset) => (CountingReader::new(&contents, next_record(&contents, offset)), b"["),
+        None => (CountingReader::new(&contents, 0), b""),
+    };
+    let bytes_read = Rc::clone(&reader.count);
+    let reader = prefix.chain(reader);
+    let mut progress_guard = progress.lock().unwrap();
+    progress_guard.input_bytes = input_bytes;
+    progress_guard.status = "Streaming JSON to CSV...".to_string();
+    drop(progress_guard);
+    debug!(bytes = input_bytes, checkpoints, "Streaming JSON");
+
+    let mut reporter = ProgressReporter::new(progress);
+    let mut cancelled = false;
+    let mut write_error = None;
+    let mut rows = 0;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+
+    let mut on_record = |record: Value| {
+        batch.push(record);
+        if batch.len() < BATCH_SIZE {
+            return Ok(());
+        }
+
+        if cancel.load(Ordering::Relaxed) {
+            cancelled = true;
+            return Err("conversion cancelled".to_string());
+        }
+
+        // Scalars are only known to have ended once the next byte was read, too late for
+        // the offset to mark their end
+        let record_ended = matches!(batch.last(), Some(Value::Object(_) | Value::Array(_)));
+        if let Err(e) = builder.write_batch(&batch) {
+            let message = e.to_string();
+            write_error = Some(e);
+            return Err(message);
+        }
+        rows += batch.len();
+        batch.clear();
+
+        if let Some(writer) = &mut checkpoint_writer {
+            let due = last_checkpoint.elapsed() >= checkpoint::INTERVAL;
+            if due && record_ended && in_first_document.get() {
+                last_checkpoint = Instant::now();
+                if let Err(e) = builder.save_checkpoint(writer, bytes_read.get()) {
+                    let message = e.to_string();
+                    write_error = Some(e);
+                    return Err(message);
+                }
+            }
+        }
+
+        // Update progress
+        let (read, output_len) = (bytes_read.get(), builder.output_len());
+        reporter.update(|progress| {
+            progress.progress = start + span * (read as f32 / input_bytes.max(1) as f32);
+            progress.rows_processed = rows;
+            progress.bytes_read = read;
+            progress.bytes_processed = output_len;
+        });
+        Ok(())
+    };
+
+    let (parsed, duplicate_keys) = stream_json(reader, settings, &mut on_record, &in_first_document);
+
+    if cancelled {
+        return Ok(None);
+    }
+    if let Some(e) = write_error {
+        return Err(e);
+    }
+    parsed?;
+    builder.write_batch(&batch)?;
+    builder.duplicate_keys = duplicate_keys;
+
+    let mut progress_guard = progress.lock().unwrap();
+    progress_guard.progress = 0.95;
+    progress_guard.status = "Finalizing...".to_string();
+    drop(progress_guard);
+
+    let result = builder.finish()?;
+    if checkpoints {
+        checkpoint::discard(path);
+    }
+    Ok(Some(result))
+}
+
+/// Whether JSON contents are a column-oriented table, keyed records or GeoJSON, see
+/// `input::table_columns`, `input::is_keyed` and `geojson::is_geojson`, or the records are
+/// taken from a records path
+///
+/// All are a single document holding all records, so they are parsed whole instead of
+/// streamed, which would read them as one record. Only the first document is parsed to
+/// find out, and only when it is an object.
+fn is_whole_document(contents: &[u8], settings: &Settings) -> bool {
+    if !settings.records_path.is_empty() {
+        return true;
+    }
+    if contents.iter().find(|b| !b.is_ascii_whitespace()) != Some(&b'{') {
+        return false;
+    }
+    let first = serde_json::Deserializer::from_slice(contents).into_iter::<Value>().next();
+    matches!(
+        first,
+        Some(Ok(value)) if input::table_columns(&value).is_some()
+            || input::is_keyed(&value, &settings.key_column)
+            || geojson::is_geojson(&value)
+    )
+}
+
+/// Whether streaming a JSON file failed on input that is not strict JSON, which is then
+/// read whole to be parsed again as JSON5, as `input::parse` does, when that is enabled
+///
+/// Errors of the output or of records that end early are not retried.
+fn is_json5_retry(error: &anyhow::Error, settings: &Settings) -> bool {
+    settings.json5_fallback
+        && error
+            .chain()
+            .any(|cause| cause.downcast_ref::<serde_json::Error>().is_some_and(serde_json::Error::is_syntax))
+}
+
+/// Whether a JSON file is read whole, see `is_whole_document`
+fn is_whole_file(path: &Path, settings: &Settings) -> Result<bool> {
+    Ok(is_whole_document(&input::read_file(path)?, settings))
+}
+
+/// Input bytes parsed at once by a partitioned conversion, split among the threads
+const WINDOW_BYTES: usize = 32 * 1024 * 1024;
+
+/// Whether file contents are NDJSON, one object or array per line: every line that is not
+/// blank holds a whole JSON value of its own
+///
+/// Contents that only start like NDJSON, such as a file whose first record fits on one
+/// line while later ones span several, are streamed instead of partitioned.
+fn is_ndjson(contents: &[u8]) -> bool {
+    contents.iter().any(|byte| !byte.is_ascii_whitespace())
+        && contents.par_split(|&byte| byte == b'\n').all(|line| {
+            match line.iter().find(|byte| !byte.is_ascii_whitespace()) {
+                None => true,
+                Some(b'{' | b'[') => serde_json::from_slice::<de::IgnoredAny>(line).is_ok(),
+                Some(_) => false,
+            }
+        })
+}
+
+/// Splits a range of bytes into up to `parts` ranges of about the same size, each ending
+/// at a line boundary
+fn split_lines(contents: &[u8], range: Range<usize>, parts: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::with_capacity(parts);
+    let mut start = range.start;
+    for remaining in (1..=parts).rev() {
+        if start >= range.end {
+            break;
+        }
+        let target = start + (range.end - start) / remaining;
+        let end = match contents[target..range.end].iter().position(|&byte| byte == b'\n') {
+            Some(newline) if remaining > 1 => target + newline + 1,
+            _ => range.end,
+        };
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges
+}
+
+/// Writes the records of an NDJSON file, parsing windows of it on all cores
+///
+/// Every window is split into one partition per thread at line boundaries; the partitions
+/// are parsed in parallel and their records written in file order, so the output is the
+/// same as a streamed conversion. Progress runs from `start` over `span`. Returns `false`
+/// when the conversion was cancelled.
+fn convert_partitions(
+    contents: &[u8],
+    builder: &mut CsvBuilder,
+    progress: &Mutex<ConversionProgress>,
+    cancel: &AtomicBool,
+    (start, span): (f32, f32),
+) -> Result<bool> {
+    let mut reporter = ProgressReporter::new(progress);
+    let mut offset = 0;
+    let mut rows = 0;
+    while offset < contents.len() {
+        let mut end = (offset + WINDOW_BYTES).min(contents.len());
+        end = match contents[end..].iter().position(|&byte| byte == b'\n') {
+            Some(newline) => end + newline + 1,
+            None => contents.len(),
+        };
+        let partitions = split_lines(contents, offset..end, rayon::current_num_threads());
+        let parsed = partitions
+            .par_iter()
+            .map(|range| {
+                serde_json::Deserializer::from_slice(&contents[range.clone()])
+                    .into_iter::<Value>()
+                    .collect::<Result<Vec<Value>, _>>()
+                    .map_err(|e| {
+                        // Positions are relative to the partition; the message is reported
+                        // with the line in the file
+                        let lines_before = contents[..range.start]
+                            .iter()
+                            .filter(|&&byte| byte == b'\n')
+                            .count();
+                        let message = e.to_string();
+                        let message = message
+                            .rsplit_once(" at line ")
+                            .map_or(message.as_str(), |(text, _)| text);
+                        anyhow!(
+                            "JSON parsing error: {} at line {} column {}",
+                            message,
+                            lines_before + e.line(),
+                            e.column()
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for records in parsed {
+            for chunk in records.chunks(BATCH_SIZE) {
+                if cancel.load(Ordering::Relaxed) {
+                    return Ok(false);
+                }
+                builder.write_batch(chunk)?;
+                rows += chunk.len();
+            }
+        }
+        offset = end;
+
+        let output_len = builder.output_len();
+        reporter.update(|progress| {
+            progress.progress = start + span * (offset as f32 / contents.len() as f32);
+            progress.rows_processed = rows;
+            progress.bytes_read = offset;
+            progress.bytes_processed = output_len;
+        });
+    }
+    Ok(true)
+}
+
+/// Number of records converted by an analysis; larger inputs are extrapolated from them
+const ANALYSIS_SAMPLE: usize = 10_000;
+
+/// What converting an input would produce, found without writing anything
+pub struct Analysis {
+    /// Number of input records
+    pub records: usize,
+    /// Number of data rows of the output, after the script and the validation rules
+    pub rows: usize,
+    pub columns: Vec<String>,
+    /// Types inferred from the converted sample, one per column
+    pub types: Vec<ColumnType>,
+    /// Length in characters of the longest value of every column in the sample
+    pub widths: Vec<usize>,
+    /// Size of the output, including the header
+    pub output_bytes: usize,
+    /// Whether only a sample was converted, so that the counts and the size are estimates
+    pub estimated: bool,
+}
+
+/// Converts the first records of an input into memory and reports on the output
+///
+/// Up to `ANALYSIS_SAMPLE` records are converted. Streamed JSON files stop being read
+/// after them and the number of records is extrapolated from the share of the file read;
+/// other inputs are parsed whole, so their record count is exact. Rows and output size are
+/// extrapolated from the sample.
+pub fn analyze(
+    input: &ConversionInput,
+    settings: &Settings,
+    selected_columns: Vec<String>,
+) -> Result<Analysis> {
+    let (sample, records) = match input {
+        ConversionInput::Text(text, format, _) => sample_text(text, *format, settings, ANALYSIS_SAMPLE)?,
+        ConversionInput::File(path) => match InputFormat::from_path(path) {
+            InputFormat::Json if !is_whole_file(path, settings)? => {
+                let contents = input::read_file(path)?;
+                match sample_json(&contents, settings, ANALYSIS_SAMPLE) {
+                    Err(e) if is_json5_retry(&e, settings) => {
+                        sample_text(input::file_text(&contents)?, InputFormat::Json, settings, ANALYSIS_SAMPLE)?
+                    }
+                    sampled => sampled?,
+                }
+            }
+            format => {
+                let contents = input::read_file(path)?;
+                let text = input::file_text(&contents)?;
+                sample_text(text, format, settings, ANALYSIS_SAMPLE)?
+            }
+        },
+        ConversionInput::S3(uri) => {
+            let text = cloud::download(uri, settings)?;
+            sample_text(&text, InputFormat::from_path(Path::new(uri)), settings, ANALYSIS_SAMPLE)?
+        }
+    };
+    let sample = Sample {
+        source: input.source(),
+        ..sample
+    };
+
+    // The whole sample is kept as preview rows to infer the types from
+    let settings = &Settings {
+        max_preview_rows: ANALYSIS_SAMPLE,
+        ..settings.clone()
+    };
+    let result = convert_sample(&sample, settings, selected_columns)?;
+    let sample = sample.records;
+
+    let sample_rows = &result.preview_data[usize::from(settings.include_headers)..];
+    let types = (0..result.columns.len())
+        .map(|index| {
+            ColumnType::infer(sample_rows.iter().map(|row| row.get(index).map_or("", String::as_str)))
+        })
+        .collect();
+    let widths = (0..result.columns.len())
+        .map(|index| {
+            sample_rows
+                .iter()
+                .map(|row| row.get(index).map_or(0, |cell| cell.chars().count()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let body_bytes = result.csv_content.len() - result.body_offset;
+    let scale = |sampled: usize| {
+        if sample.is_empty() {
+            sampled
+        } else {
+            (sampled as f64 * records as f64 / sample.len() as f64).round() as usize
+        }
+    };
+    Ok(Analysis {
+        records,
+        rows: scale(sample_rows.len()),
+        output_bytes: result.body_offset + scale(body_bytes),
+        estimated: records > sample.len(),
+        columns: result.columns,
+        types,
+        widths,
+    })
+}
+
+/// First records of an input, converted on their own for a preview or an analysis
+#[derive(Default)]
+pub struct Sample {
+    pub records: Vec<Value>,
+    /// Field names in input order, for formats that keep it
+    pub field_names: Vec<String>,
+    /// Path or URI of the input, for the `_source_file` column
+    pub source: String,
+    /// Where the records are in the input
+    pub pointers: RecordPointers,
+}
+
+/// Reads the first `limit` records of an input
+///
+/// JSON stops being read after them, so sampling a large file is quick; JSON5 that does
+/// not stream and the other formats are parsed whole. S3 objects are downloaded first.
+pub fn sample(input: &ConversionInput, settings: &Settings, limit: usize) -> Result<Sample> {
+    let (sample, _) = match input {
+        ConversionInput::Text(text, InputFormat::Json, _) if !is_whole_document(text.as_bytes(), settings) => {
+            sample_json(text.as_bytes(), settings, limit)
+                .or_else(|_| sample_text(text, InputFormat::Json, settings, limit))?
+        }
+        ConversionInput::Text(text, format, _) => sample_text(text, *format, settings, limit)?,
+        ConversionInput::File(path) => match InputFormat::from_path(path) {
+            InputFormat::Json if !is_whole_file(path, settings)? => {
+                let contents = input::read_file(path)?;
+                match sample_json(&contents, settings, limit) {
+                    Err(e) if is_json5_retry(&e, settings) => {
+                        sample_text(input::file_text(&contents)?, InputFormat::Json, settings, limit)?
+                    }
+                    sampled => sampled?,
+                }
+            }
+            format => {
+                let contents = input::read_file(path)?;
+                let text = input::file_text(&contents)?;
+                sample_text(text, format, settings, limit)?
+            }
+        },
+        ConversionInput::S3(uri) => {
+            let text = cloud::download(uri, settings)?;
+            sample_text(&text, InputFormat::from_path(Path::new(uri)), settings, limit)?
+        }
+    };
+    Ok(Sample {
+        source: input.source(),
+        ..sample
+    })
+}
+
+/// Converts a sample in memory, as the full conversion converts the first records
+pub fn convert_sample(sample: &Sample, settings: &Settings, selected_columns: Vec<String>) -> Result<ConversionResult> {
+    let mut builder = CsvBuilder::new(settings, selected_columns, sample.field_names.clone())?;
+    builder.source = sample.source.clone();
+    builder.pointers = sample.pointers.clone();
+    if settings.two_pass {
+        for chunk in sample.records.chunks(BATCH_SIZE) {
+            builder.scan_batch(chunk)?;
+        }
+        builder.use_scanned_columns();
+    }
+    for chunk in sample.records.chunks(BATCH_SIZE) {
+        builder.write_batch(chunk)?;
+    }
+    builder.finish()
+}
+
+/// Parses text and takes the first `limit` records as sample, with the number of records
+fn sample_text(content: &str, format: InputFormat, settings: &Settings, limit: usize) -> Result<(Sample, usize)> {
+    let parsed = input::parse(content, format, settings)?;
+    let mut records = match parsed.value {
+        Value::Array(arr) => arr,
+        obj @ Value::Object(_) => vec![obj],
+        _ => bail!("Unsupported JSON structure"),
+    };
+    let total = records.len();
+    records.truncate(limit);
+    let sample = Sample {
+        records,
+        field_names: parsed.field_names,
+        pointers: parsed.pointers,
+        ..Default::default()
+    };
+    Ok((sample, total))
+}
+
+/// Streams the first `limit` records of JSON contents as sample, with the estimated
+/// number of records in all of them
+fn sample_json(contents: &[u8], settings: &Settings, limit: usize) -> Result<(Sample, usize)> {
+    let input_bytes = contents.len();
+    let reader = CountingReader::new(contents, 0);
+    let bytes_read = Rc::clone(&reader.count);
+    let mut records = Vec::new();
+    let on_record = |record| {
+        records.push(record);
+        if records.len() < limit {
+            Ok(())
+        } else {
+            Err("sample complete".to_string())
+        }
+    };
+    let (parsed, _) = stream_json(reader, settings, on_record, &Cell::new(true));
+
+    // Stopping after the sample is not an error
+    let total = if records.len() < limit {
+        parsed?;
+        records.len()
+    } else {
+        let read = bytes_read.get().max(1);
+        if read >= input_bytes {
+            records.len()
+        } else {
+            (records.len() as f64 * input_bytes as f64 / read as f64).round() as usize
+        }
+    };
+    let sample = Sample {
+        records,
+        ..Default::default()
+    };
+    Ok((sample, total))
+}
+
+/// Extracts the message of a caught panic
+fn panic_message(payload: &Box<dyn Any + Send>) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown error"
+    }
+}
+
+/// Runs a conversion on the current thread
+///
+/// Returns `None` when the conversion was cancelled through `cancel`.
+pub fn convert(
+    input: &ConversionInput,
+    settings: &Settings,
+    selected_columns: Vec<String>,
+    progress: &Mutex<ConversionProgress>,
+    cancel: &AtomicBool,
+) -> Result<Option<ConversionResult>> {
+    let started = Instant::now();
+    let source = input.source();
+    debug!(
+        "Converting {} to {}",
+        if source.is_empty() { "standard input" } else { &source },
+        settings.output_kind.extension()
+    );
+    let pool = worker_pool(settings)?;
+    let result = pool.install(|| match input {
+        ConversionInput::Text(text, format, _) => {
+            convert_text(text, *format, source, settings, selected_columns, progress, cancel)
+        }
+        ConversionInput::File(path) => match InputFormat::from_path(path) {
+            InputFormat::Json if !is_whole_file(path, settings)? => {
+                match convert_file(path, settings, selected_columns.clone(), progress, cancel) {
+                    Err(e) if is_json5_retry(&e, settings) => {
+                        debug!("Streaming failed, reading {} as JSON5: {:#}", path.display(), e);
+                        let contents = input::read_file(path)?;
+                        let text = input::file_text(&contents)?;
+                        convert_text(text, InputFormat::Json, source, settings, selected_columns, progress, cancel)
+                    }
+                    outcome => outcome,
+                }
+            }
+            format => {
+                let contents = input::read_file(path)?;
+                let text = input::file_text(&contents)?;
+                convert_text(text, format, source, settings, selected_columns, progress, cancel)
+            }
+        },
+        ConversionInput::S3(uri) => {
+            progress.lock().unwrap().status = format!("Downloading {}...", uri);
+            let text = cloud::download(uri, settings)?;
+            debug!(bytes = text.len(), "Downloaded {}", uri);
+            let format = InputFormat::from_path(Path::new(uri));
+            convert_text(&text, format, source, settings, selected_columns, progress, cancel)
+        }
+    });
+    let result = match result? {
+        Some(mut result) => {
+            let timings = &mut result.timings;
+            timings.parse = started.elapsed().saturating_sub(timings.transform + timings.write);
+            info!(
+                records = result.records,
+                rows = result.rows,
+                columns = result.columns.len(),
+                bytes = result.csv_content.len(),
+                "Conversion finished in {:.2} s",
+                started.elapsed().as_secs_f64()
+            );
+            debug!(
+                parse = ?timings.parse,
+                transform = ?timings.transform,
+                write = ?timings.write,
+                "Conversion timings"
+            );
+            result
+        }
+        None => {
+            info!("Conversion cancelled");
+            return Ok(None);
+        }
+    };
+    Ok(Some(result))
+}
+
+/// Threads of the worker pool and whether they run at low priority
+type WorkerLimits = (usize, bool);
+
+/// Worker pool of the conversions, with the limits it was built for
+///
+/// All conversions share it, so the thread limit also holds with several jobs running at
+/// once. It is only built again for other limits while no conversion uses it, so two pools
+/// never run side by side; until then, new conversions join the current pool.
+static WORKERS: Mutex<Option<(WorkerLimits, Arc<ThreadPool>)>> = Mutex::new(None);
+
+/// Worker pool for the thread settings, see `WORKERS`
+fn worker_pool(settings: &Settings) -> Result<Arc<ThreadPool>> {
+    let limits = (settings.worker_threads, settings.low_priority);
+    let mut workers = WORKERS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((built_for, pool)) = &*workers {
+        // The only reference left is the one held here once no conversion runs
+        if *built_for == limits || Arc::strong_count(pool) > 1 {
+            if *built_for != limits {
+                debug!("Conversions are running; the new thread limits apply once they end");
+            }
+            return Ok(Arc::clone(pool));
+        }
+    }
+    let low_priority = settings.low_priority;
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(settings.worker_threads)
+        .thread_name(|index| format!("conversion-{}", index))
+        .start_handler(move |_| {
+            if low_priority {
+                if let Err(e) = set_current_thread_priority(ThreadPriority::Min) {
+                    debug!("Failed to lower the priority of a conversion thread: {:?}", e);
+                }
+            }
+        })
+        .build()
+        .context("Failed to start the conversion threads")?;
+    debug!(threads = pool.current_num_threads(), low_priority, "Started the conversion threads");
+    let pool = Arc::new(pool);
+    *workers = Some((limits, Arc::clone(&pool)));
+    Ok(pool)
+}
+
+/// Starts a conversion on a background thread
+///
+/// Progress, errors and the final `ConversionResult` are published through `progress`.
+/// The caller is expected to have marked `progress` as converting already. Setting
+/// `cancel` stops the conversion before the next record without producing a result.
+pub fn spawn_conversion(
+    input: ConversionInput,
+    settings: Settings,
+    selected_columns: Vec<String>,
+    progress: Arc<Mutex<ConversionProgress>>,
+    cancel: Arc<AtomicBool>,
+) {
+    thread::spawn(move || {
+        let mut progress_guard = progress.lock().unwrap();
+        progress_guard.started_at = Some(Instant::now());
+        progress_guard.rows_processed = 0;
+        progress_guard.total_rows = None;
+        progress_guard.bytes_processed = 0;
+        progress_guard.bytes_read = 0;
+        progress_guard.input_bytes = match &input {
+            ConversionInput::Text(text, _, _) => text.len(),
+            ConversionInput::File(_) | ConversionInput::S3(_) => 0,
+        };
+        drop(progress_guard);
+
+        // A panic must not leave the job marked as converting, so it is reported like
+        // any other error
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+            convert(&input, &settings, selected_columns, &progress, &cancel)
+        }))
+        .unwrap_or_else(|payload| Err(anyhow!("Conversion crashed: {}", panic_message(&payload))));
+
+        progress.clear_poison();
+        let mut progress_guard = progress.lock().unwrap_or_else(PoisonError::into_inner);
+        progress_guard.is_converting = false;
+        match outcome {
+            Ok(Some(result)) => {
+                progress_guard.progress = 1.0;
+                progress_guard.bytes_processed = result.csv_content.len();
+                progress_guard.status = "Conversion completed successfully".to_string();
+                progress_guard.result = Some(result);
+            }
+            Ok(None) => {
+                progress_guard.status = "Conversion cancelled; partial output discarded".to_string();
+            }
+            Err(e) => {
+                progress_guard.status = format!("{:#}", e);
+                progress_guard.failed = true;
+            }
+        }
+    });
+}
@@ -0,0 +1,254 @@
+//! Database export
+//!
+//! Converted rows can be written straight into a PostgreSQL or MySQL table. Column types
+//! are inferred from the converted values unless a JSON Schema declares them, the table
+//! is created if it does not exist yet, and all rows are inserted in one transaction. A
+//! dry run only returns the DDL.
+
+use crate::locale;
+use crate::schema::ColumnType;
+use crate::secrets;
+use crate::settings::{DateFormat, OutputKind, Settings};
+use anyhow::{bail, Context, Result};
+use sqlx::mysql::{MySql, MySqlConnection};
+use sqlx::postgres::{PgConnection, Postgres};
+use sqlx::{Connection, QueryBuilder};
+
+/// Maximum number of rows per `INSERT` statement
+const INSERT_BATCH_ROWS: usize = 1000;
+
+/// Maximum number of bind parameters per statement, the PostgreSQL limit
+const MAX_BIND_PARAMETERS: usize = 65535;
+
+/// SQL dialect, chosen by the scheme of the connection string
+#[derive(Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Postgres,
+    MySql,
+}
+
+impl Dialect {
+    /// Detects the dialect of a connection string such as `postgres://user@host/db`
+    pub fn from_url(url: &str) -> Result<Self> {
+        let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+        match scheme {
+            "postgres" | "postgresql" => Ok(Dialect::Postgres),
+            "mysql" | "mariadb" => Ok(Dialect::MySql),
+            _ => bail!("Unsupported database URL; use postgres://… or mysql://…"),
+        }
+    }
+
+    /// Quotes a table or column name
+    fn quote_identifier(self, name: &str) -> String {
+        match self {
+            Dialect::Postgres => format!("\"{}\"", name.replace('"', "\"\"")),
+            Dialect::MySql => format!("`{}`", name.replace('`', "``")),
+        }
+    }
+
+    /// Name of a column type in this dialect
+    fn type_name(self, column_type: ColumnType) -> &'static str {
+        match (self, column_type) {
+            (_, ColumnType::Integer) => "BIGINT",
+            (Dialect::Postgres, ColumnType::Float) => "DOUBLE PRECISION",
+            (Dialect::MySql, ColumnType::Float) => "DOUBLE",
+            (_, ColumnType::Boolean) => "BOOLEAN",
+            (_, ColumnType::Text) => "TEXT",
+        }
+    }
+}
+
+/// Rows of a conversion with their columns and their declared or inferred types
+pub struct Table {
+    pub columns: Vec<String>,
+    pub types: Vec<ColumnType>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Reads the rows back from CSV output, whose data rows start at `body_offset`
+    ///
+    /// Fails for output written with a decimal comma or local dates, whose cells would no
+    /// longer read as the numbers and dates they were.
+    pub fn from_csv(
+        content: &[u8],
+        columns: &[String],
+        body_offset: usize,
+        settings: &Settings,
+    ) -> Result<Self> {
+        if settings.output_kind != OutputKind::Csv {
+            bail!("Database export needs CSV output");
+        }
+        if columns.is_empty() {
+            bail!("The conversion produced no columns");
+        }
+        if settings.decimal_comma || settings.date_format != DateFormat::Iso {
+            bail!(
+                "Exports need decimal points and ISO dates; choose the \"Standard\" target locale, \
+                 or turn off the decimal comma and write dates as ISO 8601, and convert again"
+            );
+        }
+
+        let body = locale::decode(&content[body_offset..], settings.encoding);
+        let rows = csv::ReaderBuilder::new()
+            .delimiter(settings.delimiter.bytes().next().unwrap_or(b','))
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(body.as_bytes())
+            .records()
+            .map(|record| record.map(|record| record.iter().map(str::to_string).collect()))
+            .collect::<Result<Vec<Vec<String>>, _>>()
+            .context("Failed to read converted rows")?;
+
+        let types = columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| {
+                let schema = settings.json_schema.as_ref();
+                let declared = schema.and_then(|schema| schema.column_type(column));
+                declared.unwrap_or_else(|| {
+                    ColumnType::infer(rows.iter().map(|row| row.get(index).map_or("", String::as_str)))
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            columns: columns.to_vec(),
+            types,
+            rows,
+        })
+    }
+
+    /// `CREATE TABLE IF NOT EXISTS` statement for this table
+    pub fn create_table_sql(&self, dialect: Dialect, table_name: &str) -> String {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .zip(&self.types)
+            .map(|(name, column_type)| {
+                format!(
+                    "    {} {}",
+                    dialect.quote_identifier(name),
+                    dialect.type_name(*column_type)
+                )
+            })
+            .collect();
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (\n{}\n);",
+            dialect.quote_identifier(table_name),
+            columns.join(",\n")
+        )
+    }
+}
+
+/// Exports rows into a database table
+///
+/// Returns a summary, or the DDL and row count when `dry_run` is set, in which case the
+/// database is not contacted. The URL may hold references to environment variables or
+/// keychain secrets, such as `postgres://app:${env:DB_PASSWORD}@host/db`.
+pub fn export(url: &str, table_name: &str, table: &Table, dry_run: bool) -> Result<String> {
+    if table_name.trim().is_empty() {
+        bail!("No table name given");
+    }
+    let dialect = Dialect::from_url(url)?;
+    let ddl = table.create_table_sql(dialect, table_name);
+    if dry_run {
+        return Ok(format!("{}\n-- {} rows would be inserted", ddl, table.rows.len()));
+    }
+    let url = &secrets::resolve(url).context("Failed to read the database URL")?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start database runtime")?;
+    runtime.block_on(async {
+        match dialect {
+            Dialect::Postgres => {
+                let mut connection = PgConnection::connect(url)
+                    .await
+                    .context("Failed to connect to the database")?;
+                insert_postgres(&mut connection, dialect, table_name, table, &ddl).await
+            }
+            Dialect::MySql => {
+                let mut connection = MySqlConnection::connect(url)
+                    .await
+                    .context("Failed to connect to the database")?;
+                insert_mysql(&mut connection, dialect, table_name, table, &ddl).await
+            }
+        }
+    })?;
+
+    Ok(format!(
+        "Inserted {} rows into {}",
+        table.rows.len(),
+        table_name
+    ))
+}
+
+/// Defines a function that creates the table if needed and inserts all rows in one
+/// transaction
+///
+/// A macro instead of a generic function: sqlx cannot express the bounds a generic
+/// connection needs for executing a borrowed `QueryBuilder`.
+macro_rules! insert_table {
+    ($name:ident, $database:ty, $connection:ty) => {
+        async fn $name(
+            connection: &mut $connection,
+            dialect: Dialect,
+            table_name: &str,
+            table: &Table,
+            ddl: &str,
+        ) -> Result<()> {
+            let mut transaction = connection.begin().await?;
+            sqlx::query(ddl)
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to create the table")?;
+
+            let batch_rows =
+                (MAX_BIND_PARAMETERS / table.columns.len()).clamp(1, INSERT_BATCH_ROWS);
+            let insert_prefix = format!(
+                "INSERT INTO {} ({}) ",
+                dialect.quote_identifier(table_name),
+                table
+                    .columns
+                    .iter()
+                    .map(|name| dialect.quote_identifier(name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+
+            for batch in table.rows.chunks(batch_rows) {
+                let mut query = QueryBuilder::<$database>::new(&insert_prefix);
+                query.push_values(batch, |mut values, row| {
+                    for (index, column_type) in table.types.iter().enumerate() {
+                        let cell = row.get(index).filter(|cell| !cell.is_empty());
+                        match column_type {
+                            ColumnType::Integer => {
+                                values.push_bind(cell.and_then(|cell| cell.parse::<i64>().ok()))
+                            }
+                            ColumnType::Float => {
+                                values.push_bind(cell.and_then(|cell| cell.parse::<f64>().ok()))
+                            }
+                            ColumnType::Boolean => {
+                                values.push_bind(cell.and_then(|cell| cell.parse::<bool>().ok()))
+                            }
+                            ColumnType::Text => values.push_bind(cell.cloned()),
+                        };
+                    }
+                });
+                query
+                    .build()
+                    .execute(&mut *transaction)
+                    .await
+                    .context("Failed to insert rows")?;
+            }
+
+            transaction.commit().await?;
+            Ok(())
+        }
+    };
+}
+
+insert_table!(insert_postgres, Postgres, PgConnection);
+insert_table!(insert_mysql, MySql, MySqlConnection);
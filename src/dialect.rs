@@ -0,0 +1,159 @@
+//! CSV input dialects
+//!
+//! CSV files differ in their delimiter, their quote character and whether the first row
+//! is a header. `sniff` guesses all three from the start of a file by trying every
+//! combination of the usual delimiters and quotes and keeping the one that splits the rows
+//! most consistently; `CsvDialect` in the settings overrides any of them.
+
+use crate::settings::CsvDialect;
+use anyhow::{bail, Result};
+
+/// Delimiters tried when sniffing, in order of preference
+pub const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+/// Quote characters tried when sniffing, in order of preference
+pub const QUOTES: [u8; 2] = [b'"', b'\''];
+
+/// Bytes of the input looked at when sniffing
+const SAMPLE_BYTES: usize = 64 * 1024;
+
+/// Rows looked at when sniffing
+const SAMPLE_ROWS: usize = 50;
+
+/// How a CSV file is written
+#[derive(Clone, Copy, PartialEq)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    /// Whether the first row names the columns
+    pub has_header: bool,
+}
+
+impl Dialect {
+    /// Short description such as `delimiter ';', quote '"', header`, for the interface
+    pub fn describe(&self) -> String {
+        format!(
+            "delimiter {}, quote {}, {}",
+            name(self.delimiter),
+            name(self.quote),
+            if self.has_header { "header" } else { "no header" }
+        )
+    }
+
+    /// The sniffed dialect with the choices of the settings applied over it
+    ///
+    /// Fails when a chosen delimiter or quote is not a single ASCII character, which the
+    /// CSV reader requires.
+    pub fn with_overrides(mut self, overrides: &CsvDialect) -> Result<Self> {
+        if let Some(delimiter) = overrides.delimiter {
+            self.delimiter = ascii(delimiter, "delimiter")?;
+        }
+        if let Some(quote) = overrides.quote {
+            self.quote = ascii(quote, "quote")?;
+        }
+        if let Some(has_header) = overrides.has_header {
+            self.has_header = has_header;
+        }
+        Ok(self)
+    }
+}
+
+/// Readable name of a delimiter or quote character
+pub fn name(byte: u8) -> String {
+    match byte {
+        b'\t' => "tab".to_string(),
+        b' ' => "space".to_string(),
+        byte => format!("'{}'", byte as char),
+    }
+}
+
+fn ascii(c: char, what: &str) -> Result<u8> {
+    match u8::try_from(c) {
+        Ok(byte) if byte.is_ascii() => Ok(byte),
+        _ => bail!("Invalid CSV input {} {:?}; it must be an ASCII character", what, c),
+    }
+}
+
+/// Guesses the dialect of CSV text from its first rows
+///
+/// Every delimiter and quote is tried; the winner splits the most rows into the same
+/// number of fields, preferring more fields and earlier candidates on ties. The first row
+/// is taken for a header unless it looks like data: a field is empty or a number, a name
+/// repeats, or a column holds the same value further down.
+pub fn sniff(content: &str) -> Dialect {
+    let mut end = content.len().min(SAMPLE_BYTES);
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    // A cut row would not split like the others
+    let sample = match content[..end].rfind('\n') {
+        Some(newline) if end < content.len() => &content[..newline],
+        _ => &content[..end],
+    };
+
+    let mut best = (Dialect { delimiter: b',', quote: b'"', has_header: true }, (0, 0));
+    for quote in QUOTES {
+        for delimiter in DELIMITERS {
+            let rows = sample_rows(sample, delimiter, quote);
+            let score = consistency(&rows);
+            if score > best.1 {
+                best = (Dialect { delimiter, quote, has_header: true }, score);
+            }
+        }
+    }
+    let mut dialect = best.0;
+    dialect.has_header = looks_like_header(&sample_rows(sample, dialect.delimiter, dialect.quote));
+    dialect
+}
+
+/// First rows of the sample split with a delimiter and quote; rows that fail to parse end
+/// the sample
+fn sample_rows(sample: &str, delimiter: u8, quote: u8) -> Vec<Vec<String>> {
+    csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quote(quote)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(sample.as_bytes())
+        .records()
+        .map_while(Result::ok)
+        .take(SAMPLE_ROWS)
+        .map(|record| record.iter().map(str::to_string).collect())
+        .collect()
+}
+
+/// Number of rows with the most common field count, and that count; zero for rows of a
+/// single field, which every delimiter produces from text without it
+fn consistency(rows: &[Vec<String>]) -> (usize, usize) {
+    let mut counts: Vec<(usize, usize)> = Vec::new();
+    for row in rows {
+        match counts.iter_mut().find(|(fields, _)| *fields == row.len()) {
+            Some((_, rows)) => *rows += 1,
+            None => counts.push((row.len(), 1)),
+        }
+    }
+    counts
+        .into_iter()
+        .filter(|(fields, _)| *fields > 1)
+        .map(|(fields, rows)| (rows, fields))
+        .max()
+        .unwrap_or_default()
+}
+
+fn looks_like_header(rows: &[Vec<String>]) -> bool {
+    let Some((first, rest)) = rows.split_first() else {
+        return true;
+    };
+    let data_like = first
+        .iter()
+        .any(|field| field.trim().is_empty() || field.trim().parse::<f64>().is_ok());
+    let repeated = first
+        .iter()
+        .enumerate()
+        .any(|(index, field)| first[..index].contains(field));
+    let recurs_below = first
+        .iter()
+        .enumerate()
+        .any(|(index, field)| rest.iter().any(|row| row.get(index) == Some(field)));
+    !data_like && !repeated && !recurs_below
+}
@@ -0,0 +1,150 @@
+//! Data dictionary
+//!
+//! Governance teams want every column of a feed documented. When a dictionary format is
+//! set, a conversion describes its output columns: the JSON path their values come from,
+//! their type, the share of null cells and the transforms applied to them. The dictionary
+//! is saved next to the output as `<name>_dictionary.csv` or `<name>_dictionary.md`.
+
+use crate::output::write_atomically;
+use crate::schema::ColumnType;
+use crate::settings::DictionaryFormat;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Description of one output column
+#[derive(Clone)]
+pub struct DictionaryColumn {
+    pub column: String,
+    /// JSON Pointer of the value within a record, or what fills a metadata column
+    pub source: String,
+    /// Type declared by a JSON Schema, if there is one
+    pub declared_type: Option<ColumnType>,
+    /// Type of the cells written so far; `None` before the first non-null cell
+    pub inferred_type: Option<ColumnType>,
+    pub rows: usize,
+    /// Empty cells and JSON `null`s
+    pub nulls: usize,
+    /// Transforms the cells went through, in the order they ran
+    pub transforms: Vec<String>,
+}
+
+impl DictionaryColumn {
+    pub fn new(column: &str, source: String, declared_type: Option<ColumnType>, transforms: Vec<String>) -> Self {
+        Self {
+            column: column.to_string(),
+            source,
+            declared_type,
+            inferred_type: None,
+            rows: 0,
+            nulls: 0,
+            transforms,
+        }
+    }
+
+    /// Declared or inferred type
+    pub fn column_type(&self) -> ColumnType {
+        self.declared_type.or(self.inferred_type).unwrap_or(ColumnType::Text)
+    }
+
+    /// Share of null cells in percent
+    pub fn null_percent(&self) -> f64 {
+        if self.rows == 0 {
+            0.0
+        } else {
+            self.nulls as f64 * 100.0 / self.rows as f64
+        }
+    }
+}
+
+/// Descriptions of the output columns of a conversion
+#[derive(Clone)]
+pub struct Dictionary {
+    pub format: DictionaryFormat,
+    pub columns: Vec<DictionaryColumn>,
+}
+
+impl Dictionary {
+    /// Counts the cells of a written row, in column order
+    pub fn add(&mut self, values: &[String]) {
+        for (column, value) in self.columns.iter_mut().zip(values) {
+            column.rows += 1;
+            if value.is_empty() || value == "null" {
+                column.nulls += 1;
+            }
+            column.inferred_type = ColumnType::widen(column.inferred_type, value);
+        }
+    }
+
+    /// Leaves out the columns that are not among `columns`, such as dropped empty ones
+    pub fn retain(&mut self, columns: &[String]) {
+        self.columns.retain(|column| columns.contains(&column.column));
+    }
+
+    /// Contents of the dictionary file
+    pub fn render(&self) -> Result<Vec<u8>> {
+        match self.format {
+            DictionaryFormat::Off | DictionaryFormat::Csv => self.to_csv(),
+            DictionaryFormat::Markdown => Ok(self.to_markdown().into_bytes()),
+        }
+    }
+
+    fn to_csv(&self) -> Result<Vec<u8>> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(["column", "source", "type", "null_percent", "transforms"])?;
+        for column in &self.columns {
+            let null_percent = format!("{:.1}", column.null_percent());
+            let transforms = column.transforms.join("; ");
+            writer.write_record([
+                column.column.as_str(),
+                column.source.as_str(),
+                column.column_type().label(),
+                null_percent.as_str(),
+                transforms.as_str(),
+            ])?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("Failed to write the data dictionary")
+    }
+
+    fn to_markdown(&self) -> String {
+        // Pipes would end the cell early
+        let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+        let rows = self.columns.first().map_or(0, |column| column.rows);
+        let mut markdown = format!("# Data Dictionary\n\n{} columns, {} rows\n\n", self.columns.len(), rows);
+        markdown.push_str("| Column | Source | Type | Null Rate | Transforms |\n|---|---|---|---:|---|\n");
+        for column in &self.columns {
+            let source = if column.source.starts_with('/') {
+                format!("`{}`", cell(&column.source))
+            } else {
+                cell(&column.source)
+            };
+            markdown.push_str(&format!(
+                "| {} | {} | {} | {:.1}% | {} |\n",
+                cell(&column.column),
+                source,
+                column.column_type().label(),
+                column.null_percent(),
+                cell(&column.transforms.join(", "))
+            ));
+        }
+        markdown
+    }
+}
+
+/// Path of the dictionary of an output file, e.g. `sales_dictionary.csv` for `sales.csv`;
+/// `dictionary.csv` in the current directory for an empty path, as for standard output
+fn dictionary_path(output: &Path, format: DictionaryFormat) -> PathBuf {
+    match output.file_stem() {
+        Some(stem) => output.with_file_name(format!("{}_dictionary.{}", stem.to_string_lossy(), format.extension())),
+        None => PathBuf::from(format!("dictionary.{}", format.extension())),
+    }
+}
+
+/// Saves the dictionary next to the output and returns its path
+pub fn save(output: &Path, dictionary: &Dictionary) -> Result<PathBuf> {
+    let path = dictionary_path(output, dictionary.format);
+    write_atomically(&path, &dictionary.render()?).context("Failed to save the data dictionary")?;
+    Ok(path)
+}
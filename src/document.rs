@@ -0,0 +1,2469 @@
+//! A single document tab
+//!
+//! Each tab owns its input file, settings, conversion progress and result, so several
+//! conversions can run side by side without interfering with each other.
+
+use crate::checkpoint;
+use crate::cloud;
+use crate::conversion::{self, format_bytes, Analysis, ConversionInput, ConversionProgress, ConversionResult, Sample};
+use crate::database::{self, Table};
+use crate::dialect::{self, Dialect};
+use crate::dictionary::{self, Dictionary};
+use crate::input::InputFormat;
+use crate::jobs::JobQueue;
+use crate::locale::TargetLocale;
+use crate::output::{append_to_csv_file, next_free_path, write_atomically};
+use crate::replace::{self, FindReplace, FindRequest, Finder};
+use crate::schema::{AvroField, ColumnType, JsonSchema};
+use crate::preset::{self, Preset};
+use crate::session::TabState;
+use crate::settings::{
+    Anonymization, ColumnAnonymization, ColumnDefault, ColumnWidth, ControlCharacters, DateFormat, DictionaryFormat,
+    DuplicateKeys, GeometryColumns, LargeFileAction, LineEndings, MappedValue, NewlineHandling, OutputKind, RaggedRows,
+    Settings, TextEncoding, ValueMapping,
+};
+use crate::secrets;
+use crate::sheets;
+use crate::snapshot;
+use crate::validation::{self, ColumnRule, InvalidRows};
+use crate::stats::ColumnStats;
+use crate::summary::ConversionSummary;
+use eframe::egui;
+use rfd::FileDialog;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::{debug, error, info, warn};
+
+/// State of one document tab
+pub struct Document {
+    /// Path to the currently loaded JSON file
+    pub json_path: Option<PathBuf>,
+    /// Path to the saved CSV file
+    pub csv_path: Option<PathBuf>,
+    /// Current status message of this document
+    pub status: String,
+    /// Content of the loaded JSON file
+    pub json_content: Option<String>,
+    /// Whether the input is too large to load and is streamed from `json_path` instead
+    pub stream_input: bool,
+    /// Dialect sniffed from the loaded content when it is CSV
+    pub detected_dialect: Option<Dialect>,
+    /// Generated CSV content
+    pub csv_content: Option<Vec<u8>>,
+    /// Columns of the generated CSV content
+    pub csv_columns: Vec<String>,
+    /// Byte offset in the CSV content where the data rows start
+    pub csv_body_offset: usize,
+    /// Rows rejected by the validation rules as CSV, saved next to the output
+    pub rejected_csv: Option<Vec<u8>>,
+    /// Descriptions of the output columns, saved next to the output when requested
+    pub dictionary: Option<Dictionary>,
+    /// Preview data for the grid view
+    pub preview_data: Option<Vec<Vec<String>>>,
+    /// Area of the preview table on screen, cropped from screenshots
+    pub preview_rect: egui::Rect,
+    /// PNG file the preview is saved to once the requested screenshot arrives
+    pub snapshot_path: Option<PathBuf>,
+    /// Input values behind the preview rows, before the transform script
+    pub raw_preview: Vec<Vec<Option<Value>>>,
+    /// Whether the preview shows the sample rather than the output of a conversion
+    pub preview_is_sample: bool,
+    /// First records of the loaded input, previewed before the full conversion
+    pub sample: Option<Sample>,
+    /// Whether the sample is being read
+    pub sampling: bool,
+    /// Outcome of the latest sampling, filled in by the sampling thread
+    pub sample_result: Arc<Mutex<Option<Result<Sample, String>>>>,
+    /// Fingerprint of the settings the sample was read with
+    pub sampled_with: u64,
+    /// Fingerprint of the settings and columns the preview was made with
+    pub previewed_with: u64,
+    /// Why the sample could not be previewed with the current settings
+    pub sample_error: Option<String>,
+    /// Preview columns by index that show the input values instead of the CSV cells
+    pub raw_columns: HashSet<usize>,
+    /// Statistics of the preview columns by index, computed when their header is hovered
+    pub column_stats: HashMap<usize, ColumnStats>,
+    /// Progress tracking for conversion
+    pub progress: Arc<Mutex<ConversionProgress>>,
+    /// Id of the job running the latest conversion
+    pub job_id: Option<u64>,
+    /// Whether to show the preview panel
+    pub show_preview: bool,
+    /// Current error message if any
+    pub error_message: Option<String>,
+    /// Settings used for this document
+    pub settings: Settings,
+    /// Current search query for preview
+    pub search_query: String,
+    /// Selected columns for export
+    pub selected_columns: Vec<String>,
+    /// Column selection of the last preset loaded, which files opened later start with
+    pub preset_columns: Vec<String>,
+    /// All available columns from the JSON
+    pub all_columns: Vec<String>,
+    /// Save target waiting for overwrite confirmation
+    pub pending_overwrite: Option<PathBuf>,
+    /// Large file and its size in bytes, waiting for the user to choose how to open it
+    pub pending_large_file: Option<(PathBuf, u64)>,
+    /// `s3://` URI of the input when it was loaded from S3 instead of a file
+    pub source_uri: Option<String>,
+    /// S3 URI typed into the load field
+    pub s3_input: String,
+    /// S3 URI typed into the upload field
+    pub s3_output: String,
+    /// Whether an upload to S3 is running
+    pub uploading: bool,
+    /// Outcome of the latest upload, filled in by the upload thread
+    pub upload_result: Arc<Mutex<Option<Result<String, String>>>>,
+    /// Name and secret typed into the credentials manager, to be saved in the keychain
+    pub new_secret: (String, String),
+    /// Connection string typed into the database export field
+    pub db_url: String,
+    /// Table name typed into the database export field
+    pub db_table: String,
+    /// Title typed into the Google Sheets export field
+    pub sheet_title: String,
+    /// Whether an export to a database or Google Sheets is running
+    pub exporting: bool,
+    /// Whether the running or latest database export is a dry run
+    pub export_dry_run: bool,
+    /// Whether the running or latest export goes to Google Sheets rather than a database
+    pub sheets_export: bool,
+    /// Outcome of the latest export, filled in by the export thread
+    pub export_result: Arc<Mutex<Option<Result<String, String>>>>,
+    /// DDL of the latest dry run
+    pub export_ddl: Option<String>,
+    /// Ctrl+H find and replace window
+    pub find_replace: FindReplace,
+    /// Whether an analysis of the input is running
+    pub analyzing: bool,
+    /// Outcome of the latest analysis, filled in by the analysis thread
+    pub analysis_result: Arc<Mutex<Option<Result<Analysis, String>>>>,
+    /// Latest analysis, shown below the conversion button
+    pub analysis: Option<Analysis>,
+    /// Summary of the latest conversion
+    pub summary: Option<ConversionSummary>,
+    /// Whether the summary window is open
+    pub summary_open: bool,
+}
+
+impl Document {
+    /// Creates an empty document using the given settings
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            json_path: None,
+            csv_path: None,
+            status: "Ready".to_string(),
+            json_content: None,
+            detected_dialect: None,
+            stream_input: false,
+            csv_content: None,
+            csv_columns: Vec::new(),
+            csv_body_offset: 0,
+            rejected_csv: None,
+            dictionary: None,
+            preview_data: None,
+            preview_rect: egui::Rect::NOTHING,
+            snapshot_path: None,
+            raw_preview: Vec::new(),
+            preview_is_sample: false,
+            sample: None,
+            sampling: false,
+            sample_result: Arc::new(Mutex::new(None)),
+            sampled_with: 0,
+            previewed_with: 0,
+            sample_error: None,
+            raw_columns: HashSet::new(),
+            column_stats: HashMap::new(),
+            progress: Arc::new(Mutex::new(ConversionProgress::default())),
+            job_id: None,
+            show_preview: false,
+            error_message: None,
+            settings,
+            search_query: String::new(),
+            selected_columns: Vec::new(),
+            preset_columns: Vec::new(),
+            all_columns: Vec::new(),
+            pending_overwrite: None,
+            pending_large_file: None,
+            source_uri: None,
+            s3_input: String::new(),
+            s3_output: String::new(),
+            uploading: false,
+            upload_result: Arc::new(Mutex::new(None)),
+            new_secret: (String::new(), String::new()),
+            db_url: String::new(),
+            db_table: String::new(),
+            sheet_title: String::new(),
+            exporting: false,
+            export_dry_run: false,
+            sheets_export: false,
+            export_result: Arc::new(Mutex::new(None)),
+            export_ddl: None,
+            find_replace: FindReplace::default(),
+            analyzing: false,
+            analysis_result: Arc::new(Mutex::new(None)),
+            analysis: None,
+            summary: None,
+            summary_open: false,
+        }
+    }
+
+    /// Recreates a tab of an autosaved session, loading its input again
+    ///
+    /// The column selection is put back after loading, so convert-on-load waits for it.
+    pub fn restore(state: TabState, jobs: &mut JobQueue) -> Self {
+        let convert_on_load = state.settings.convert_on_load;
+        let mut document = Self::new(state.settings);
+        document.settings.convert_on_load = false;
+        if let Some(uri) = state.source_uri {
+            document.open_s3(uri, jobs);
+        } else if let Some(path) = state.json_path {
+            document.load_json_file(path, jobs);
+        }
+        document.settings.convert_on_load = convert_on_load;
+
+        document.all_columns = state.all_columns;
+        document.selected_columns = state.selected_columns;
+        document.s3_output = state.s3_output;
+        document.db_url = state.db_url;
+        document.db_table = state.db_table;
+        document.sheet_title = state.sheet_title;
+        let loaded = document.json_content.is_some()
+            || document.stream_input
+            || document.source_uri.is_some();
+        if convert_on_load && loaded {
+            document.convert_to_csv(jobs);
+        }
+        document
+    }
+
+    /// State of this tab for the session autosave
+    pub fn tab_state(&self) -> TabState {
+        TabState {
+            json_path: self.json_path.clone(),
+            source_uri: self.source_uri.clone(),
+            settings: self.settings.clone(),
+            all_columns: self.all_columns.clone(),
+            selected_columns: self.selected_columns.clone(),
+            s3_output: self.s3_output.clone(),
+            db_url: secrets::without_password(&self.db_url),
+            db_table: self.db_table.clone(),
+            sheet_title: self.sheet_title.clone(),
+        }
+    }
+
+    /// Title shown in the tab bar
+    pub fn title(&self) -> String {
+        if let Some(uri) = &self.source_uri {
+            return uri.rsplit('/').next().unwrap_or(uri).to_string();
+        }
+        self.json_path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled".to_string())
+    }
+
+    /// Forgets the columns of the previous input; the selection falls back to that of the
+    /// last preset loaded
+    pub fn reset_columns(&mut self) {
+        self.all_columns.clear();
+        self.selected_columns = self.preset_columns.clone();
+    }
+
+    /// Applies the settings and column selection of a preset file picked by the user
+    pub fn load_preset(&mut self) {
+        let Some(path) = FileDialog::new().add_filter("Preset", &["json"]).pick_file() else {
+            return;
+        };
+        match preset::load(&path) {
+            Ok(preset) => {
+                self.settings = preset.settings_over(&self.settings);
+                self.selected_columns = preset.columns.clone();
+                self.preset_columns = preset.columns;
+                self.status = format!("Preset {} loaded", path.display());
+                self.error_message = None;
+            }
+            Err(e) => self.error_message = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Loads a JSON Schema of the records picked by the user into the settings
+    fn load_json_schema(&mut self) {
+        let Some(path) = FileDialog::new().add_filter("JSON Schema", &["json"]).pick_file() else {
+            return;
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let schema = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))
+            .and_then(|schema| JsonSchema::parse(&name, &schema));
+        match schema {
+            Ok(schema) => {
+                self.status = format!("JSON Schema {} loaded with {} columns", name, schema.columns.len());
+                self.settings.json_schema = Some(schema);
+                self.error_message = None;
+            }
+            Err(e) => self.error_message = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Saves the secret typed into the credentials manager in the keychain
+    fn save_keychain_secret(&mut self) {
+        let name = self.new_secret.0.trim().to_string();
+        match secrets::save(&name, &self.new_secret.1) {
+            Ok(()) => {
+                if !self.settings.keychain_secrets.contains(&name) {
+                    self.settings.keychain_secrets.push(name.clone());
+                }
+                self.status = format!("Secret saved; use {} to refer to it", secrets::keychain_reference(&name));
+                self.new_secret = (String::new(), String::new());
+                self.error_message = None;
+            }
+            Err(e) => self.error_message = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Removes a secret of the credentials manager from the keychain
+    fn remove_keychain_secret(&mut self, index: usize) {
+        let name = self.settings.keychain_secrets[index].clone();
+        match secrets::remove(&name) {
+            Ok(()) => {
+                self.settings.keychain_secrets.remove(index);
+                self.status = format!("Secret {} removed from the keychain", name);
+            }
+            Err(e) => self.error_message = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Saves the settings and column selection as a preset file picked by the user
+    pub fn save_preset(&mut self) {
+        let Some(path) = FileDialog::new()
+            .add_filter("Preset", &["json"])
+            .set_file_name("preset.json")
+            .save_file()
+        else {
+            return;
+        };
+        match preset::save(&path, &Preset::new(&self.settings, &self.selected_columns)) {
+            Ok(()) => self.status = format!("Preset saved to {}", path.display()),
+            Err(e) => self.error_message = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Whether a conversion of this document is currently running
+    pub fn is_converting(&self) -> bool {
+        self.progress.lock().unwrap().is_converting
+    }
+
+    /// Loads a JSON file into this document and, when convert-on-load is enabled, starts
+    /// the conversion right away
+    ///
+    /// Files above the large file threshold are not read into memory: depending on the
+    /// settings they are streamed during conversion or the user is asked first.
+    /// Returns whether the file could be read.
+    pub fn load_json_file(&mut self, path: PathBuf, jobs: &mut JobQueue) -> bool {
+        let size = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                error!("Failed to read {}: {}", path.display(), e);
+                self.error_message = Some(format!("Failed to read JSON file: {}", e));
+                self.status = "Error loading file".to_string();
+                return false;
+            }
+        };
+        if size > self.settings.large_file_threshold_mb * 1024 * 1024 {
+            match self.settings.large_file_action {
+                LargeFileAction::Stream => self.open_streamed(path, size, jobs),
+                LargeFileAction::Warn => self.pending_large_file = Some((path, size)),
+            }
+            return true;
+        }
+
+        self.read_json_file(path, jobs)
+    }
+
+    /// Reads a JSON file into memory
+    fn read_json_file(&mut self, path: PathBuf, jobs: &mut JobQueue) -> bool {
+        self.json_path = Some(path.clone());
+        self.source_uri = None;
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                info!(bytes = content.len(), "Loaded {}", path.display());
+                self.detected_dialect = (InputFormat::from_path(&path) == InputFormat::Csv)
+                    .then(|| dialect::sniff(&content));
+                if let Some(dialect) = &self.detected_dialect {
+                    debug!("Sniffed CSV dialect: {}", dialect.describe());
+                }
+                self.json_content = Some(content);
+                self.stream_input = false;
+                self.status = format!(
+                    "{} file loaded successfully",
+                    InputFormat::from_path(&path).name()
+                );
+                self.error_message = None;
+                self.preview_data = None;
+                self.reset_columns();
+                self.sample_input();
+
+                if self.settings.convert_on_load {
+                    self.convert_to_csv(jobs);
+                }
+                true
+            }
+            Err(e) => {
+                error!("Failed to read {}: {}", path.display(), e);
+                self.error_message = Some(format!("Failed to read JSON file: {}", e));
+                self.status = "Error loading file".to_string();
+                false
+            }
+        }
+    }
+
+    /// Opens a JSON file for streaming without reading it into memory
+    fn open_streamed(&mut self, path: PathBuf, size: u64, jobs: &mut JobQueue) {
+        info!(bytes = size, "Opened {} for streaming", path.display());
+        self.json_path = Some(path);
+        self.source_uri = None;
+        self.json_content = None;
+        self.detected_dialect = None;
+        self.stream_input = true;
+        self.status = format!(
+            "Large file ({}) will be streamed during conversion",
+            format_bytes(size as usize)
+        );
+        self.error_message = None;
+        self.preview_data = None;
+        self.reset_columns();
+        self.sample_input();
+
+        if self.settings.convert_on_load {
+            self.convert_to_csv(jobs);
+        }
+    }
+
+    /// Uses an S3 object as input; it is downloaded when the conversion starts
+    fn open_s3(&mut self, uri: String, jobs: &mut JobQueue) {
+        if cloud::S3Location::parse(&uri).is_none() {
+            self.error_message = Some(format!("Invalid S3 URI {:?}; expected s3://bucket/key", uri));
+            return;
+        }
+        // Suggest uploading the output next to the input
+        let stem = uri.rsplit_once('.').map_or(uri.as_str(), |(stem, _)| stem);
+        self.s3_output = format!("{}.{}", stem, self.settings.output_kind.extension());
+        info!("Opened {}", uri);
+
+        self.json_path = None;
+        self.json_content = None;
+        self.detected_dialect = None;
+        self.stream_input = false;
+        self.status = format!("{} will be downloaded during conversion", uri);
+        self.source_uri = Some(uri);
+        self.error_message = None;
+        self.preview_data = None;
+        // Sampling would download the whole object
+        self.discard_sample();
+        self.reset_columns();
+
+        if self.settings.convert_on_load {
+            self.convert_to_csv(jobs);
+        }
+    }
+
+    /// Uploads the converted output to the S3 URI of the upload field in the background
+    fn upload_to_s3(&mut self) {
+        let Some(content) = self.csv_content.clone() else {
+            return;
+        };
+        let uri = self.s3_output.clone();
+        let settings = self.settings.clone();
+        let result = Arc::clone(&self.upload_result);
+        self.uploading = true;
+        self.status = format!("Uploading to {}...", uri);
+
+        thread::spawn(move || {
+            let outcome = cloud::upload(
+                &uri,
+                &content,
+                settings.output_kind.content_type(),
+                &settings,
+            )
+            .map(|_| format!("Uploaded to {}", uri))
+            .map_err(|e| format!("{:#}", e));
+            *result.lock().unwrap() = Some(outcome);
+        });
+    }
+
+    /// Inserts the converted rows into the table of the database export fields in the
+    /// background, or only shows the DDL when `dry_run` is set
+    fn export_to_database(&mut self, dry_run: bool) {
+        let Some(table) = self.export_table() else {
+            return;
+        };
+        let url = self.db_url.trim().to_string();
+        let table_name = self.db_table.trim().to_string();
+        let result = Arc::clone(&self.export_result);
+        self.exporting = true;
+        self.export_dry_run = dry_run;
+        self.sheets_export = false;
+        self.export_ddl = None;
+        self.status = format!("Exporting to {}...", table_name);
+
+        thread::spawn(move || {
+            let outcome = database::export(&url, &table_name, &table, dry_run)
+                .map_err(|e| format!("{:#}", e));
+            *result.lock().unwrap() = Some(outcome);
+        });
+    }
+
+    /// Signs in to Google in the browser and pushes the converted rows to a new spreadsheet
+    /// in the background
+    fn export_to_sheets(&mut self) {
+        let Some(table) = self.export_table() else {
+            return;
+        };
+        let title = match self.sheet_title.trim() {
+            "" => self.title(),
+            title => title.to_string(),
+        };
+        let settings = self.settings.clone();
+        let result = Arc::clone(&self.export_result);
+        self.exporting = true;
+        self.export_dry_run = false;
+        self.sheets_export = true;
+        self.status = "Waiting for Google sign-in in the browser...".to_string();
+
+        thread::spawn(move || {
+            let outcome = sheets::authorize(&settings)
+                .and_then(|token| sheets::export(&token, &title, &table, &settings))
+                .map(|url| format!("Exported to {}", url))
+                .map_err(|e| format!("{:#}", e));
+            *result.lock().unwrap() = Some(outcome);
+        });
+    }
+
+    /// Reads the converted rows back for an export, reporting a failure as error message
+    fn export_table(&mut self) -> Option<Table> {
+        let content = self.csv_content.as_ref()?;
+        match Table::from_csv(content, &self.csv_columns, self.csv_body_offset, &self.settings) {
+            Ok(table) => Some(table),
+            Err(e) => {
+                self.error_message = Some(format!("{:#}", e));
+                None
+            }
+        }
+    }
+
+    /// Input of a conversion of the loaded content, reporting an error when there is none
+    fn conversion_input(&mut self) -> Option<ConversionInput> {
+        let input = match (&self.source_uri, &self.json_content, &self.json_path) {
+            (Some(uri), _, _) => ConversionInput::S3(uri.clone()),
+            (_, _, Some(path)) if self.stream_input => ConversionInput::File(path.clone()),
+            (_, Some(content), path) => ConversionInput::Text(
+                content.clone(),
+                path.as_deref().map_or(InputFormat::Json, InputFormat::from_path),
+                path.as_deref().map(|path| path.display().to_string()).unwrap_or_default(),
+            ),
+            _ => {
+                self.error_message = Some("No JSON content loaded".to_string());
+                return None;
+            }
+        };
+        Some(input)
+    }
+
+    /// Reports what converting the loaded content would produce, without writing anything
+    /// The analysis runs in the background; large inputs are estimated from a sample
+    fn analyze_input(&mut self) {
+        let Some(input) = self.conversion_input() else {
+            return;
+        };
+        let settings = self.settings.clone();
+        let selected_columns = self.selected_columns.clone();
+        let result = Arc::clone(&self.analysis_result);
+        self.analyzing = true;
+        self.status = "Analyzing...".to_string();
+
+        thread::spawn(move || {
+            let outcome = conversion::analyze(&input, &settings, selected_columns)
+                .map_err(|e| format!("{:#}", e));
+            *result.lock().unwrap() = Some(outcome);
+        });
+    }
+
+    /// Reads the first records of the loaded input in the background, so the preview shows
+    /// them before the full conversion ran
+    fn sample_input(&mut self) {
+        self.discard_sample();
+        let Some(input) = self.conversion_input() else {
+            return;
+        };
+        let settings = self.settings.clone();
+        let result = Arc::clone(&self.sample_result);
+        self.sampling = true;
+        self.sampled_with = sample_fingerprint(&settings);
+
+        thread::spawn(move || {
+            let outcome = conversion::sample(&input, &settings, settings.max_preview_rows)
+                .map_err(|e| format!("{:#}", e));
+            *result.lock().unwrap() = Some(outcome);
+        });
+    }
+
+    /// Forgets the sample of the previous input, including one still being read
+    fn discard_sample(&mut self) {
+        self.sample = None;
+        self.sampling = false;
+        // A new slot, so a sampling still running cannot fill it
+        self.sample_result = Arc::new(Mutex::new(None));
+    }
+
+    /// Previews the sample with the current settings
+    fn preview_sample(&mut self) {
+        let Some(sample) = &self.sample else {
+            return;
+        };
+        self.previewed_with = checkpoint::fingerprint(&self.settings, &self.selected_columns);
+        match conversion::convert_sample(sample, &self.settings, self.selected_columns.clone()) {
+            Ok(result) => {
+                if self.all_columns.is_empty() {
+                    self.all_columns = result.columns;
+                }
+                self.preview_data = Some(result.preview_data);
+                self.raw_preview = result.raw_preview;
+                self.preview_is_sample = true;
+                self.sample_error = None;
+                self.column_stats.clear();
+            }
+            // Shown above the previous preview, which stays until the settings work again
+            Err(e) => {
+                debug!("Failed to preview the sample: {:#}", e);
+                self.sample_error = Some(format!("{:#}", e));
+            }
+        }
+    }
+
+    /// Previews the sample again when the settings or the column selection changed since
+    /// the preview was made, reading it again first when a setting of the input parsing
+    /// changed
+    pub fn refresh_preview(&mut self) {
+        if self.sampling || self.sample.is_none() {
+            return;
+        }
+        if sample_fingerprint(&self.settings) != self.sampled_with {
+            self.sample_input();
+        } else if checkpoint::fingerprint(&self.settings, &self.selected_columns) != self.previewed_with {
+            self.preview_sample();
+        }
+    }
+
+    /// Converts the loaded JSON content to CSV format
+    /// The conversion is queued as a background job to keep the UI responsive
+    pub fn convert_to_csv(&mut self, jobs: &mut JobQueue) {
+        let Some(input) = self.conversion_input() else {
+            return;
+        };
+
+        if self.is_converting() {
+            self.error_message = Some("A conversion is already running".to_string());
+            return;
+        }
+
+        self.job_id = Some(jobs.submit(
+            self.title(),
+            input,
+            self.settings.clone(),
+            self.selected_columns.clone(),
+            Arc::clone(&self.progress),
+        ));
+    }
+
+    /// Picks up the outcome of a finished conversion, if there is one
+    pub fn poll_conversion(&mut self) {
+        if let Some(outcome) = self.upload_result.lock().unwrap().take() {
+            self.uploading = false;
+            match outcome {
+                Ok(status) => {
+                    info!("{}", status);
+                    self.status = status;
+                    self.error_message = None;
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    self.status = "Error uploading file".to_string();
+                    self.error_message = Some(e);
+                }
+            }
+        }
+
+        if let Some(outcome) = self.export_result.lock().unwrap().take() {
+            self.exporting = false;
+            match outcome {
+                // A dry run returns the DDL, which is shown below the export fields
+                Ok(report) if self.export_dry_run => {
+                    self.status = "Dry run completed".to_string();
+                    self.export_ddl = Some(report);
+                    self.error_message = None;
+                }
+                Ok(status) => {
+                    info!("{}", status);
+                    self.status = status;
+                    self.error_message = None;
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    self.status = if self.sheets_export {
+                        "Error exporting to Google Sheets".to_string()
+                    } else {
+                        "Error exporting to database".to_string()
+                    };
+                    self.error_message = Some(e);
+                }
+            }
+        }
+
+        if let Some(outcome) = self.analysis_result.lock().unwrap().take() {
+            self.analyzing = false;
+            match outcome {
+                Ok(analysis) => {
+                    self.status = "Analysis completed".to_string();
+                    self.analysis = Some(analysis);
+                    self.error_message = None;
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    self.status = "Analysis failed".to_string();
+                    self.error_message = Some(e);
+                }
+            }
+        }
+
+        let sampled = self.sample_result.lock().unwrap().take();
+        if let Some(outcome) = sampled {
+            self.sampling = false;
+            match outcome {
+                Ok(sample) => {
+                    debug!(records = sample.records.len(), "Sampled {}", sample.source);
+                    self.sample = Some(sample);
+                    // A conversion that finished first shows its own output
+                    if self.preview_data.is_none() || self.preview_is_sample {
+                        self.preview_sample();
+                    }
+                }
+                Err(e) => debug!("Failed to sample the input: {}", e),
+            }
+        }
+
+        if self.job_id.is_none() {
+            return;
+        }
+
+        let mut progress = self.progress.lock().unwrap();
+        if progress.is_converting {
+            return;
+        }
+        let result = progress.result.take();
+        let (failed, status) = (progress.failed, progress.status.clone());
+        drop(progress);
+
+        self.job_id = None;
+        if let Some(result) = result {
+            self.apply_conversion_result(result);
+        } else if failed {
+            error!("{}", status);
+            self.error_message = Some(status);
+            self.status = "Conversion failed".to_string();
+        } else {
+            self.status = status;
+        }
+    }
+
+    /// Takes over the output of a finished conversion
+    fn apply_conversion_result(&mut self, result: ConversionResult) {
+        let summary = ConversionSummary::new(&result);
+        for warning in &summary.warnings {
+            warn!("{}", warning);
+        }
+        self.status = summary.status();
+        self.summary = Some(summary);
+        self.summary_open = self.settings.show_summary;
+        self.rejected_csv = result.validation.rejected_csv;
+        self.dictionary = result.dictionary;
+        self.csv_content = Some(result.csv_content);
+        // Columns first seen after the sample join the selection list
+        for column in &result.columns {
+            if !self.all_columns.contains(column) {
+                self.all_columns.push(column.clone());
+            }
+        }
+        self.csv_columns = result.columns;
+        self.csv_body_offset = result.body_offset;
+        self.preview_data = Some(result.preview_data);
+        self.raw_preview = result.raw_preview;
+        self.preview_is_sample = false;
+        self.sample_error = None;
+        self.previewed_with = checkpoint::fingerprint(&self.settings, &self.selected_columns);
+        self.column_stats.clear();
+        self.error_message = None;
+    }
+
+    /// Asks for a target path and saves the converted CSV content to it
+    ///
+    /// The dialog starts in the default output directory (or next to the input file) with a
+    /// name derived from the input file. Existing files are appended to in append mode,
+    /// renamed around when auto-rename is on, and otherwise need confirmation.
+    pub fn save_csv_file(&mut self) {
+        if self.csv_content.is_none() {
+            return;
+        }
+
+        let extension = self.settings.output_kind.extension();
+        let mut dialog = FileDialog::new().add_filter(extension.to_uppercase(), &[extension]);
+        let input_dir = self
+            .json_path
+            .as_ref()
+            .and_then(|path| path.parent())
+            .map(Path::to_path_buf);
+        if let Some(dir) = self.settings.output_dir.clone().or(input_dir) {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(stem) = self.json_path.as_ref().and_then(|path| path.file_stem()) {
+            dialog = dialog.set_file_name(format!("{}.{}", stem.to_string_lossy(), extension));
+        }
+
+        if let Some(path) = dialog.save_file() {
+            if !path.exists() || self.settings.appends_to_existing() {
+                self.write_csv_file(path);
+            } else if self.settings.auto_rename {
+                self.write_csv_file(next_free_path(&path));
+            } else {
+                self.pending_overwrite = Some(path);
+            }
+        }
+    }
+
+    /// Writes the converted CSV content to `path`, appending when append mode is enabled
+    fn write_csv_file(&mut self, path: PathBuf) {
+        let Some(content) = &self.csv_content else {
+            return;
+        };
+
+        let appending = self.settings.appends_to_existing() && path.exists();
+        let outcome = if appending {
+            append_to_csv_file(
+                &path,
+                content,
+                &self.csv_columns,
+                self.csv_body_offset,
+                &self.settings,
+            )
+        } else {
+            write_atomically(&path, content)
+        };
+
+        let outcome = outcome.and_then(|_| match &self.rejected_csv {
+            Some(rejected) => validation::save_rejected(&path, rejected).map(Some),
+            None => Ok(None),
+        });
+        let outcome = outcome.and_then(|rejected_path| match &self.dictionary {
+            Some(dictionary) => Ok((rejected_path, Some(dictionary::save(&path, dictionary)?))),
+            None => Ok((rejected_path, None)),
+        });
+
+        match outcome {
+            Ok((rejected_path, dictionary_path)) => {
+                self.status = if appending {
+                    format!("CSV rows appended to {}", path.display())
+                } else {
+                    format!("CSV file saved to {}", path.display())
+                };
+                if let Some(rejected_path) = rejected_path {
+                    self.status += &format!(", rejected rows to {}", rejected_path.display());
+                }
+                if let Some(dictionary_path) = dictionary_path {
+                    self.status += &format!(", data dictionary to {}", dictionary_path.display());
+                }
+                info!(bytes = content.len(), "{}", self.status);
+                self.csv_path = Some(path);
+                self.error_message = None;
+            }
+            Err(e) => {
+                error!("Failed to save {}: {:#}", path.display(), e);
+                self.error_message = Some(format!("Failed to save CSV file: {:#}", e));
+                self.status = "Error saving file".to_string();
+            }
+        }
+    }
+
+    /// Displays the dialogs waiting for a decision of the user
+    pub fn show_dialogs(&mut self, ctx: &egui::Context, jobs: &mut JobQueue) {
+        self.show_overwrite_dialog(ctx);
+        self.show_large_file_dialog(ctx, jobs);
+        self.show_summary_dialog(ctx);
+        self.show_find_replace(ctx);
+    }
+
+    /// Displays the summary of the latest conversion while it is open
+    fn show_summary_dialog(&mut self, ctx: &egui::Context) {
+        let Some(summary) = &self.summary else {
+            return;
+        };
+        let mut open = self.summary_open;
+        egui::Window::new("Conversion Summary")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| summary.show(ui, &mut self.settings.show_summary));
+        self.summary_open = open;
+    }
+
+    /// Displays the find and replace window and carries out its requests
+    fn show_find_replace(&mut self, ctx: &egui::Context) {
+        let unavailable = if self.csv_content.is_none() {
+            Some("Convert a file first")
+        } else if self.settings.output_kind != OutputKind::Csv {
+            Some("Find and replace works on CSV output only")
+        } else {
+            None
+        };
+        if let Some(request) = self.find_replace.show(ctx, unavailable) {
+            let message = self.find_in_output(request).unwrap_or_else(|e| format!("{:#}", e));
+            self.find_replace.message = Some(message);
+        }
+    }
+
+    /// Counts or replaces the matches of the find and replace window in the converted CSV
+    /// and returns the outcome
+    fn find_in_output(&mut self, request: FindRequest) -> anyhow::Result<String> {
+        let search = &self.find_replace;
+        let finder = Finder::new(&search.find, search.use_regex, search.match_case)?;
+        let content = self.csv_content.as_deref().unwrap_or_default();
+        match request {
+            FindRequest::Count => {
+                let count = replace::count_in_csv(content, self.csv_body_offset, &finder, &self.settings)?;
+                Ok(format!("{} matches", count))
+            }
+            FindRequest::ReplaceAll => {
+                let replacement = search.replacement.clone();
+                let (replaced, count) = replace::replace_in_csv(
+                    content,
+                    self.csv_body_offset,
+                    &finder,
+                    &replacement,
+                    &self.settings,
+                )?;
+                self.csv_content = Some(replaced);
+                // The preview starts with the header row when headers are enabled
+                let skip = usize::from(self.settings.include_headers);
+                for row in self.preview_data.iter_mut().flatten().skip(skip) {
+                    for cell in row {
+                        if let Some((replaced, _)) = finder.replace(cell, &replacement) {
+                            *cell = replaced;
+                        }
+                    }
+                }
+                self.column_stats.clear();
+                Ok(format!("{} replacements made", count))
+            }
+        }
+    }
+
+    /// Displays the warning shown before opening a file above the large file threshold
+    fn show_large_file_dialog(&mut self, ctx: &egui::Context, jobs: &mut JobQueue) {
+        let Some((path, size)) = self.pending_large_file.clone() else {
+            return;
+        };
+
+        egui::Window::new("Large File")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} is {}. Loading it into memory may make the application unresponsive.",
+                    path.display(),
+                    format_bytes(size as usize)
+                ));
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Stream During Conversion").clicked() {
+                        self.pending_large_file = None;
+                        self.open_streamed(path.clone(), size, jobs);
+                    }
+                    if ui.button("Load Anyway").clicked() {
+                        self.pending_large_file = None;
+                        self.read_json_file(path.clone(), jobs);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.pending_large_file = None;
+                    }
+                });
+            });
+    }
+
+    /// Displays the overwrite confirmation dialog while a save target already exists
+    fn show_overwrite_dialog(&mut self, ctx: &egui::Context) {
+        let Some(path) = self.pending_overwrite.clone() else {
+            return;
+        };
+
+        egui::Window::new("Overwrite File?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("{} already exists.", path.display()));
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Overwrite").clicked() {
+                        self.pending_overwrite = None;
+                        self.write_csv_file(path.clone());
+                    }
+                    if ui.button("Save as New Name").clicked() {
+                        self.pending_overwrite = None;
+                        self.write_csv_file(next_free_path(&path));
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.pending_overwrite = None;
+                        self.status = "Save cancelled".to_string();
+                    }
+                });
+            });
+    }
+
+    /// Displays the conversion controls, progress, preview and status of this document
+    ///
+    /// Returns whether the user asked to open a file.
+    pub fn show(&mut self, ui: &mut egui::Ui, jobs: &mut JobQueue) -> bool {
+        let mut open_requested = false;
+
+        // File selection
+        if ui.button("Select JSON File").clicked() {
+            open_requested = true;
+        }
+
+        if let Some(path) = &self.json_path {
+            ui.label(format!("Selected JSON file: {}", path.display()));
+        }
+
+        // S3 input
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.s3_input).hint_text("s3://bucket/key.json"));
+            if ui.button("Load from S3").clicked() {
+                self.open_s3(self.s3_input.trim().to_string(), jobs);
+            }
+        });
+        if let Some(uri) = &self.source_uri {
+            ui.label(format!("Selected S3 object: {}", uri));
+        }
+
+        ui.add_space(10.0);
+
+        // Conversion button and progress
+        let progress = self.progress.lock().unwrap();
+        let is_converting = progress.is_converting;
+        let progress_value = progress.progress;
+        let status = progress.status.clone();
+        let throughput = progress.started_at.map(|_| progress.throughput_summary());
+        drop(progress);
+
+        if !is_converting {
+            ui.horizontal(|ui| {
+                if ui.button("Convert to CSV").clicked() {
+                    self.convert_to_csv(jobs);
+                }
+                if ui.add_enabled(!self.analyzing, egui::Button::new("Analyze Only")).clicked() {
+                    self.analyze_input();
+                }
+            });
+        }
+        if let Some(analysis) = &self.analysis {
+            show_analysis(ui, analysis);
+        }
+
+        // Progress bar
+        if is_converting {
+            ui.add_space(10.0);
+            let progress_bar = egui::ProgressBar::new(progress_value)
+                .show_percentage()
+                .animate(true);
+            ui.add(progress_bar);
+            ui.horizontal(|ui| {
+                ui.label(&status);
+                if let Some(id) = self.job_id {
+                    if ui.button("Cancel").clicked() {
+                        jobs.cancel(id);
+                    }
+                }
+            });
+            if let Some(throughput) = throughput {
+                ui.small(throughput);
+            }
+        }
+
+        // Preview controls
+        if let Some(_content) = &self.csv_content {
+            ui.add_space(10.0);
+            ui.horizontal(|ui| {
+                if ui.button("Save CSV File").clicked() {
+                    self.save_csv_file();
+                }
+                if self.summary.is_some() && ui.button("Summary").clicked() {
+                    self.summary_open = true;
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.s3_output).hint_text("s3://bucket/key.csv"));
+                if ui.add_enabled(!self.uploading, egui::Button::new("Upload to S3")).clicked() {
+                    self.upload_to_s3();
+                }
+            });
+
+            egui::CollapsingHeader::new("Database Export")
+                .id_source("database_export")
+                .show(ui, |ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.db_url)
+                            .hint_text("postgres://user:${env:DB_PASSWORD}@host/database"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Table:");
+                        ui.text_edit_singleline(&mut self.db_table);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(!self.exporting, egui::Button::new("Dry Run")).clicked() {
+                            self.export_to_database(true);
+                        }
+                        if ui.add_enabled(!self.exporting, egui::Button::new("Export")).clicked() {
+                            self.export_to_database(false);
+                        }
+                    });
+                    if let Some(ddl) = &self.export_ddl {
+                        ui.add(
+                            egui::TextEdit::multiline(&mut ddl.as_str())
+                                .font(egui::TextStyle::Monospace)
+                                .desired_width(f32::INFINITY),
+                        );
+                    }
+                });
+
+            egui::CollapsingHeader::new("Google Sheets")
+                .id_source("sheets_export")
+                .show(ui, |ui| {
+                    let default_title = self.title();
+                    ui.horizontal(|ui| {
+                        ui.label("Title:");
+                        ui.add(egui::TextEdit::singleline(&mut self.sheet_title)
+                            .hint_text(default_title));
+                    });
+                    let button = egui::Button::new("Export to Google Sheets");
+                    if ui.add_enabled(!self.exporting, button).clicked() {
+                        self.export_to_sheets();
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.show_preview, "Show Preview");
+                if self.show_preview {
+                    let columns = self.preview_data.iter().flatten().next().map_or(0, Vec::len);
+                    let mut all_raw = columns > 0 && self.raw_columns.len() >= columns;
+                    if ui.checkbox(&mut all_raw, "Raw JSON")
+                        .on_hover_text("Show the input values before the script ran instead of \
+                            the CSV cells; right-click a column header to switch one column")
+                        .changed()
+                    {
+                        self.raw_columns = if all_raw { (0..columns).collect() } else { HashSet::new() };
+                    }
+                    ui.text_edit_singleline(&mut self.search_query);
+                    if ui.button("🔍").clicked() {
+                        // TODO: Implement search functionality
+                    }
+                }
+            });
+        }
+
+        // Error message
+        if let Some(error) = &self.error_message {
+            ui.add_space(10.0);
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.add_space(20.0);
+        ui.label(format!("Status: {}", self.status));
+
+        open_requested
+    }
+
+    /// Whether the preview is turned on and there is data to show in it
+    pub fn has_preview(&self) -> bool {
+        self.show_preview && self.preview_data.is_some()
+    }
+
+    /// Displays the preview grid, filling the panel it is shown in
+    ///
+    /// Hovering a header cell shows the statistics of its column, and right-clicking it
+    /// switches the column between CSV cells and raw input values. Raw values that were
+    /// changed on the way to the CSV cell are highlighted.
+    pub fn show_preview(&mut self, ui: &mut egui::Ui) {
+        if self.preview_data.is_none() {
+            return;
+        }
+        self.save_snapshot(ui.ctx());
+        ui.horizontal(|ui| {
+            if ui.small_button("Export as HTML...")
+                .on_hover_text("Save the preview rows as an HTML table to paste into a ticket or email")
+                .clicked()
+            {
+                self.export_preview_html();
+            }
+            if ui.small_button("Export as PNG...")
+                .on_hover_text("Save an image of the preview as shown")
+                .clicked()
+            {
+                self.export_preview_png(ui.ctx());
+            }
+        });
+        let Some(preview_data) = &self.preview_data else {
+            return;
+        };
+        if let Some(error) = &self.sample_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        } else if self.preview_is_sample {
+            let records = self.sample.as_ref().map_or(0, |sample| sample.records.len());
+            ui.weak(format!(
+                "Sample of the first {} records with the current settings; convert to update the output",
+                records
+            ));
+        }
+        let has_header = self.settings.include_headers;
+        let (raw_preview, raw_columns) = (&self.raw_preview, &mut self.raw_columns);
+        let column_stats = &mut self.column_stats;
+        let scroll = egui::ScrollArea::both()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                egui::Grid::new("preview_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (row_index, row) in preview_data.iter().enumerate() {
+                            let data_row = row_index.checked_sub(usize::from(has_header));
+                            for (index, cell) in row.iter().enumerate() {
+                                if let Some(data_row) = data_row {
+                                    if raw_columns.contains(&index) {
+                                        let raw = raw_preview.get(data_row).and_then(|row| row.get(index));
+                                        show_raw_cell(ui, raw.and_then(Option::as_ref), cell);
+                                    } else {
+                                        ui.label(cell);
+                                    }
+                                    continue;
+                                }
+                                let raw = raw_columns.contains(&index);
+                                let title = if raw { format!("{} (raw)", cell) } else { cell.clone() };
+                                let header = ui.label(egui::RichText::new(title).strong());
+                                header.context_menu(|ui| {
+                                    let label = if raw { "Show CSV Values" } else { "Show Raw JSON" };
+                                    if ui.button(label).clicked() {
+                                        if raw {
+                                            raw_columns.remove(&index);
+                                        } else {
+                                            raw_columns.insert(index);
+                                        }
+                                        ui.close_menu();
+                                    }
+                                });
+                                header.on_hover_ui(|ui| {
+                                    let stats = column_stats.entry(index).or_insert_with(|| {
+                                        ColumnStats::compute(
+                                            preview_data[1..]
+                                                .iter()
+                                                .map(|row| row.get(index).map_or("", String::as_str)),
+                                        )
+                                    });
+                                    show_column_stats(ui, stats);
+                                });
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        self.preview_rect = scroll.inner_rect;
+    }
+
+    /// Saves the preview rows as an HTML table to a file picked by the user
+    fn export_preview_html(&mut self) {
+        let Some(rows) = &self.preview_data else {
+            return;
+        };
+        let Some(path) = FileDialog::new()
+            .add_filter("HTML", &["html"])
+            .set_file_name("preview.html")
+            .save_file()
+        else {
+            return;
+        };
+        let html = snapshot::preview_html(rows, self.settings.include_headers);
+        match write_atomically(&path, html.as_bytes()) {
+            Ok(()) => self.status = format!("Preview saved to {}", path.display()),
+            Err(e) => self.error_message = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Asks for a screenshot, which `save_snapshot` saves to a PNG file picked by the user
+    fn export_preview_png(&mut self, ctx: &egui::Context) {
+        let Some(path) = FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .set_file_name("preview.png")
+            .save_file()
+        else {
+            return;
+        };
+        self.snapshot_path = Some(path);
+        ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot);
+        ctx.request_repaint();
+    }
+
+    /// Saves the preview area of a screenshot asked for by `export_preview_png`, once it
+    /// arrived
+    fn save_snapshot(&mut self, ctx: &egui::Context) {
+        if self.snapshot_path.is_none() {
+            return;
+        }
+        let screenshot = ctx.input(|input| {
+            input.raw.events.iter().find_map(|event| match event {
+                egui::Event::Screenshot { image, .. } => Some(Arc::clone(image)),
+                _ => None,
+            })
+        });
+        let (Some(image), Some(path)) = (screenshot, self.snapshot_path.take()) else {
+            return;
+        };
+        let region = image.region(&self.preview_rect, Some(ctx.pixels_per_point()));
+        match snapshot::encode_png(&region).and_then(|png| write_atomically(&path, &png)) {
+            Ok(()) => self.status = format!("Preview saved to {}", path.display()),
+            Err(e) => self.error_message = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Displays the fields of the Avro schema for editing
+    ///
+    /// Without edited fields, the schema is generated from all converted values.
+    fn show_avro_schema_editor(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let can_infer = !self.csv_columns.is_empty();
+            if ui.add_enabled(can_infer, egui::Button::new("Edit Schema"))
+                .on_hover_text("Start from the columns of the last conversion, typed by the preview rows")
+                .clicked()
+            {
+                self.avro_fields_from_preview();
+            }
+            if !self.settings.avro_fields.is_empty() && ui.button("Generate Automatically").clicked() {
+                self.settings.avro_fields.clear();
+            }
+        });
+
+        if self.settings.avro_fields.is_empty() {
+            ui.label("The schema is generated from the column names and types");
+            return;
+        }
+        egui::Grid::new("avro_fields").striped(true).show(ui, |ui| {
+            for (index, field) in self.settings.avro_fields.iter_mut().enumerate() {
+                ui.label(self.csv_columns.get(index).map_or("", String::as_str));
+                ui.text_edit_singleline(&mut field.name);
+                egui::ComboBox::from_id_source(("avro_type", index))
+                    .selected_text(field.column_type.avro_name())
+                    .show_ui(ui, |ui| {
+                        for column_type in ColumnType::ALL {
+                            ui.selectable_value(&mut field.column_type, column_type, column_type.avro_name());
+                        }
+                    });
+                ui.end_row();
+            }
+        });
+    }
+
+    /// Displays the widths of the fixed-width columns for editing
+    ///
+    /// Columns without a width are as wide as their longest value.
+    fn show_fixed_width_editor(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let can_measure = self.preview_data.is_some();
+            if ui.add_enabled(can_measure, egui::Button::new("Measure from Preview"))
+                .on_hover_text("List the columns of the preview with the length of their longest value")
+                .clicked()
+            {
+                self.fixed_widths_from_preview();
+            }
+            if !self.settings.fixed_widths.is_empty() && ui.button("Measure Automatically").clicked() {
+                self.settings.fixed_widths.clear();
+            }
+        });
+
+        if self.settings.fixed_widths.is_empty() {
+            ui.label("Every column is as wide as its longest value");
+            return;
+        }
+        let mut removed = None;
+        egui::Grid::new("fixed_widths").striped(true).show(ui, |ui| {
+            for (index, width) in self.settings.fixed_widths.iter_mut().enumerate() {
+                ui.label(&width.column);
+                let mut measured = width.width.is_none();
+                if ui.checkbox(&mut measured, "Auto")
+                    .on_hover_text("As wide as the longest value")
+                    .changed()
+                {
+                    width.width = if measured { None } else { Some(width.column.chars().count().max(1)) };
+                }
+                if let Some(characters) = &mut width.width {
+                    ui.add(egui::DragValue::new(characters).clamp_range(1..=1000).suffix(" chars"));
+                } else {
+                    ui.label("");
+                }
+                ui.checkbox(&mut width.align_right, "Right")
+                    .on_hover_text("Pad on the left, as numbers usually are");
+                if ui.small_button("×").on_hover_text("Remove").clicked() {
+                    removed = Some(index);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(index) = removed {
+            self.settings.fixed_widths.remove(index);
+        }
+    }
+
+    /// Lists the columns of the preview with the length of their longest value, or name
+    /// when headers are on, as fixed widths
+    fn fixed_widths_from_preview(&mut self) {
+        let rows = self.preview_data.as_deref().unwrap_or_default();
+        let columns: Vec<String> = if self.settings.include_headers {
+            rows.first().cloned().unwrap_or_default()
+        } else {
+            self.csv_columns.clone()
+        };
+        self.settings.fixed_widths = columns
+            .into_iter()
+            .enumerate()
+            .map(|(index, column)| {
+                let longest = rows
+                    .iter()
+                    .map(|row| row.get(index).map_or(0, |cell| cell.chars().count()))
+                    .max()
+                    .unwrap_or_default();
+                ColumnWidth {
+                    column,
+                    width: Some(longest.max(1)),
+                    align_right: false,
+                }
+            })
+            .collect();
+    }
+
+    /// Fills the Avro schema fields with the columns of the last conversion
+    fn avro_fields_from_preview(&mut self) {
+        // The preview starts with the header row when headers are enabled
+        let skip = usize::from(self.settings.include_headers);
+        let rows = self.preview_data.as_deref().unwrap_or_default();
+        self.settings.avro_fields = self
+            .csv_columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| {
+                let values = rows
+                    .iter()
+                    .skip(skip)
+                    .map(|row| row.get(index).map_or("", String::as_str));
+                AvroField::for_column(column, ColumnType::infer(values))
+            })
+            .collect();
+    }
+
+    /// Displays the loaded JSON Schema and the validation rules for editing
+    fn show_validation_rules(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("JSON Schema:");
+            match &self.settings.json_schema {
+                Some(schema) => {
+                    ui.label(&schema.name).on_hover_text(format!(
+                        "{} columns and {} rules; the columns are written unless others are selected",
+                        schema.columns.len(),
+                        schema.rules.len()
+                    ));
+                }
+                None => {
+                    ui.weak("none");
+                }
+            }
+            let load = ui
+                .button("Load...")
+                .on_hover_text("Take the columns, their types and constraints from a JSON Schema of the records");
+            if load.clicked() {
+                self.load_json_schema();
+            }
+            if self.settings.json_schema.is_some() && ui.button("Remove").clicked() {
+                self.settings.json_schema = None;
+            }
+        });
+        ui.separator();
+
+        let mut remove = None;
+        for (index, rule) in self.settings.validation_rules.iter_mut().enumerate() {
+            ui.push_id(("validation_rule", index), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Column:");
+                    ui.add(egui::TextEdit::singleline(&mut rule.column).desired_width(120.0));
+                    if ui.small_button("×").on_hover_text("Remove rule").clicked() {
+                        remove = Some(index);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut rule.required, "Required")
+                        .on_hover_text("Fail the conversion when the column is not in the output");
+                    ui.checkbox(&mut rule.non_empty, "Not Empty");
+                    egui::ComboBox::from_id_source("column_type")
+                        .selected_text(rule.column_type.map_or("Any Type", ColumnType::label))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut rule.column_type, None, "Any Type");
+                            // Any cell is text, so only the other types are checked
+                            for column_type in ColumnType::ALL.into_iter().filter(|t| *t != ColumnType::Text) {
+                                ui.selectable_value(&mut rule.column_type, Some(column_type), column_type.label());
+                            }
+                        });
+                });
+                ui.horizontal(|ui| {
+                    for (label, bound) in [("Min", &mut rule.min), ("Max", &mut rule.max)] {
+                        let mut enabled = bound.is_some();
+                        if ui.checkbox(&mut enabled, label).changed() {
+                            *bound = enabled.then_some(0.0);
+                        }
+                        if let Some(value) = bound {
+                            ui.add(egui::DragValue::new(value));
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Pattern:");
+                    ui.add(egui::TextEdit::singleline(&mut rule.pattern)
+                        .hint_text("regular expression")
+                        .desired_width(160.0));
+                });
+                ui.separator();
+            });
+        }
+        if let Some(index) = remove {
+            self.settings.validation_rules.remove(index);
+        }
+        if ui.button("Add Rule").clicked() {
+            self.settings.validation_rules.push(ColumnRule::default());
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Invalid Rows:");
+            egui::ComboBox::from_id_source("invalid_rows")
+                .selected_text(match self.settings.invalid_rows {
+                    InvalidRows::Report => "Report",
+                    InvalidRows::Reject => "Reject",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.settings.invalid_rows, InvalidRows::Report, "Report")
+                        .on_hover_text("List them in the status, but write them");
+                    ui.selectable_value(&mut self.settings.invalid_rows, InvalidRows::Reject, "Reject")
+                        .on_hover_text("Leave them out and save them to rejected.csv next to the output");
+                });
+        });
+    }
+
+    /// Displays the default values of columns for records lacking them
+    fn show_column_defaults(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+        egui::Grid::new("column_defaults").show(ui, |ui| {
+            for (index, default) in self.settings.column_defaults.iter_mut().enumerate() {
+                ui.add(egui::TextEdit::singleline(&mut default.column)
+                    .hint_text("column")
+                    .desired_width(110.0));
+                ui.add(egui::TextEdit::singleline(&mut default.value)
+                    .hint_text("default")
+                    .desired_width(110.0));
+                if ui.small_button("×").on_hover_text("Remove default").clicked() {
+                    remove = Some(index);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(index) = remove {
+            self.settings.column_defaults.remove(index);
+        }
+        if ui.button("Add Default").clicked() {
+            self.settings.column_defaults.push(ColumnDefault::default());
+        }
+    }
+
+    /// Displays the dialect of CSV input, sniffed or chosen
+    fn show_csv_input(&mut self, ui: &mut egui::Ui) {
+        let dialect = &mut self.settings.csv_input;
+        match &self.detected_dialect {
+            Some(detected) => ui.weak(format!("Detected: {}", detected.describe())),
+            None => ui.weak("Auto settings are sniffed from the file when it is converted"),
+        };
+        egui::Grid::new("csv_input").show(ui, |ui| {
+            ui.label("Delimiter:");
+            let text = |choice: Option<char>| choice.map_or("Auto".to_string(), |c| dialect::name(c as u8));
+            egui::ComboBox::from_id_source("csv_input_delimiter")
+                .selected_text(text(dialect.delimiter))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut dialect.delimiter, None, "Auto");
+                    for delimiter in dialect::DELIMITERS {
+                        let choice = Some(delimiter as char);
+                        ui.selectable_value(&mut dialect.delimiter, choice, text(choice));
+                    }
+                });
+            ui.end_row();
+            ui.label("Quote:");
+            egui::ComboBox::from_id_source("csv_input_quote")
+                .selected_text(text(dialect.quote))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut dialect.quote, None, "Auto");
+                    for quote in dialect::QUOTES {
+                        let choice = Some(quote as char);
+                        ui.selectable_value(&mut dialect.quote, choice, text(choice));
+                    }
+                });
+            ui.end_row();
+            ui.label("Header Row:");
+            egui::ComboBox::from_id_source("csv_input_header")
+                .selected_text(match dialect.has_header {
+                    None => "Auto",
+                    Some(true) => "Yes",
+                    Some(false) => "No",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut dialect.has_header, None, "Auto");
+                    ui.selectable_value(&mut dialect.has_header, Some(true), "Yes");
+                    ui.selectable_value(&mut dialect.has_header, Some(false), "No")
+                        .on_hover_text("Name the columns col_0, col_1, ...");
+                });
+            ui.end_row();
+            ui.label("Ragged Rows:").on_hover_text("Lines with more or fewer fields than there are columns");
+            let ragged_rows = &mut self.settings.ragged_rows;
+            egui::ComboBox::from_id_source("ragged_rows")
+                .selected_text(match ragged_rows {
+                    RaggedRows::Fix => "Pad or cut",
+                    RaggedRows::Skip => "Skip",
+                    RaggedRows::Fail => "Fail",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(ragged_rows, RaggedRows::Fix, "Pad or cut")
+                        .on_hover_text("Fill missing fields with empty cells and drop extra ones");
+                    ui.selectable_value(ragged_rows, RaggedRows::Skip, "Skip");
+                    ui.selectable_value(ragged_rows, RaggedRows::Fail, "Fail");
+                });
+            ui.end_row();
+        });
+    }
+
+    /// Displays the value mappings for editing
+    fn show_value_mappings(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+        for (index, mapping) in self.settings.value_mappings.iter_mut().enumerate() {
+            ui.push_id(("value_mapping", index), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Column:");
+                    ui.add(egui::TextEdit::singleline(&mut mapping.column).desired_width(120.0));
+                    if ui.small_button("×").on_hover_text("Remove mapping").clicked() {
+                        remove = Some(index);
+                    }
+                });
+                let mut remove_value = None;
+                egui::Grid::new("mapped_values").show(ui, |ui| {
+                    for (index, value) in mapping.values.iter_mut().enumerate() {
+                        ui.add(egui::TextEdit::singleline(&mut value.from)
+                            .hint_text("1")
+                            .desired_width(90.0));
+                        ui.label("→");
+                        ui.add(egui::TextEdit::singleline(&mut value.to)
+                            .hint_text("active")
+                            .desired_width(90.0));
+                        if ui.small_button("×").on_hover_text("Remove value").clicked() {
+                            remove_value = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(index) = remove_value {
+                    mapping.values.remove(index);
+                }
+                if ui.button("Add Value").clicked() {
+                    mapping.values.push(MappedValue::default());
+                }
+                ui.separator();
+            });
+        }
+        if let Some(index) = remove {
+            self.settings.value_mappings.remove(index);
+        }
+        if ui.button("Add Mapping").clicked() {
+            self.settings.value_mappings.push(ValueMapping::default());
+        }
+    }
+
+    /// Displays the anonymized columns and how their cells are hidden
+    fn show_anonymization(&mut self, ui: &mut egui::Ui) {
+        let settings = &mut self.settings;
+        let mut remove = None;
+        egui::Grid::new("anonymized_columns").show(ui, |ui| {
+            for (index, rule) in settings.anonymized_columns.iter_mut().enumerate() {
+                ui.add(egui::TextEdit::singleline(&mut rule.column)
+                    .hint_text("email")
+                    .desired_width(110.0));
+                egui::ComboBox::from_id_source(("anonymization", index))
+                    .selected_text(match rule.method {
+                        Anonymization::Hash => "SHA-256",
+                        Anonymization::Mask => "Mask",
+                        Anonymization::Drop => "Drop",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut rule.method, Anonymization::Hash, "SHA-256")
+                            .on_hover_text("Replace cells with their hash; equal values keep equal hashes");
+                        ui.selectable_value(&mut rule.method, Anonymization::Mask, "Mask")
+                            .on_hover_text("Replace cells with the mask text");
+                        ui.selectable_value(&mut rule.method, Anonymization::Drop, "Drop")
+                            .on_hover_text("Leave the column out, even when selected");
+                    });
+                if ui.small_button("×").on_hover_text("Remove column").clicked() {
+                    remove = Some(index);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(index) = remove {
+            settings.anonymized_columns.remove(index);
+        }
+        if ui.button("Add Column").clicked() {
+            settings.anonymized_columns.push(ColumnAnonymization::default());
+        }
+        egui::Grid::new("anonymization_options").show(ui, |ui| {
+            ui.label("Mask:");
+            ui.add(egui::TextEdit::singleline(&mut settings.anonymization_mask).desired_width(110.0));
+            ui.end_row();
+            ui.label("Salt:");
+            ui.add(egui::TextEdit::singleline(&mut settings.anonymization_salt)
+                .password(true)
+                .desired_width(110.0))
+                .on_hover_text("Hashed along with every cell, so hashes cannot be looked up in a table of common values");
+            ui.end_row();
+        });
+    }
+
+    /// Displays the settings of this document
+    pub fn show_settings_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Load Preset...")
+                .on_hover_text("Apply the settings and column selection of a preset file")
+                .clicked()
+            {
+                self.load_preset();
+            }
+            if ui.button("Save Preset...")
+                .on_hover_text("Save the settings and column selection to share them")
+                .clicked()
+            {
+                self.save_preset();
+            }
+        });
+
+        section("Input").show(ui, |ui| {
+            ui.checkbox(&mut self.settings.convert_on_load, "Convert on Load")
+                .on_hover_text("Start the conversion as soon as a file is opened");
+            ui.checkbox(&mut self.settings.json5_fallback, "Accept JSON5")
+                .on_hover_text("Parse input with comments, trailing commas or unquoted keys as JSON5 \
+                    when it is not strict JSON (not available for streamed large files)");
+            ui.horizontal(|ui| {
+                ui.label("Duplicate Keys:");
+                egui::ComboBox::from_id_source("duplicate_keys")
+                    .selected_text(match self.settings.duplicate_keys {
+                        DuplicateKeys::Off => "Don't check",
+                        DuplicateKeys::KeepFirst => "Keep first",
+                        DuplicateKeys::KeepLast => "Keep last",
+                        DuplicateKeys::Suffix => "Keep all as key_2, ...",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.settings.duplicate_keys, DuplicateKeys::Off, "Don't check");
+                        ui.selectable_value(&mut self.settings.duplicate_keys, DuplicateKeys::KeepFirst, "Keep first");
+                        ui.selectable_value(&mut self.settings.duplicate_keys, DuplicateKeys::KeepLast, "Keep last");
+                        ui.selectable_value(&mut self.settings.duplicate_keys, DuplicateKeys::Suffix, "Keep all as key_2, ...");
+                    });
+            })
+            .response
+            .on_hover_text("Report JSON objects that repeat a key, and choose which value to keep");
+            ui.horizontal(|ui| {
+                ui.label("Records At:");
+                ui.add(egui::TextEdit::singleline(&mut self.settings.records_path)
+                    .hint_text("whole input")
+                    .desired_width(110.0));
+            })
+            .response
+            .on_hover_text("JSON Pointer of the array holding the records, like /data/items; \
+                the Wizard lists the arrays of the loaded file");
+            ui.horizontal(|ui| {
+                ui.label("Key Column:");
+                ui.add(egui::TextEdit::singleline(&mut self.settings.key_column)
+                    .hint_text("none")
+                    .desired_width(110.0));
+            })
+            .response
+            .on_hover_text("Column receiving the keys of input that maps keys to records, like \
+                {\"user1\": {...}, \"user2\": {...}}; leave it empty to read such input as one record");
+            ui.horizontal(|ui| {
+                ui.label("GeoJSON Geometry:");
+                let geometry_columns = &mut self.settings.geometry_columns;
+                egui::ComboBox::from_id_source("geometry_columns")
+                    .selected_text(match geometry_columns {
+                        GeometryColumns::Wkt => "WKT",
+                        GeometryColumns::LonLat => "Longitude/Latitude",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(geometry_columns, GeometryColumns::Wkt, "WKT")
+                            .on_hover_text("Well-Known Text in a geometry column, e.g. POINT (30 10)");
+                        ui.selectable_value(geometry_columns, GeometryColumns::LonLat, "Longitude/Latitude")
+                            .on_hover_text("longitude and latitude columns; the center of lines and polygons");
+                    });
+            });
+            ui.checkbox(&mut self.settings.two_pass, "Columns from All Records")
+                .on_hover_text("Read the input twice: first to collect the keys of every record, \
+                    then to write the rows, instead of taking the columns from the first record");
+        });
+
+        section("Output").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Output Format:");
+                egui::ComboBox::from_id_source("output_kind")
+                    .selected_text(match self.settings.output_kind {
+                        OutputKind::Csv => "CSV",
+                        OutputKind::Template => "Template",
+                        OutputKind::Arrow => "Arrow (Feather)",
+                        OutputKind::Avro => "Avro",
+                        OutputKind::Ods => "OpenDocument (ODS)",
+                        OutputKind::FixedWidth => "Fixed Width",
+                        OutputKind::Xlsx => "Excel (XLSX)",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.settings.output_kind, OutputKind::Csv, "CSV");
+                        ui.selectable_value(&mut self.settings.output_kind, OutputKind::Template, "Template");
+                        ui.selectable_value(&mut self.settings.output_kind, OutputKind::Arrow, "Arrow (Feather)")
+                            .on_hover_text("Typed columns for pandas, polars or R; the preview still shows text");
+                        ui.selectable_value(&mut self.settings.output_kind, OutputKind::Avro, "Avro");
+                        ui.selectable_value(&mut self.settings.output_kind, OutputKind::Ods, "OpenDocument (ODS)");
+                        ui.selectable_value(&mut self.settings.output_kind, OutputKind::FixedWidth, "Fixed Width")
+                            .on_hover_text("Columns padded to fixed widths, without delimiters or quotes");
+                        ui.selectable_value(&mut self.settings.output_kind, OutputKind::Xlsx, "Excel (XLSX)");
+                    });
+            });
+            if self.settings.output_kind == OutputKind::Template {
+                ui.label("Handlebars templates; columns are variables, e.g. {{name}}, {{sql name}}, {{pad name 10}}");
+                for (label, template, hint) in [
+                    ("Header", &mut self.settings.template_header, "BEGIN;"),
+                    ("Row", &mut self.settings.template_row, "INSERT INTO people VALUES ('{{sql name}}', {{age}});"),
+                    ("Footer", &mut self.settings.template_footer, "COMMIT; -- {{row_count}} rows"),
+                ] {
+                    ui.label(label);
+                    ui.add(egui::TextEdit::multiline(template)
+                        .code_editor()
+                        .desired_rows(2)
+                        .hint_text(hint));
+                }
+            }
+            if self.settings.output_kind == OutputKind::Avro {
+                self.show_avro_schema_editor(ui);
+            }
+            if self.settings.output_kind == OutputKind::FixedWidth {
+                self.show_fixed_width_editor(ui);
+            }
+            if self.settings.output_kind == OutputKind::Xlsx {
+                ui.horizontal(|ui| {
+                    ui.label("Sheet per Value of:");
+                    let selected = match self.settings.sheet_column.as_str() {
+                        "" => "(single sheet)",
+                        column => column,
+                    };
+                    egui::ComboBox::from_id_source("sheet_column")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.settings.sheet_column, String::new(), "(single sheet)");
+                            for column in &self.all_columns {
+                                ui.selectable_value(&mut self.settings.sheet_column, column.clone(), column);
+                            }
+                        });
+                })
+                .response
+                .on_hover_text("Write the rows of every value of this column to a sheet of their own, named after the value");
+            }
+            ui.horizontal(|ui| {
+                ui.label("Data Dictionary:");
+                egui::ComboBox::from_id_source("data_dictionary")
+                    .selected_text(match self.settings.data_dictionary {
+                        DictionaryFormat::Off => "Off",
+                        DictionaryFormat::Csv => "CSV",
+                        DictionaryFormat::Markdown => "Markdown",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.settings.data_dictionary, DictionaryFormat::Off, "Off");
+                        ui.selectable_value(&mut self.settings.data_dictionary, DictionaryFormat::Csv, "CSV");
+                        ui.selectable_value(&mut self.settings.data_dictionary, DictionaryFormat::Markdown, "Markdown");
+                    });
+            })
+            .response
+            .on_hover_text("Save a file next to the output describing every column: its source path, type, null rate and transforms");
+        });
+
+        section("CSV Settings").show(ui, |ui| {
+            // Target locale, setting the five conventions below at once
+            ui.horizontal(|ui| {
+                ui.label("Target:");
+                let current = TargetLocale::of(&self.settings);
+                egui::ComboBox::from_id_source("target_locale")
+                    .selected_text(current.map_or("Custom", TargetLocale::name))
+                    .show_ui(ui, |ui| {
+                        for locale in TargetLocale::ALL {
+                            if ui.selectable_label(current == Some(locale), locale.name()).clicked() {
+                                locale.apply(&mut self.settings);
+                            }
+                        }
+                    });
+            })
+            .response
+            .on_hover_text("Delimiter, decimal separator, date format, encoding and line endings the spreadsheet application of a region expects");
+
+            // Delimiter selection
+            ui.horizontal(|ui| {
+                ui.label("Delimiter:");
+                egui::ComboBox::from_label("")
+                    .selected_text(&self.settings.delimiter)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.settings.delimiter, ",".to_string(), "Comma (,)");
+                        ui.selectable_value(&mut self.settings.delimiter, ";".to_string(), "Semicolon (;)");
+                        ui.selectable_value(&mut self.settings.delimiter, "\t".to_string(), "Tab");
+                    });
+            });
+            ui.checkbox(&mut self.settings.decimal_comma, "Decimal Comma")
+                .on_hover_text("Write JSON numbers as 1,5 instead of 1.5; text is left as it is");
+            ui.horizontal(|ui| {
+                ui.label("Dates:");
+                let format = &mut self.settings.date_format;
+                egui::ComboBox::from_id_source("date_format")
+                    .selected_text(match format {
+                        DateFormat::Iso => "2024-03-05 (ISO)",
+                        DateFormat::MonthDayYear => "03/05/2024",
+                        DateFormat::DayMonthYear => "05/03/2024",
+                        DateFormat::DayMonthYearDots => "05.03.2024",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(format, DateFormat::Iso, "2024-03-05 (ISO)");
+                        ui.selectable_value(format, DateFormat::MonthDayYear, "03/05/2024");
+                        ui.selectable_value(format, DateFormat::DayMonthYear, "05/03/2024");
+                        ui.selectable_value(format, DateFormat::DayMonthYearDots, "05.03.2024");
+                    });
+            });
+            ui.horizontal(|ui| {
+                ui.label("Encoding:");
+                let encoding = &mut self.settings.encoding;
+                egui::ComboBox::from_id_source("encoding")
+                    .selected_text(match encoding {
+                        TextEncoding::Utf8 => "UTF-8",
+                        TextEncoding::Utf8Bom => "UTF-8 with BOM",
+                        TextEncoding::Windows1252 => "Windows-1252",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(encoding, TextEncoding::Utf8, "UTF-8");
+                        ui.selectable_value(encoding, TextEncoding::Utf8Bom, "UTF-8 with BOM");
+                        ui.selectable_value(encoding, TextEncoding::Windows1252, "Windows-1252")
+                            .on_hover_text("Characters Windows-1252 lacks are written as ?");
+                    });
+            });
+            ui.horizontal(|ui| {
+                ui.label("Line Endings:");
+                let endings = &mut self.settings.line_endings;
+                egui::ComboBox::from_id_source("line_endings")
+                    .selected_text(match endings {
+                        LineEndings::Lf => "LF (Linux, macOS)",
+                        LineEndings::CrLf => "CRLF (Windows)",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(endings, LineEndings::Lf, "LF (Linux, macOS)");
+                        ui.selectable_value(endings, LineEndings::CrLf, "CRLF (Windows)");
+                    });
+            });
+
+            // Embedded newline handling
+            ui.horizontal(|ui| {
+                ui.label("Newlines in Fields:");
+                egui::ComboBox::from_id_source("newline_handling")
+                    .selected_text(match self.settings.newline_handling {
+                        NewlineHandling::Keep => "Keep",
+                        NewlineHandling::Space => "Replace with space",
+                        NewlineHandling::Escape => "Replace with \\n",
+                        NewlineHandling::Custom => "Replace with custom",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.settings.newline_handling, NewlineHandling::Keep, "Keep");
+                        ui.selectable_value(&mut self.settings.newline_handling, NewlineHandling::Space, "Replace with space");
+                        ui.selectable_value(&mut self.settings.newline_handling, NewlineHandling::Escape, "Replace with \\n");
+                        ui.selectable_value(&mut self.settings.newline_handling, NewlineHandling::Custom, "Replace with custom");
+                    });
+            });
+            if self.settings.newline_handling == NewlineHandling::Custom {
+                ui.horizontal(|ui| {
+                    ui.label("Replacement:");
+                    ui.text_edit_singleline(&mut self.settings.newline_token);
+                });
+            }
+
+            ui.checkbox(&mut self.settings.include_headers, "Include Headers");
+            ui.checkbox(&mut self.settings.quote_fields, "Quote Fields");
+            ui.checkbox(&mut self.settings.append_mode, "Append to Existing File")
+                .on_hover_text("Add rows to an existing CSV with a matching header instead of overwriting it");
+            ui.checkbox(&mut self.settings.auto_rename, "Auto-Rename Existing Files")
+                .on_hover_text("Save as \"name (2).csv\" instead of asking to overwrite");
+
+            // Default output directory
+            ui.horizontal(|ui| {
+                ui.label("Output Folder:");
+                match &self.settings.output_dir {
+                    Some(dir) => ui.label(dir.display().to_string()),
+                    None => ui.label("Same as input"),
+                };
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Choose...").clicked() {
+                    if let Some(dir) = FileDialog::new().pick_folder() {
+                        self.settings.output_dir = Some(dir);
+                    }
+                }
+                if self.settings.output_dir.is_some() && ui.button("Reset").clicked() {
+                    self.settings.output_dir = None;
+                }
+            });
+        });
+
+        // Secrets referred to by the credential fields
+        ui.collapsing("Credentials", |ui| {
+            ui.weak("Credential fields and database URLs may refer to ${env:NAME} or ${keychain:NAME} \
+                instead of holding the secret");
+            let mut remove = None;
+            egui::Grid::new("keychain_secrets").show(ui, |ui| {
+                for (index, name) in self.settings.keychain_secrets.iter().enumerate() {
+                    let reference = secrets::keychain_reference(name);
+                    ui.monospace(&reference);
+                    if ui.small_button("Copy").on_hover_text("Copy the reference").clicked() {
+                        ui.output_mut(|output| output.copied_text = reference);
+                    }
+                    if ui.small_button("×").on_hover_text("Remove from the keychain").clicked() {
+                        remove = Some(index);
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(index) = remove {
+                self.remove_keychain_secret(index);
+            }
+            ui.horizontal(|ui| {
+                let (name, secret) = &mut self.new_secret;
+                ui.add(egui::TextEdit::singleline(name).hint_text("name").desired_width(90.0));
+                ui.add(egui::TextEdit::singleline(secret).hint_text("secret").password(true).desired_width(110.0));
+                let ready = !name.trim().is_empty() && !secret.is_empty();
+                if ui.add_enabled(ready, egui::Button::new("Save to Keychain")).clicked() {
+                    self.save_keychain_secret();
+                }
+            });
+        });
+
+        // S3 connection
+        ui.collapsing("S3", |ui| {
+            egui::Grid::new("s3_settings").show(ui, |ui| {
+                ui.label("Region:");
+                ui.text_edit_singleline(&mut self.settings.s3_region);
+                ui.end_row();
+                ui.label("Endpoint:");
+                ui.add(egui::TextEdit::singleline(&mut self.settings.s3_endpoint)
+                    .hint_text("AWS"));
+                ui.end_row();
+                ui.label("Access Key:");
+                ui.add(egui::TextEdit::singleline(&mut self.settings.s3_access_key)
+                    .hint_text("from environment"));
+                ui.end_row();
+                ui.label("Secret Key:");
+                ui.add(egui::TextEdit::singleline(&mut self.settings.s3_secret_key)
+                    .password(true));
+                ui.end_row();
+            });
+        });
+
+        // Google OAuth client for Sheets export
+        ui.collapsing("Google Sheets", |ui| {
+            egui::Grid::new("google_settings").show(ui, |ui| {
+                ui.label("Client ID:");
+                ui.text_edit_singleline(&mut self.settings.google_client_id);
+                ui.end_row();
+                ui.label("Client Secret:");
+                ui.add(egui::TextEdit::singleline(&mut self.settings.google_client_secret)
+                    .password(true));
+                ui.end_row();
+            });
+        });
+
+        // Large file guard
+        section("Large Files").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Large File Threshold:");
+                ui.add(egui::DragValue::new(&mut self.settings.large_file_threshold_mb)
+                    .clamp_range(1..=1_000_000)
+                    .suffix(" MB"));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Large Files:");
+                egui::ComboBox::from_id_source("large_file_action")
+                    .selected_text(match self.settings.large_file_action {
+                        LargeFileAction::Warn => "Ask first",
+                        LargeFileAction::Stream => "Stream automatically",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.settings.large_file_action, LargeFileAction::Warn, "Ask first");
+                        ui.selectable_value(&mut self.settings.large_file_action, LargeFileAction::Stream, "Stream automatically");
+                    });
+            });
+            ui.checkbox(&mut self.settings.checkpoints, "Checkpoints")
+                .on_hover_text("Save the progress of streamed conversions to CSV every 30 seconds, \
+                    so converting the file again after a crash or cancel resumes from there");
+            if let Some(path) = self.json_path.as_ref().filter(|path| checkpoint::exists(path)) {
+                ui.horizontal(|ui| {
+                    ui.label("This file has a checkpoint.");
+                    if ui.button("Discard").clicked() {
+                        checkpoint::discard(path);
+                    }
+                });
+            }
+        });
+
+        section("Performance").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Conversion Threads:");
+                ui.add(egui::DragValue::new(&mut self.settings.worker_threads)
+                    .clamp_range(0..=256)
+                    .custom_formatter(|threads, _| match threads as usize {
+                        0 => "All cores".to_string(),
+                        threads => threads.to_string(),
+                    }));
+            })
+            .response
+            .on_hover_text("Cap the threads all running conversions share, queued jobs included");
+            ui.checkbox(&mut self.settings.low_priority, "Low Priority")
+                .on_hover_text("Run conversions at the lowest priority, so long ones don't slow down other programs");
+        });
+
+        section("Preview").show(ui, |ui| {
+            ui.add(egui::Slider::new(&mut self.settings.max_preview_rows, 10..=1000)
+                .text("Max Preview Rows"));
+
+            // Cell length cap
+            let mut limit_cells = self.settings.max_cell_length.is_some();
+            if ui.checkbox(&mut limit_cells, "Truncate Long Cells").changed() {
+                self.settings.max_cell_length = limit_cells.then_some(1000);
+            }
+            if let Some(max_cell_length) = &mut self.settings.max_cell_length {
+                ui.add(egui::Slider::new(max_cell_length, 10..=100_000)
+                    .logarithmic(true)
+                    .text("Max Cell Length"));
+            }
+        });
+
+        section("Missing Values").show(ui, |ui| {
+            ui.weak("Written when a record lacks the key of a column, instead of an empty cell");
+            self.show_column_defaults(ui);
+        });
+
+        section("CSV Input").show(ui, |ui| self.show_csv_input(ui));
+
+        section("Column Filters").show(ui, |ui| {
+            ui.weak("Columns matching a pattern are left out unless selected; * and ? are wildcards");
+            show_patterns(ui, "exclude_columns", &mut self.settings.exclude_columns, "internal_*");
+            ui.checkbox(&mut self.settings.drop_empty_columns, "Drop Empty Columns")
+                .on_hover_text("Leave out columns that are empty or null in every row; rows are held in memory until the end");
+        });
+
+        section("Number Cleanup").show(ui, |ui| {
+            ui.weak("Text in matching columns is stripped to its number, e.g. $1,234.50 to 1234.50");
+            show_patterns(ui, "number_columns", &mut self.settings.number_columns, "price");
+        });
+
+        section("Keep as JSON").show(ui, |ui| {
+            ui.weak("Values of matching columns are written as their JSON text, without any cleanup");
+            show_patterns(ui, "json_columns", &mut self.settings.json_columns, "address");
+            ui.checkbox(&mut self.settings.pretty_json, "Indented")
+                .on_hover_text("Spread the JSON text over several indented lines instead of one");
+        });
+
+        section("Text Cleanup").show(ui, |ui| {
+            let cleanup = &mut self.settings.text_cleanup;
+            ui.checkbox(&mut cleanup.normalize, "Normalize Unicode (NFC)")
+                .on_hover_text("Write accented letters made of several code points as one, e.g. e + ◌́ as é");
+            ui.horizontal(|ui| {
+                ui.label("Control Characters:");
+                egui::ComboBox::from_id_source("control_characters")
+                    .selected_text(match cleanup.control_characters {
+                        ControlCharacters::Keep => "Keep",
+                        ControlCharacters::Strip => "Remove",
+                        ControlCharacters::Escape => "Escape as \\uXXXX",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut cleanup.control_characters, ControlCharacters::Keep, "Keep");
+                        ui.selectable_value(&mut cleanup.control_characters, ControlCharacters::Strip, "Remove");
+                        ui.selectable_value(&mut cleanup.control_characters, ControlCharacters::Escape, "Escape as \\uXXXX");
+                    });
+            })
+            .response
+            .on_hover_text("Control characters other than tab and line breaks, and zero-width characters");
+        });
+
+        section("Value Mapping").show(ui, |ui| {
+            ui.weak("Replaces cells of a column found in its table; other cells are kept");
+            self.show_value_mappings(ui);
+        });
+
+        section("Anonymization").show(ui, |ui| {
+            ui.weak("Hides sensitive columns; * and ? are wildcards");
+            self.show_anonymization(ui);
+        });
+
+        section("Metadata Columns").show(ui, |ui| {
+            let metadata = &mut self.settings.metadata_columns;
+            ui.checkbox(&mut metadata.row_number, "_row_number")
+                .on_hover_text("Number of the data row, from 1");
+            ui.checkbox(&mut metadata.source_file, "_source_file")
+                .on_hover_text("Path of the input file");
+            ui.checkbox(&mut metadata.json_pointer, "_json_pointer")
+                .on_hover_text("JSON Pointer of the input record a row came from, e.g. /41");
+        });
+
+        section("Validation").show(ui, |ui| self.show_validation_rules(ui));
+
+        // Record transform script
+        section("Record Script").show(ui, |ui| {
+            ui.add(egui::TextEdit::multiline(&mut self.settings.transform_script)
+                .code_editor()
+                .desired_rows(4)
+                .hint_text("record.full_name = record.first + \" \" + record.last;"))
+                .on_hover_text("Rhai script run on every record as `record`; set `record = ();` to drop it");
+        });
+
+        // Column Selection
+        if !self.all_columns.is_empty() {
+            section("Column Selection").show(ui, |ui| {
+                let columns: Vec<&String> = self.all_columns.iter().collect();
+                show_column_tree(ui, &columns, "", &mut self.selected_columns);
+            });
+        }
+    }
+}
+
+/// Fingerprint of the settings that change how the input is parsed, and of the size of
+/// the sample
+fn sample_fingerprint(settings: &Settings) -> u64 {
+    let parsing = (
+        settings.json5_fallback,
+        settings.duplicate_keys,
+        &settings.records_path,
+        &settings.key_column,
+        settings.geometry_columns,
+        &settings.csv_input,
+        settings.max_preview_rows,
+    );
+    checkpoint::fingerprint(&parsing, &[])
+}
+
+/// Displays checkboxes selecting columns, grouped by the dotted paths of their names
+///
+/// Two or more columns under the same parent, such as `address.city` and `address.zip`,
+/// form a collapsible node whose checkbox selects or deselects them all and shows a dash
+/// when only some are selected. `prefix` is the path of the node shown, with its trailing
+/// dot, and empty at the top.
+pub fn show_column_tree(ui: &mut egui::Ui, columns: &[&String], prefix: &str, selected: &mut Vec<String>) {
+    let mut groups: Vec<(&str, Vec<&String>)> = Vec::new();
+    for &column in columns {
+        let rest = &column[prefix.len()..];
+        let head = rest.split('.').next().unwrap_or(rest);
+        match groups.iter_mut().find(|(name, _)| *name == head) {
+            Some((_, members)) => members.push(column),
+            None => groups.push((head, vec![column])),
+        }
+    }
+
+    for (head, members) in groups {
+        if let [column] = members[..] {
+            show_column_checkbox(ui, column, &column[prefix.len()..], selected);
+            continue;
+        }
+        let path = format!("{}{}", prefix, head);
+        let count = members.iter().filter(|column| selected.contains(column)).count();
+        let id = ui.make_persistent_id(("column_group", &path));
+        egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
+            .show_header(ui, |ui| {
+                let mut all = count == members.len();
+                let checkbox = egui::Checkbox::new(&mut all, format!("{} ({})", head, members.len()))
+                    .indeterminate(count > 0 && count < members.len());
+                if ui.add(checkbox).on_hover_text(format!("{}.*", path)).changed() {
+                    if all {
+                        for column in &members {
+                            if !selected.contains(column) {
+                                selected.push((*column).clone());
+                            }
+                        }
+                    } else {
+                        selected.retain(|column| !members.contains(&column));
+                    }
+                }
+            })
+            .body(|ui| {
+                // A column named like the node itself, next to the columns below it
+                let (own, children): (Vec<&String>, Vec<&String>) =
+                    members.iter().partition(|column| column.len() == path.len());
+                for column in own {
+                    show_column_checkbox(ui, column, head, selected);
+                }
+                show_column_tree(ui, &children, &format!("{}.", path), selected);
+            });
+    }
+}
+
+/// Displays the checkbox selecting one column, labelled with its name below its node
+fn show_column_checkbox(ui: &mut egui::Ui, column: &String, label: &str, selected: &mut Vec<String>) {
+    let mut is_selected = selected.contains(column);
+    let mut response = ui.checkbox(&mut is_selected, label);
+    if label != column {
+        response = response.on_hover_text(column);
+    }
+    if response.changed() {
+        if is_selected {
+            selected.push(column.clone());
+        } else {
+            selected.retain(|c| c != column);
+        }
+    }
+}
+
+/// Displays an editable list of column patterns
+fn show_patterns(ui: &mut egui::Ui, id: &str, patterns: &mut Vec<String>, hint: &str) {
+    let mut remove = None;
+    egui::Grid::new(id).show(ui, |ui| {
+        for (index, pattern) in patterns.iter_mut().enumerate() {
+            ui.add(egui::TextEdit::singleline(pattern)
+                .hint_text(hint)
+                .desired_width(224.0));
+            if ui.small_button("×").on_hover_text("Remove pattern").clicked() {
+                remove = Some(index);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(index) = remove {
+        patterns.remove(index);
+    }
+    if ui.button("Add Pattern").clicked() {
+        patterns.push(String::new());
+    }
+}
+
+/// Displays the input value of a preview cell as JSON, highlighted with the CSV cell as
+/// tooltip when rendering changed it
+fn show_raw_cell(ui: &mut egui::Ui, raw: Option<&Value>, cell: &str) {
+    let Some(value) = raw else {
+        ui.weak("missing");
+        return;
+    };
+    let json = value.to_string();
+    let unchanged = match value {
+        Value::String(s) => s == cell,
+        _ => json == cell,
+    };
+    let text = egui::RichText::new(json).monospace();
+    if unchanged {
+        ui.label(text);
+    } else {
+        ui.label(text.color(ui.visuals().warn_fg_color))
+            .on_hover_text(format!("CSV: {}", cell));
+    }
+}
+
+/// Displays the outcome of "Analyze Only"
+fn show_analysis(ui: &mut egui::Ui, analysis: &Analysis) {
+    let about = if analysis.estimated { "about " } else { "" };
+    egui::CollapsingHeader::new("Analysis")
+        .id_source("analysis")
+        .default_open(true)
+        .show(ui, |ui| {
+            egui::Grid::new("analysis_summary").show(ui, |ui| {
+                ui.label("Records:");
+                ui.label(format!("{}{}", about, analysis.records));
+                ui.end_row();
+                ui.label("Rows:");
+                ui.label(format!("{}{}", about, analysis.rows));
+                ui.end_row();
+                ui.label("Columns:");
+                ui.label(analysis.columns.len().to_string());
+                ui.end_row();
+                ui.label("Output size:");
+                ui.label(format!("{}{}", about, format_bytes(analysis.output_bytes)));
+                ui.end_row();
+            });
+            egui::CollapsingHeader::new("Schema")
+                .id_source("analysis_schema")
+                .show(ui, |ui| {
+                    egui::Grid::new("analysis_schema_grid").striped(true).show(ui, |ui| {
+                        ui.strong("Column");
+                        ui.strong("Type");
+                        ui.strong("Longest");
+                        ui.end_row();
+                        let types = analysis.types.iter().zip(&analysis.widths);
+                        for (column, (column_type, width)) in analysis.columns.iter().zip(types) {
+                            ui.label(column);
+                            ui.label(column_type.label());
+                            ui.label(format!("{} chars", width));
+                            ui.end_row();
+                        }
+                    });
+                });
+            if analysis.estimated {
+                ui.weak("Estimated from a sample of the input");
+            }
+        });
+}
+
+/// Displays the statistics of a preview column in its header tooltip
+fn show_column_stats(ui: &mut egui::Ui, stats: &ColumnStats) {
+    egui::Grid::new("column_stats").show(ui, |ui| {
+        ui.label("Type:");
+        ui.label(stats.column_type.label());
+        ui.end_row();
+        ui.label("Nulls:");
+        ui.label(format!("{:.1}% ({} of {})", stats.null_percent(), stats.nulls, stats.rows));
+        ui.end_row();
+        ui.label("Distinct:");
+        ui.label(stats.distinct.to_string());
+        ui.end_row();
+        if let (Some(min), Some(max)) = (&stats.min, &stats.max) {
+            ui.label("Min:");
+            ui.label(min);
+            ui.end_row();
+            ui.label("Max:");
+            ui.label(max);
+            ui.end_row();
+        }
+    });
+    ui.weak(format!("Over the {} preview rows", stats.rows));
+}
+
+/// Settings section that can be folded away, open at first
+fn section(title: &str) -> egui::CollapsingHeader {
+    egui::CollapsingHeader::new(title).default_open(true)
+}
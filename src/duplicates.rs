@@ -0,0 +1,223 @@
+//! Duplicate key detection
+//!
+//! `serde_json` keeps only the last value when an object repeats a key, so the earlier
+//! values vanish without a trace. When a strategy is chosen in the settings, JSON input is
+//! read into `RawValue`, which keeps every entry of an object, and `Resolver` turns it into
+//! records while choosing which value to keep and noting the affected records and keys.
+
+use crate::settings::DuplicateKeys;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Number of affected records listed in the summary
+const MAX_EXAMPLES: usize = 5;
+
+/// Key under which `serde_json` hands a number to a visitor as a map, so that its text is
+/// kept exactly
+const NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+/// JSON value whose objects keep repeated keys
+pub enum RawValue {
+    Scalar(Value),
+    Array(Vec<RawValue>),
+    Object(Vec<(String, RawValue)>),
+}
+
+impl RawValue {
+    /// Records of one of several concatenated documents, like `input::document_records`
+    pub fn into_records(self) -> Vec<RawValue> {
+        match self {
+            RawValue::Array(items) => items,
+            other => vec![other],
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RawValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(RawValueVisitor)
+    }
+}
+
+struct RawValueVisitor;
+
+impl<'de> Visitor<'de> for RawValueVisitor {
+    type Value = RawValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any JSON value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<RawValue, E> {
+        Ok(RawValue::Scalar(Value::Bool(value)))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<RawValue, E> {
+        Ok(RawValue::Scalar(Value::from(value)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<RawValue, E> {
+        Ok(RawValue::Scalar(Value::from(value)))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<RawValue, E> {
+        Ok(RawValue::Scalar(Value::from(value)))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<RawValue, E> {
+        Ok(RawValue::Scalar(Value::String(value.to_string())))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<RawValue, E> {
+        Ok(RawValue::Scalar(Value::String(value)))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<RawValue, E> {
+        Ok(RawValue::Scalar(Value::Null))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<RawValue, E> {
+        Ok(RawValue::Scalar(Value::Null))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<RawValue, D::Error> {
+        RawValue::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<RawValue, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(RawValue::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RawValue, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if entries.is_empty() && key == NUMBER_TOKEN {
+                let text: String = map.next_value()?;
+                let number = text.parse().map_err(de::Error::custom)?;
+                return Ok(RawValue::Scalar(Value::Number(number)));
+            }
+            entries.push((key, map.next_value()?));
+        }
+        Ok(RawValue::Object(entries))
+    }
+}
+
+/// Records with repeated keys found in one input
+#[derive(Default)]
+pub struct DuplicateReport {
+    /// Number of records with at least one repeated key
+    pub records: usize,
+    /// Number and repeated keys of the first affected records; keys of nested objects are
+    /// given as paths such as `address.city`
+    pub examples: Vec<(usize, Vec<String>)>,
+}
+
+impl DuplicateReport {
+    /// Warning listing the affected records, if there are any
+    pub fn summary(&self) -> Option<String> {
+        if self.records == 0 {
+            return None;
+        }
+        let examples: Vec<String> = self
+            .examples
+            .iter()
+            .map(|(record, keys)| format!("record {}: {}", record, keys.join(", ")))
+            .collect();
+        let more = if self.records > self.examples.len() { ", ..." } else { "" };
+        Some(format!(
+            "{} records with duplicate keys ({}{})",
+            self.records,
+            examples.join("; "),
+            more
+        ))
+    }
+}
+
+/// Turns raw values into records, resolving repeated keys with a strategy
+pub struct Resolver {
+    strategy: DuplicateKeys,
+    /// Number of records resolved so far
+    records: usize,
+    pub report: DuplicateReport,
+}
+
+impl Resolver {
+    pub fn new(strategy: DuplicateKeys) -> Self {
+        Self {
+            strategy,
+            records: 0,
+            report: DuplicateReport::default(),
+        }
+    }
+
+    /// Resolves a whole document, counting the elements of a top-level array as records
+    pub fn document(&mut self, document: RawValue) -> Value {
+        match document {
+            RawValue::Array(items) => Value::Array(items.into_iter().map(|item| self.record(item)).collect()),
+            other => self.record(other),
+        }
+    }
+
+    /// Resolves one record
+    pub fn record(&mut self, record: RawValue) -> Value {
+        self.records += 1;
+        let mut repeated = Vec::new();
+        let value = self.resolve(record, "", &mut repeated);
+        if !repeated.is_empty() {
+            self.report.records += 1;
+            if self.report.examples.len() < MAX_EXAMPLES {
+                self.report.examples.push((self.records, repeated));
+            }
+        }
+        value
+    }
+
+    fn resolve(&self, value: RawValue, path: &str, repeated: &mut Vec<String>) -> Value {
+        match value {
+            RawValue::Scalar(value) => value,
+            RawValue::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|item| self.resolve(item, path, repeated))
+                    .collect(),
+            ),
+            RawValue::Object(entries) => {
+                let mut object = Map::new();
+                for (key, value) in entries {
+                    let key_path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    let value = self.resolve(value, &key_path, repeated);
+                    if !object.contains_key(&key) {
+                        object.insert(key, value);
+                        continue;
+                    }
+                    if !repeated.contains(&key_path) {
+                        repeated.push(key_path);
+                    }
+                    match self.strategy {
+                        DuplicateKeys::KeepFirst => {}
+                        DuplicateKeys::Off | DuplicateKeys::KeepLast => {
+                            object.insert(key, value);
+                        }
+                        DuplicateKeys::Suffix => {
+                            let free = (2..)
+                                .map(|n| format!("{}_{}", key, n))
+                                .find(|name| !object.contains_key(name))
+                                .unwrap();
+                            object.insert(free, value);
+                        }
+                    }
+                }
+                Value::Object(object)
+            }
+        }
+    }
+}
@@ -0,0 +1,1009 @@
+//! Output formats
+//!
+//! The conversion renders every record into a row of cell strings and hands it to an
+//! `OutputFormat`, which decides how header and rows end up in the output. Adding a format
+//! only takes a new implementation and an entry in `output_format`.
+
+use crate::locale::{self, Localizer};
+use crate::schema::{avro_schema, AvroField, ColumnType};
+use crate::settings::{ColumnWidth, LineEndings, OutputKind, Settings, TextEncoding};
+use anyhow::{anyhow, bail, Context, Result};
+use apache_avro::types::Value as AvroValue;
+use apache_avro::{Schema as AvroSchema, Writer as AvroWriter};
+use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema};
+use handlebars::{handlebars_helper, no_escape, Handlebars};
+use serde_json::{json, Map, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Writes rendered rows in a particular output format
+///
+/// `Send` is required because conversions run on background threads.
+pub trait OutputFormat: Send {
+    /// Receives the names of the columns and writes the header, if the format has one
+    ///
+    /// Called exactly once, before the first row. Formats with an optional header check
+    /// `Settings::include_headers` themselves.
+    fn write_header(&mut self, columns: &[String]) -> Result<()>;
+
+    /// Writes one data row, with one cell per column
+    fn write_row(&mut self, values: &[String]) -> Result<()>;
+
+    /// Number of output bytes produced so far
+    fn len(&self) -> usize;
+
+    /// Output produced so far, for formats whose output can be continued by appending to
+    /// it, as checkpoints need; `None` for the others
+    fn written(&mut self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Completes the output and returns it
+    fn finish(self: Box<Self>) -> Result<Vec<u8>>;
+}
+
+/// Cell a format cannot write, such as text in a typed column
+///
+/// Formats fail `write_row` with this error so the conversion can say where the cell came
+/// from.
+#[derive(Debug)]
+pub struct CellError {
+    /// Index of the column of the cell
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CellError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CellError {}
+
+/// Creates the output format selected in the settings
+pub fn output_format(settings: &Settings) -> Result<Box<dyn OutputFormat>> {
+    Ok(match settings.output_kind {
+        OutputKind::Csv => Box::new(CsvFormat::new(settings)?),
+        OutputKind::Template => Box::new(TemplateFormat::new(settings)?),
+        OutputKind::Arrow => Box::new(ArrowFormat::new(settings)),
+        OutputKind::Avro => Box::new(AvroFormat::new(settings)?),
+        OutputKind::Ods => Box::new(OdsFormat::new(settings)),
+        OutputKind::FixedWidth => Box::new(FixedWidthFormat::new(settings)),
+        OutputKind::Xlsx => Box::new(XlsxFormat::new(settings)),
+    })
+}
+
+/// Delimited text, written with the `csv` crate
+///
+/// The writer produces UTF-8, which is moved to `output` in the encoding of the settings
+/// after every record.
+pub struct CsvFormat {
+    writer: csv::Writer<Vec<u8>>,
+    include_headers: bool,
+    localizer: Option<Localizer>,
+    encoding: TextEncoding,
+    output: Vec<u8>,
+}
+
+impl CsvFormat {
+    /// Creates a CSV writer configured with the user settings
+    ///
+    /// Fails if the delimiter is not a single byte, which is all the CSV writer supports.
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let &[delimiter] = settings.delimiter.as_bytes() else {
+            bail!(
+                "Invalid delimiter {:?}: it must be a single ASCII character",
+                settings.delimiter
+            );
+        };
+
+        let writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .quote_style(if settings.quote_fields {
+                csv::QuoteStyle::Necessary
+            } else {
+                csv::QuoteStyle::Never
+            })
+            .terminator(match settings.line_endings {
+                LineEndings::Lf => csv::Terminator::Any(b'\n'),
+                LineEndings::CrLf => csv::Terminator::CRLF,
+            })
+            .from_writer(vec![]);
+        Ok(Self {
+            writer,
+            include_headers: settings.include_headers,
+            localizer: Localizer::new(settings),
+            encoding: settings.encoding,
+            output: Vec::new(),
+        })
+    }
+
+    /// Moves the records written so far to the output, encoded
+    fn encode_written(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        let written = std::mem::take(self.writer.get_mut());
+        // The writer only receives complete records of text
+        let text = String::from_utf8(written).context("CSV generation error")?;
+        locale::encode(&text, self.encoding, &mut self.output);
+        Ok(())
+    }
+}
+
+impl OutputFormat for CsvFormat {
+    fn write_header(&mut self, columns: &[String]) -> Result<()> {
+        // The byte order mark starts the file even without a header
+        if self.encoding == TextEncoding::Utf8Bom {
+            self.output.extend_from_slice(locale::UTF8_BOM);
+        }
+        if !self.include_headers {
+            return Ok(());
+        }
+        self.writer
+            .write_record(columns)
+            .context("Failed to write CSV headers")?;
+        // Encoded right away so `len` marks where the data rows start
+        self.encode_written().context("Failed to write CSV headers")
+    }
+
+    fn write_row(&mut self, values: &[String]) -> Result<()> {
+        match &self.localizer {
+            Some(localizer) => {
+                let cells: Vec<Cow<str>> = values.iter().map(|cell| localizer.cell(cell)).collect();
+                self.writer.write_record(cells.iter().map(|cell| cell.as_bytes()))?;
+            }
+            None => self.writer.write_record(values)?,
+        }
+        self.encode_written()
+    }
+
+    fn len(&self) -> usize {
+        self.output.len()
+    }
+
+    fn written(&mut self) -> Option<&[u8]> {
+        Some(&self.output)
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        Ok(self.output)
+    }
+}
+
+/// Text rendered from Handlebars templates
+///
+/// The header template sees the column names as `columns`, the row template sees every
+/// column as a variable plus `row_number`, and the footer template sees `row_count`. A
+/// column named `row_number` is hidden by the row number. Each rendered part ends with a
+/// single line break. Values are not HTML-escaped; the `sql`
+/// helper doubles single quotes, and `pad`/`lpad` pad a value to a fixed width.
+pub struct TemplateFormat {
+    registry: Handlebars<'static>,
+    columns: Vec<String>,
+    output: Vec<u8>,
+    rows: usize,
+}
+
+impl TemplateFormat {
+    /// Compiles the templates of the settings
+    pub fn new(settings: &Settings) -> Result<Self> {
+        if settings.template_row.trim().is_empty() {
+            bail!("The row template is empty");
+        }
+
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        registry.register_escape_fn(no_escape);
+        registry.register_helper("sql", Box::new(sql));
+        registry.register_helper("pad", Box::new(pad));
+        registry.register_helper("lpad", Box::new(lpad));
+        for (name, template) in [
+            ("header", &settings.template_header),
+            ("row", &settings.template_row),
+            ("footer", &settings.template_footer),
+        ] {
+            registry
+                .register_template_string(name, template)
+                .with_context(|| format!("Invalid {} template", name))?;
+        }
+
+        Ok(Self {
+            registry,
+            columns: Vec::new(),
+            output: Vec::new(),
+            rows: 0,
+        })
+    }
+
+    /// Renders a template and appends it as a line; empty results add nothing
+    ///
+    /// A trailing line break of the template, as left by most editors, is not doubled.
+    fn render_line(&mut self, name: &str, data: &Value) -> Result<()> {
+        let text = self
+            .registry
+            .render(name, data)
+            .map_err(|e| anyhow!("Failed to render {} template: {}", name, e))?;
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        if !text.is_empty() {
+            self.output.extend_from_slice(text.as_bytes());
+            self.output.push(b'\n');
+        }
+        Ok(())
+    }
+}
+
+impl OutputFormat for TemplateFormat {
+    fn write_header(&mut self, columns: &[String]) -> Result<()> {
+        self.columns = columns.to_vec();
+        self.render_line("header", &json!({ "columns": columns }))
+    }
+
+    fn write_row(&mut self, values: &[String]) -> Result<()> {
+        self.rows += 1;
+        let mut data = Map::new();
+        for (column, value) in self.columns.iter().zip(values) {
+            data.insert(column.clone(), Value::String(value.clone()));
+        }
+        data.insert("row_number".to_string(), Value::from(self.rows));
+        self.render_line("row", &Value::Object(data))
+    }
+
+    fn len(&self) -> usize {
+        self.output.len()
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<Vec<u8>> {
+        let data = json!({ "row_count": self.rows });
+        self.render_line("footer", &data)?;
+        Ok(self.output)
+    }
+}
+
+/// Rows collected by formats that can only be written once all column types are known
+#[derive(Default)]
+struct RowBuffer {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+    bytes: usize,
+    /// Column types declared by the JSON Schema of the settings, used instead of inferred
+    /// ones
+    declared_types: Vec<(String, ColumnType)>,
+}
+
+impl RowBuffer {
+    fn new(settings: &Settings) -> Self {
+        Self {
+            declared_types: settings
+                .json_schema
+                .as_ref()
+                .map(|schema| schema.columns.clone())
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    fn push(&mut self, values: &[String]) {
+        self.bytes += values.iter().map(String::len).sum::<usize>();
+        self.rows.push(values.to_vec());
+    }
+
+    /// Cells of a column, with `None` for empty and missing cells
+    fn cells(&self, index: usize) -> impl Iterator<Item = Option<&str>> + '_ {
+        self.rows
+            .iter()
+            .map(move |row| row.get(index).map(String::as_str).filter(|cell| !cell.is_empty()))
+    }
+
+    /// Declared or inferred type of a column
+    fn column_type(&self, index: usize) -> ColumnType {
+        let column = self.columns.get(index);
+        self.declared_types
+            .iter()
+            .find(|(name, _)| Some(name) == column)
+            .map(|(_, column_type)| *column_type)
+            .unwrap_or_else(|| ColumnType::infer(self.cells(index).flatten()))
+    }
+}
+
+/// Arrow IPC file, also known as Feather v2
+///
+/// Rows are collected until the end, when every column gets the narrowest type holding all
+/// of its values: 64-bit integer, 64-bit float, boolean or UTF-8 string, unless a JSON
+/// Schema declares the type. Empty cells become nulls, and so do JSON `null`s and cells not
+/// of the declared type in typed columns. The column names are always part of the schema.
+pub struct ArrowFormat {
+    buffer: RowBuffer,
+}
+
+impl ArrowFormat {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            buffer: RowBuffer::new(settings),
+        }
+    }
+
+    /// Builds the array of one column in its inferred type
+    fn column_array(&self, index: usize) -> (DataType, ArrayRef) {
+        let cells = || self.buffer.cells(index);
+        match self.buffer.column_type(index) {
+            ColumnType::Integer => (
+                DataType::Int64,
+                Arc::new(cells().map(|cell| cell?.parse().ok()).collect::<Int64Array>()),
+            ),
+            ColumnType::Float => (
+                DataType::Float64,
+                Arc::new(cells().map(|cell| cell?.parse().ok()).collect::<Float64Array>()),
+            ),
+            ColumnType::Boolean => (
+                DataType::Boolean,
+                Arc::new(cells().map(|cell| cell?.parse().ok()).collect::<BooleanArray>()),
+            ),
+            ColumnType::Text => (DataType::Utf8, Arc::new(cells().collect::<StringArray>())),
+        }
+    }
+}
+
+impl OutputFormat for ArrowFormat {
+    fn write_header(&mut self, columns: &[String]) -> Result<()> {
+        self.buffer.columns = columns.to_vec();
+        Ok(())
+    }
+
+    fn write_row(&mut self, values: &[String]) -> Result<()> {
+        self.buffer.push(values);
+        Ok(())
+    }
+
+    /// Size of the collected cell values, as the file is only written at the end
+    fn len(&self) -> usize {
+        self.buffer.bytes
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let columns = &self.buffer.columns;
+        let (fields, arrays): (Vec<Field>, Vec<ArrayRef>) = (0..columns.len())
+            .map(|index| {
+                let (data_type, array) = self.column_array(index);
+                (Field::new(&columns[index], data_type, true), array)
+            })
+            .unzip();
+        let schema = Arc::new(Schema::new(fields));
+
+        let mut writer =
+            FileWriter::try_new(Vec::new(), &schema).context("Arrow generation error")?;
+        if !arrays.is_empty() {
+            let batch = RecordBatch::try_new(Arc::clone(&schema), arrays)
+                .context("Arrow generation error")?;
+            writer.write(&batch).context("Arrow generation error")?;
+        }
+        writer.finish().context("Arrow generation error")?;
+        writer.into_inner().context("Arrow generation error")
+    }
+}
+
+/// Avro object container file
+///
+/// The schema is a record with one nullable field per column. Its fields come from
+/// `Settings::avro_fields` when the user edited them, and are otherwise generated from the
+/// column names and inferred types once all rows are collected. Empty cells and JSON
+/// `null`s are written as nulls. Cells not of the type of an edited field fail the row.
+pub struct AvroFormat {
+    buffer: RowBuffer,
+    fields: Vec<AvroField>,
+}
+
+impl AvroFormat {
+    /// Checks the edited schema of the settings, if there is one
+    pub fn new(settings: &Settings) -> Result<Self> {
+        if !settings.avro_fields.is_empty() {
+            AvroSchema::parse(&avro_schema(&settings.avro_fields)).context("Invalid Avro schema")?;
+        }
+        Ok(Self {
+            buffer: RowBuffer::new(settings),
+            fields: settings.avro_fields.clone(),
+        })
+    }
+}
+
+impl OutputFormat for AvroFormat {
+    fn write_header(&mut self, columns: &[String]) -> Result<()> {
+        if !self.fields.is_empty() && self.fields.len() != columns.len() {
+            bail!(
+                "The Avro schema has {} fields, but the conversion produced {} columns",
+                self.fields.len(),
+                columns.len()
+            );
+        }
+        self.buffer.columns = columns.to_vec();
+        Ok(())
+    }
+
+    fn write_row(&mut self, values: &[String]) -> Result<()> {
+        // Edited fields fix the types up front, so a cell of another type fails its own row
+        for (index, field) in self.fields.iter().enumerate() {
+            let cell = values.get(index).map_or("", String::as_str);
+            if let Err(e) = avro_value(cell, field.column_type) {
+                let message = format!("field {}: {:#}", field.name, e);
+                return Err(CellError { column: index, message }.into());
+            }
+        }
+        self.buffer.push(values);
+        Ok(())
+    }
+
+    /// Size of the collected cell values, as the file is only written at the end
+    fn len(&self) -> usize {
+        self.buffer.bytes
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let fields = if self.fields.is_empty() {
+            self.buffer
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(index, column)| AvroField::for_column(column, self.buffer.column_type(index)))
+                .collect()
+        } else {
+            self.fields
+        };
+        let schema = AvroSchema::parse(&avro_schema(&fields)).context("Invalid Avro schema")?;
+
+        let mut writer = AvroWriter::new(&schema, Vec::new());
+        for (row_index, row) in self.buffer.rows.iter().enumerate() {
+            let record = fields
+                .iter()
+                .enumerate()
+                .map(|(index, field)| {
+                    let cell = row.get(index).map_or("", String::as_str);
+                    avro_value(cell, field.column_type)
+                        .map(|value| (field.name.clone(), value))
+                        .with_context(|| format!("Row {}, field {}", row_index + 1, field.name))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            writer
+                .append(AvroValue::Record(record))
+                .context("Avro generation error")?;
+        }
+        writer.into_inner().context("Avro generation error")
+    }
+}
+
+/// Avro value of a cell for a nullable field of the given type
+fn avro_value(cell: &str, column_type: ColumnType) -> Result<AvroValue> {
+    if cell.is_empty() || cell == "null" {
+        return Ok(AvroValue::Union(0, Box::new(AvroValue::Null)));
+    }
+    let value = match column_type {
+        ColumnType::Integer => AvroValue::Long(
+            cell.parse()
+                .with_context(|| format!("{:?} is not a long", cell))?,
+        ),
+        ColumnType::Float => AvroValue::Double(
+            cell.parse()
+                .with_context(|| format!("{:?} is not a double", cell))?,
+        ),
+        ColumnType::Boolean => AvroValue::Boolean(
+            cell.parse()
+                .with_context(|| format!("{:?} is not a boolean", cell))?,
+        ),
+        ColumnType::Text => AvroValue::String(cell.to_string()),
+    };
+    Ok(AvroValue::Union(1, Box::new(value)))
+}
+
+/// OpenDocument spreadsheet (`.ods`), as used by LibreOffice
+///
+/// A single sheet holds the header row, when headers are enabled, and the data rows. Cells
+/// of number and boolean columns are typed, so they can be calculated with; line breaks in
+/// text become separate paragraphs of the cell.
+pub struct OdsFormat {
+    buffer: RowBuffer,
+    include_headers: bool,
+}
+
+impl OdsFormat {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            buffer: RowBuffer::new(settings),
+            include_headers: settings.include_headers,
+        }
+    }
+
+    /// Builds `content.xml`, the part of the document holding the table
+    fn content_xml(&self) -> String {
+        let types: Vec<ColumnType> = (0..self.buffer.columns.len())
+            .map(|index| self.buffer.column_type(index))
+            .collect();
+
+        let mut xml = String::from(ODS_CONTENT_START);
+        if self.include_headers {
+            xml.push_str("<table:table-row>");
+            for column in &self.buffer.columns {
+                push_ods_cell(&mut xml, column, ColumnType::Text);
+            }
+            xml.push_str("</table:table-row>");
+        }
+        for row in &self.buffer.rows {
+            xml.push_str("<table:table-row>");
+            for (index, column_type) in types.iter().enumerate() {
+                push_ods_cell(&mut xml, row.get(index).map_or("", String::as_str), *column_type);
+            }
+            xml.push_str("</table:table-row>");
+        }
+        xml.push_str(ODS_CONTENT_END);
+        xml
+    }
+}
+
+impl OutputFormat for OdsFormat {
+    fn write_header(&mut self, columns: &[String]) -> Result<()> {
+        self.buffer.columns = columns.to_vec();
+        Ok(())
+    }
+
+    fn write_row(&mut self, values: &[String]) -> Result<()> {
+        self.buffer.push(values);
+        Ok(())
+    }
+
+    /// Size of the collected cell values, as the file is only written at the end
+    fn len(&self) -> usize {
+        self.buffer.bytes
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        // The mimetype must come first and uncompressed, so the file type can be sniffed
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(ODS_MIMETYPE.as_bytes())?;
+        for (name, content) in [
+            ("META-INF/manifest.xml", ODS_MANIFEST.to_string()),
+            ("content.xml", self.content_xml()),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default())?;
+            zip.write_all(content.as_bytes())?;
+        }
+        let file = zip.finish().context("ODS generation error")?;
+        Ok(file.into_inner())
+    }
+}
+
+const ODS_MIMETYPE: &str = "application/vnd.oasis.opendocument.spreadsheet";
+
+const ODS_MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2">
+<manifest:file-entry manifest:full-path="/" manifest:version="1.2" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>
+<manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>
+"#;
+
+const ODS_CONTENT_START: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" office:version="1.2"><office:body><office:spreadsheet><table:table table:name="Data">"#;
+
+const ODS_CONTENT_END: &str = "</table:table></office:spreadsheet></office:body></office:document-content>\n";
+
+/// Appends a table cell; empty cells and nulls of typed columns are left empty
+///
+/// Only finite numbers are written as floats, since `NaN` and `inf` are no valid
+/// `office:value`; such cells are written as strings.
+fn push_ods_cell(xml: &mut String, cell: &str, column_type: ColumnType) {
+    let typed = match column_type {
+        ColumnType::Integer | ColumnType::Float if cell.parse::<f64>().is_ok_and(f64::is_finite) => {
+            Some(format!(r#"office:value-type="float" office:value="{}""#, cell))
+        }
+        ColumnType::Boolean if cell == "true" || cell == "false" => {
+            Some(format!(r#"office:value-type="boolean" office:boolean-value="{}""#, cell))
+        }
+        _ => None,
+    };
+    match typed {
+        Some(attributes) => {
+            xml.push_str(&format!(
+                "<table:table-cell {}><text:p>{}</text:p></table:table-cell>",
+                attributes, cell
+            ));
+        }
+        None if cell.is_empty() || (column_type != ColumnType::Text && cell == "null") => {
+            xml.push_str("<table:table-cell/>");
+        }
+        None => {
+            xml.push_str(r#"<table:table-cell office:value-type="string">"#);
+            for line in cell.split('\n') {
+                xml.push_str("<text:p>");
+                push_xml_text(xml, line.strip_suffix('\r').unwrap_or(line));
+                xml.push_str("</text:p>");
+            }
+            xml.push_str("</table:table-cell>");
+        }
+    }
+}
+
+/// Appends text with XML special characters escaped and characters XML cannot hold left out
+fn push_xml_text(xml: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => xml.push_str("&amp;"),
+            '<' => xml.push_str("&lt;"),
+            '>' => xml.push_str("&gt;"),
+            '"' => xml.push_str("&quot;"),
+            '\t' | '\n' => xml.push(c),
+            c if c < ' ' => {}
+            c => xml.push(c),
+        }
+    }
+}
+
+/// Excel workbook (Office Open XML)
+///
+/// The rows go to a sheet named "Data", or with `Settings::sheet_column` to one sheet per
+/// value of that column, named after the value, in the order the values first appear.
+/// Number and boolean columns are typed like in ODS output; other cells are inline text,
+/// with line breaks kept.
+pub struct XlsxFormat {
+    buffer: RowBuffer,
+    include_headers: bool,
+    sheet_column: String,
+}
+
+impl XlsxFormat {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            buffer: RowBuffer::new(settings),
+            include_headers: settings.include_headers,
+            sheet_column: settings.sheet_column.clone(),
+        }
+    }
+
+    /// Sheet names with the indices of their rows; names are cut to the 31 characters
+    /// Excel allows, stripped of the characters it forbids and made unique
+    fn sheets(&self) -> Vec<(String, Vec<usize>)> {
+        let Some(column) = self.buffer.columns.iter().position(|column| *column == self.sheet_column) else {
+            return vec![("Data".to_string(), (0..self.buffer.rows.len()).collect())];
+        };
+        let mut sheets: Vec<(String, Vec<usize>)> = Vec::new();
+        // Sheet of every value seen so far
+        let mut by_value: HashMap<String, usize> = HashMap::new();
+        for (index, row) in self.buffer.rows.iter().enumerate() {
+            let value = row.get(column).map_or("", String::as_str);
+            if let Some(&sheet) = by_value.get(value) {
+                sheets[sheet].1.push(index);
+                continue;
+            }
+            let base: String = value
+                .chars()
+                .map(|c| if "[]:*?/\\".contains(c) || c < ' ' { '_' } else { c })
+                .take(XLSX_SHEET_NAME_LENGTH)
+                .collect();
+            // Names cannot start or end with an apostrophe
+            let base = match base.trim_matches('\'') {
+                "" => "(empty)".to_string(),
+                trimmed => trimmed.to_string(),
+            };
+            let mut name = base.clone();
+            let mut number = 2;
+            // Excel compares sheet names without regard to case
+            while sheets.iter().any(|(sheet, _)| sheet.to_lowercase() == name.to_lowercase()) {
+                let suffix = format!(" ({})", number);
+                let kept: String = base.chars().take(XLSX_SHEET_NAME_LENGTH - suffix.len()).collect();
+                name = format!("{}{}", kept, suffix);
+                number += 1;
+            }
+            by_value.insert(value.to_string(), sheets.len());
+            sheets.push((name, vec![index]));
+        }
+        if sheets.is_empty() {
+            sheets.push(("Data".to_string(), Vec::new()));
+        }
+        sheets
+    }
+
+    /// Builds a worksheet holding the rows at `rows`
+    fn sheet_xml(&self, rows: &[usize], types: &[ColumnType]) -> String {
+        let mut xml = String::from(XLSX_SHEET_START);
+        let mut number = 0;
+        if self.include_headers {
+            number += 1;
+            xml.push_str(&format!(r#"<row r="{}">"#, number));
+            for (index, column) in self.buffer.columns.iter().enumerate() {
+                push_xlsx_cell(&mut xml, index, number, column, ColumnType::Text);
+            }
+            xml.push_str("</row>");
+        }
+        for &row in rows {
+            number += 1;
+            xml.push_str(&format!(r#"<row r="{}">"#, number));
+            for (index, column_type) in types.iter().enumerate() {
+                let cell = self.buffer.rows[row].get(index).map_or("", String::as_str);
+                push_xlsx_cell(&mut xml, index, number, cell, *column_type);
+            }
+            xml.push_str("</row>");
+        }
+        xml.push_str(XLSX_SHEET_END);
+        xml
+    }
+}
+
+impl OutputFormat for XlsxFormat {
+    fn write_header(&mut self, columns: &[String]) -> Result<()> {
+        if !self.sheet_column.is_empty() && !columns.contains(&self.sheet_column) {
+            bail!("Column {:?} to split the sheets by is not part of the output", self.sheet_column);
+        }
+        self.buffer.columns = columns.to_vec();
+        Ok(())
+    }
+
+    fn write_row(&mut self, values: &[String]) -> Result<()> {
+        self.buffer.push(values);
+        Ok(())
+    }
+
+    /// Size of the collected cell values, as the file is only written at the end
+    fn len(&self) -> usize {
+        self.buffer.bytes
+    }
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        let types: Vec<ColumnType> = (0..self.buffer.columns.len())
+            .map(|index| self.buffer.column_type(index))
+            .collect();
+        let sheets = self.sheets();
+
+        let mut content_types = String::from(XLSX_CONTENT_TYPES_START);
+        let mut workbook = String::from(XLSX_WORKBOOK_START);
+        let mut relationships = String::from(XLSX_WORKBOOK_RELS_START);
+        for (index, (name, _)) in sheets.iter().enumerate() {
+            let number = index + 1;
+            content_types.push_str(&format!(
+                r#"<Override PartName="/xl/worksheets/sheet{}.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+                number
+            ));
+            workbook.push_str(r#"<sheet name=""#);
+            push_xml_text(&mut workbook, name);
+            workbook.push_str(&format!(r#"" sheetId="{}" r:id="rId{}"/>"#, number, number));
+            relationships.push_str(&format!(
+                r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{}.xml"/>"#,
+                number, number
+            ));
+        }
+        content_types.push_str("</Types>");
+        workbook.push_str("</sheets></workbook>");
+        relationships.push_str("</Relationships>");
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in [
+            ("[Content_Types].xml", content_types),
+            ("_rels/.rels", XLSX_RELS.to_string()),
+            ("xl/workbook.xml", workbook),
+            ("xl/_rels/workbook.xml.rels", relationships),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default())?;
+            zip.write_all(content.as_bytes())?;
+        }
+        for (index, (_, rows)) in sheets.iter().enumerate() {
+            zip.start_file(format!("xl/worksheets/sheet{}.xml", index + 1), SimpleFileOptions::default())?;
+            zip.write_all(self.sheet_xml(rows, &types).as_bytes())?;
+        }
+        let file = zip.finish().context("XLSX generation error")?;
+        Ok(file.into_inner())
+    }
+}
+
+/// Longest sheet name Excel accepts
+const XLSX_SHEET_NAME_LENGTH: usize = 31;
+
+const XLSX_CONTENT_TYPES_START: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>"#;
+
+const XLSX_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>
+"#;
+
+const XLSX_WORKBOOK_START: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets>"#;
+
+const XLSX_WORKBOOK_RELS_START: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#;
+
+const XLSX_SHEET_START: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#;
+
+const XLSX_SHEET_END: &str = "</sheetData></worksheet>\n";
+
+/// Appends a cell at column `index` of row `row`, counted from 1; empty cells and nulls of
+/// typed columns are left out
+///
+/// Only finite numbers are written as numbers, as Excel cannot open a sheet holding
+/// `NaN` or `inf` values; such cells are written as text.
+fn push_xlsx_cell(xml: &mut String, index: usize, row: usize, cell: &str, column_type: ColumnType) {
+    let mut reference = String::new();
+    let mut column = index + 1;
+    while column > 0 {
+        column -= 1;
+        reference.insert(0, (b'A' + (column % 26) as u8) as char);
+        column /= 26;
+    }
+    match column_type {
+        ColumnType::Integer | ColumnType::Float if cell.parse::<f64>().is_ok_and(f64::is_finite) => {
+            xml.push_str(&format!(r#"<c r="{}{}"><v>{}</v></c>"#, reference, row, cell));
+        }
+        ColumnType::Boolean if cell == "true" || cell == "false" => {
+            let value = u8::from(cell == "true");
+            xml.push_str(&format!(r#"<c r="{}{}" t="b"><v>{}</v></c>"#, reference, row, value));
+        }
+        _ if cell.is_empty() || (column_type != ColumnType::Text && cell == "null") => {}
+        _ => {
+            xml.push_str(&format!(r#"<c r="{}{}" t="inlineStr"><is><t xml:space="preserve">"#, reference, row));
+            push_xml_text(xml, cell);
+            xml.push_str("</t></is></c>");
+        }
+    }
+}
+
+/// Text with every column padded to a fixed width, as record-oriented loaders such as
+/// mainframe jobs read it
+///
+/// Cells are padded with spaces to the width of their column and longer ones are cut, so
+/// every line has the same length; there are no delimiters or quotes, and line breaks in
+/// values become spaces. Widths count bytes in the encoding of the settings, so a line of
+/// UTF-8 text with accents is as long as one without; cells are only cut between
+/// characters. Columns without a configured width are as wide as their longest value, or
+/// name with headers on, so the rows are collected until the end unless every column has
+/// one.
+pub struct FixedWidthFormat {
+    widths: Vec<ColumnWidth>,
+    include_headers: bool,
+    encoding: TextEncoding,
+    /// Line break ending every line
+    line_ending: &'static str,
+    /// Width and alignment of every column, once they are known
+    layout: Vec<(usize, bool)>,
+    /// Rows held back until the widths measured from them are known
+    buffer: Option<RowBuffer>,
+    output: Vec<u8>,
+}
+
+impl FixedWidthFormat {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            widths: settings.fixed_widths.clone(),
+            include_headers: settings.include_headers,
+            encoding: settings.encoding,
+            line_ending: match settings.line_endings {
+                LineEndings::Lf => "\n",
+                LineEndings::CrLf => "\r\n",
+            },
+            layout: Vec::new(),
+            buffer: None,
+            output: Vec::new(),
+        }
+    }
+
+    /// Configured width and alignment of a column
+    fn configured(&self, column: &str) -> (Option<usize>, bool) {
+        self.widths
+            .iter()
+            .find(|width| width.column == column)
+            .map_or((None, false), |width| (width.width, width.align_right))
+    }
+
+    fn write_line<'c>(&mut self, cells: impl Iterator<Item = &'c str>) {
+        let mut cells = cells.fuse();
+        let mut line = String::new();
+        for &(width, align_right) in &self.layout {
+            let cell = cells.next().unwrap_or_default();
+            let (mut text, mut bytes) = (String::new(), 0);
+            for c in cell.chars().map(|c| if c == '\n' || c == '\r' { ' ' } else { c }) {
+                let len = locale::char_len(c, self.encoding);
+                if bytes + len > width {
+                    break;
+                }
+                text.push(c);
+                bytes += len;
+            }
+            let padding = " ".repeat(width - bytes);
+            if align_right {
+                line.push_str(&padding);
+                line.push_str(&text);
+            } else {
+                line.push_str(&text);
+                line.push_str(&padding);
+            }
+        }
+        line.push_str(self.line_ending);
+        locale::encode(&line, self.encoding, &mut self.output);
+    }
+
+    /// Width of a cell, in bytes of the encoding
+    fn width_of(&self, cell: &str) -> usize {
+        cell.chars().map(|c| locale::char_len(c, self.encoding)).sum()
+    }
+}
+
+impl OutputFormat for FixedWidthFormat {
+    fn write_header(&mut self, columns: &[String]) -> Result<()> {
+        if self.encoding == TextEncoding::Utf8Bom {
+            self.output.extend_from_slice(locale::UTF8_BOM);
+        }
+        let configured: Vec<(Option<usize>, bool)> =
+            columns.iter().map(|column| self.configured(column)).collect();
+        if configured.iter().any(|(width, _)| width.is_none()) {
+            self.buffer = Some(RowBuffer {
+                columns: columns.to_vec(),
+                ..Default::default()
+            });
+            return Ok(());
+        }
+        self.layout = configured
+            .into_iter()
+            .map(|(width, align_right)| (width.unwrap_or_default(), align_right))
+            .collect();
+        if self.include_headers {
+            self.write_line(columns.iter().map(String::as_str));
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, values: &[String]) -> Result<()> {
+        match &mut self.buffer {
+            Some(buffer) => buffer.push(values),
+            None => self.write_line(values.iter().map(String::as_str)),
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.output.len() + self.buffer.as_ref().map_or(0, |buffer| buffer.bytes)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<Vec<u8>> {
+        let Some(buffer) = self.buffer.take() else {
+            return Ok(self.output);
+        };
+        self.layout = buffer
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| match self.configured(column) {
+                (Some(width), align_right) => (width, align_right),
+                (None, align_right) => {
+                    let longest = buffer
+                        .cells(index)
+                        .map(|cell| cell.map_or(0, |cell| self.width_of(cell)))
+                        .max()
+                        .unwrap_or_default();
+                    let header = if self.include_headers { self.width_of(column) } else { 0 };
+                    (longest.max(header), align_right)
+                }
+            })
+            .collect();
+        if self.include_headers {
+            self.write_line(buffer.columns.iter().map(String::as_str));
+        }
+        for row in &buffer.rows {
+            self.write_line(row.iter().map(String::as_str));
+        }
+        Ok(self.output)
+    }
+}
+
+handlebars_helper!(sql: |value: str| value.replace('\'', "''"));
+handlebars_helper!(pad: |value: str, width: u64| {
+    let width = width as usize;
+    let value: String = value.chars().take(width).collect();
+    format!("{:<width$}", value)
+});
+handlebars_helper!(lpad: |value: str, width: u64| {
+    let width = width as usize;
+    let value: String = value.chars().take(width).collect();
+    format!("{:>width$}", value)
+});
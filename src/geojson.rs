@@ -0,0 +1,143 @@
+//! GeoJSON input
+//!
+//! A GeoJSON `FeatureCollection`, or a single `Feature`, is read as one record per feature:
+//! the `properties` become the columns, next to the `id` of the feature when it has one.
+//! The geometry is written as Well-Known Text in a `geometry` column, or as the
+//! `longitude` and `latitude` of its center, as chosen in the settings.
+
+use crate::settings::GeometryColumns;
+use serde_json::{Map, Number, Value};
+
+/// Whether a value is a GeoJSON `FeatureCollection` or `Feature`
+pub fn is_geojson(value: &Value) -> bool {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => value["features"].is_array(),
+        Some("Feature") => value.get("geometry").is_some(),
+        _ => false,
+    }
+}
+
+/// Records of the features of a GeoJSON value accepted by `is_geojson`
+pub fn feature_records(value: Value, geometry: GeometryColumns) -> Value {
+    let features = match value {
+        Value::Object(mut obj) if obj.get("type").and_then(Value::as_str) == Some("FeatureCollection") => {
+            obj.remove("features").unwrap_or_default()
+        }
+        feature => Value::Array(vec![feature]),
+    };
+    let Value::Array(features) = features else {
+        return Value::Array(Vec::new());
+    };
+    Value::Array(
+        features
+            .into_iter()
+            .map(|feature| feature_record(feature, geometry))
+            .collect(),
+    )
+}
+
+/// Record of one feature; properties take precedence over the `id` and geometry columns
+fn feature_record(mut feature: Value, geometry: GeometryColumns) -> Value {
+    let mut fields = Map::new();
+    if let Some(id) = feature.get_mut("id") {
+        fields.insert("id".to_string(), id.take());
+    }
+    let shape = feature.get_mut("geometry").map(Value::take).unwrap_or_default();
+    match geometry {
+        GeometryColumns::Wkt => {
+            let wkt = wkt(&shape).map_or(Value::Null, Value::String);
+            fields.insert("geometry".to_string(), wkt);
+        }
+        GeometryColumns::LonLat => {
+            let (longitude, latitude) = center(&shape).unwrap_or((Value::Null, Value::Null));
+            fields.insert("longitude".to_string(), longitude);
+            fields.insert("latitude".to_string(), latitude);
+        }
+    }
+    if let Some(Value::Object(properties)) = feature.get_mut("properties").map(Value::take) {
+        fields.extend(properties);
+    }
+    Value::Object(fields)
+}
+
+/// Well-Known Text of a geometry, such as `POINT (30 10)`; `None` for a `null` or
+/// malformed geometry
+fn wkt(geometry: &Value) -> Option<String> {
+    let (name, depth) = match geometry["type"].as_str()? {
+        "Point" => ("POINT", 0),
+        "LineString" => ("LINESTRING", 1),
+        "MultiPoint" => ("MULTIPOINT", 1),
+        "Polygon" => ("POLYGON", 2),
+        "MultiLineString" => ("MULTILINESTRING", 2),
+        "MultiPolygon" => ("MULTIPOLYGON", 3),
+        "GeometryCollection" => {
+            let geometries = geometry["geometries"].as_array()?;
+            if geometries.is_empty() {
+                return Some("GEOMETRYCOLLECTION EMPTY".to_string());
+            }
+            let geometries: Option<Vec<String>> = geometries.iter().map(wkt).collect();
+            return Some(format!("GEOMETRYCOLLECTION ({})", geometries?.join(", ")));
+        }
+        _ => return None,
+    };
+    let coordinates = &geometry["coordinates"];
+    let text = match depth {
+        0 if coordinates.as_array().is_some_and(Vec::is_empty) => "EMPTY".to_string(),
+        0 => format!("({})", wkt_coordinates(coordinates, 0)?),
+        depth => wkt_coordinates(coordinates, depth)?,
+    };
+    Some(format!("{} {}", name, text))
+}
+
+/// Coordinates nested `depth` arrays deep: a position at depth 0, as in `30 10`, and a
+/// parenthesized list of the nested coordinates above
+fn wkt_coordinates(coordinates: &Value, depth: usize) -> Option<String> {
+    let items = coordinates.as_array()?;
+    if depth == 0 {
+        let numbers: Option<Vec<String>> = items
+            .iter()
+            .map(|number| number.as_number().map(Number::to_string))
+            .collect();
+        return numbers.filter(|numbers| numbers.len() >= 2).map(|numbers| numbers.join(" "));
+    }
+    if items.is_empty() {
+        return Some("EMPTY".to_string());
+    }
+    let items: Option<Vec<String>> = items.iter().map(|item| wkt_coordinates(item, depth - 1)).collect();
+    Some(format!("({})", items?.join(", ")))
+}
+
+/// Longitude and latitude of a point, or the mean of all positions of another geometry
+fn center(geometry: &Value) -> Option<(Value, Value)> {
+    if geometry["type"] == "Point" {
+        let position = geometry["coordinates"].as_array()?;
+        return Some((position.first()?.clone(), position.get(1)?.clone()));
+    }
+    let mut positions = Vec::new();
+    collect_positions(geometry, &mut positions);
+    if positions.is_empty() {
+        return None;
+    }
+    let count = positions.len() as f64;
+    let longitude = positions.iter().map(|(longitude, _)| longitude).sum::<f64>() / count;
+    let latitude = positions.iter().map(|(_, latitude)| latitude).sum::<f64>() / count;
+    let number = |value: f64| Number::from_f64(value).map_or(Value::Null, Value::Number);
+    Some((number(longitude), number(latitude)))
+}
+
+/// Positions of a geometry, or of all geometries of a collection
+fn collect_positions(geometry: &Value, positions: &mut Vec<(f64, f64)>) {
+    fn walk(coordinates: &Value, positions: &mut Vec<(f64, f64)>) {
+        let Some(items) = coordinates.as_array() else {
+            return;
+        };
+        match (items.first().and_then(Value::as_f64), items.get(1).and_then(Value::as_f64)) {
+            (Some(longitude), Some(latitude)) => positions.push((longitude, latitude)),
+            _ => items.iter().for_each(|item| walk(item, positions)),
+        }
+    }
+    match geometry["geometries"].as_array() {
+        Some(geometries) => geometries.iter().for_each(|geometry| collect_positions(geometry, positions)),
+        None => walk(&geometry["coordinates"], positions),
+    }
+}
@@ -0,0 +1,459 @@
+//! Input formats
+//!
+//! JSON, YAML, TOML and CSV are all parsed into the same `serde_json::Value` model, so the
+//! rest of the conversion pipeline does not need to know where the records came from.
+
+use crate::dialect;
+use crate::geojson;
+use crate::duplicates::{DuplicateReport, RawValue, Resolver};
+use crate::settings::{DuplicateKeys, Settings};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use memmap2::Mmap;
+use serde_json::{Map, Number, Value};
+use std::fs::{self, File};
+use std::ops::Deref;
+use std::path::Path;
+
+/// Extensions offered by the open dialog, for all supported formats
+pub const INPUT_EXTENSIONS: &[&str] = &["json", "json5", "geojson", "yaml", "yml", "toml", "csv", "tsv"];
+
+/// Files from this size on are memory-mapped instead of read into memory
+const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// Contents of an input file
+///
+/// Large files are mapped into memory, so the operating system pages them in as they are
+/// parsed and can drop pages that were already read, instead of the whole file being
+/// copied to the heap first. Small files, and files that cannot be mapped, are read.
+pub enum FileContents {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Deref for FileContents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileContents::Mapped(map) => map,
+            FileContents::Read(data) => data,
+        }
+    }
+}
+
+/// Opens an input file, mapping it into memory when it is large
+pub fn read_file(path: &Path) -> Result<FileContents> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let len = file.metadata().map(|metadata| metadata.len()).unwrap_or_default();
+    if len >= MMAP_THRESHOLD {
+        // SAFETY: the mapping goes wrong if another process truncates the file while it is
+        // converted; that is accepted for input files like it is by other tools mapping
+        // their input, and reading through the mapping only sees changed bytes otherwise
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            return Ok(FileContents::Mapped(map));
+        }
+    }
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(FileContents::Read(data))
+}
+
+/// Text of an input file read with `read_file`
+pub fn file_text(contents: &FileContents) -> Result<&str> {
+    std::str::from_utf8(contents).context("Input file is not valid UTF-8")
+}
+
+/// Format of an input file
+#[derive(Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Json,
+    Yaml,
+    Toml,
+    /// An existing CSV file, re-written with the current output settings
+    Csv,
+}
+
+/// Parsed input
+pub struct ParsedInput {
+    /// Records of the input
+    pub value: Value,
+    /// Names of the elements of array records, e.g. the header of a CSV file; empty when
+    /// they have none
+    pub field_names: Vec<String>,
+    /// Objects with repeated keys, when checking for them is enabled
+    pub duplicate_keys: DuplicateReport,
+    /// Where the records are in the input
+    pub pointers: RecordPointers,
+}
+
+/// Where the records of an input are within it, so each can be named by its JSON Pointer
+#[derive(Clone)]
+pub enum RecordPointers {
+    /// Elements of the array at a pointer, e.g. `/data/items` or `/rows`; empty for the
+    /// top-level array, and for concatenated documents, whose records are numbered across
+    /// them
+    Elements(String),
+    /// Values of the object at a pointer, in the order of their keys
+    Entries(String, Vec<String>),
+    /// The single record at a pointer
+    Whole(String),
+    /// Features of the GeoJSON `FeatureCollection` at a pointer
+    Features(String),
+    /// The single GeoJSON `Feature` at a pointer
+    Feature(String),
+}
+
+impl Default for RecordPointers {
+    fn default() -> Self {
+        RecordPointers::Elements(String::new())
+    }
+}
+
+impl RecordPointers {
+    /// JSON Pointer of the record at `index`, counted from 0, e.g. `/data/items/41`
+    pub fn pointer(&self, index: usize) -> String {
+        match self {
+            RecordPointers::Elements(prefix) => format!("{}/{}", prefix, index),
+            RecordPointers::Entries(prefix, keys) => match keys.get(index) {
+                Some(key) => format!("{}/{}", prefix, pointer_token(key)),
+                None => format!("{}/{}", prefix, index),
+            },
+            RecordPointers::Features(prefix) => format!("{}/features/{}", prefix, index),
+            RecordPointers::Whole(pointer) | RecordPointers::Feature(pointer) => pointer.clone(),
+        }
+    }
+
+    /// JSON Pointer of the value of a column of the object record at `index`
+    ///
+    /// Columns of GeoJSON features lead to their properties, or to the `id` or geometry
+    /// they were made of.
+    pub fn field(&self, index: usize, column: &str) -> String {
+        let record = self.pointer(index);
+        match self {
+            RecordPointers::Features(_) | RecordPointers::Feature(_) => match column {
+                "id" => format!("{}/id", record),
+                "geometry" | "longitude" | "latitude" => format!("{}/geometry", record),
+                column => format!("{}/properties/{}", record, pointer_token(column)),
+            },
+            _ => format!("{}/{}", record, pointer_token(column)),
+        }
+    }
+}
+
+/// Key written as a reference token of a JSON Pointer, with `~` and `/` escaped
+pub fn pointer_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+impl InputFormat {
+    /// Detects the format from the file extension, defaulting to JSON
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("yaml" | "yml") => InputFormat::Yaml,
+            Some("toml") => InputFormat::Toml,
+            Some("csv" | "tsv") => InputFormat::Csv,
+            _ => InputFormat::Json,
+        }
+    }
+
+    /// Name of the format shown in status messages
+    pub fn name(self) -> &'static str {
+        match self {
+            InputFormat::Json => "JSON",
+            InputFormat::Yaml => "YAML",
+            InputFormat::Toml => "TOML",
+            InputFormat::Csv => "CSV",
+        }
+    }
+}
+
+/// Parses text held in memory into the records model
+///
+/// With a records path in the settings, only the value it points to is read. A
+/// column-oriented table, see `table_columns`, is read as its rows named by its columns,
+/// a map of keys to records, see `is_keyed`, as those records, and GeoJSON as its features.
+///
+/// GeoJSON is checked first, as a feature collection may also look like keyed records.
+/// The pointers of the records lead to where they are in the input, e.g. `/features/41`
+/// or `/user1` for keyed input.
+pub fn parse(content: &str, format: InputFormat, settings: &Settings) -> Result<ParsedInput> {
+    let (mut value, duplicate_keys, mut prefix) = match format {
+        InputFormat::Json => {
+            let (value, duplicate_keys) = parse_json(content, settings)?;
+            (value, duplicate_keys, String::new())
+        }
+        InputFormat::Yaml => (parse_yaml(content)?, DuplicateReport::default(), String::new()),
+        InputFormat::Toml => {
+            let (value, prefix) = parse_toml(content)?;
+            (value, DuplicateReport::default(), prefix)
+        }
+        InputFormat::Csv => return parse_csv(content, settings),
+    };
+    if !settings.records_path.is_empty() {
+        value = match value.pointer_mut(&settings.records_path) {
+            Some(records) => records.take(),
+            None => bail!("The input has no value at {}", settings.records_path),
+        };
+        prefix.push_str(&settings.records_path);
+    }
+    let field_names = table_columns(&value).unwrap_or_default();
+    let pointers = if geojson::is_geojson(&value) {
+        let collection = value["type"] == "FeatureCollection";
+        value = geojson::feature_records(value, settings.geometry_columns);
+        if collection {
+            RecordPointers::Features(prefix)
+        } else {
+            RecordPointers::Feature(prefix)
+        }
+    } else if !field_names.is_empty() {
+        value = value["rows"].take();
+        RecordPointers::Elements(format!("{}/rows", prefix))
+    } else if is_keyed(&value, &settings.key_column) {
+        let keys = value.as_object().map(|obj| obj.keys().cloned().collect()).unwrap_or_default();
+        value = keyed_records(value, &settings.key_column);
+        RecordPointers::Entries(prefix, keys)
+    } else if value.is_array() {
+        RecordPointers::Elements(prefix)
+    } else {
+        RecordPointers::Whole(prefix)
+    };
+    Ok(ParsedInput {
+        value,
+        field_names,
+        duplicate_keys,
+        pointers,
+    })
+}
+
+/// Column names of a column-oriented table, as several BI tools export it:
+/// `{"columns": ["a", "b"], "rows": [[1, 2], [3, 4]]}`
+///
+/// Columns are names, or objects with a `name` such as `{"name": "a", "type": "string"}`.
+/// `None` for any other value, including objects with further keys.
+pub fn table_columns(value: &Value) -> Option<Vec<String>> {
+    let Value::Object(obj) = value else {
+        return None;
+    };
+    let rows = obj.get("rows")?.as_array()?;
+    if obj.len() != 2 || !rows.iter().all(Value::is_array) {
+        return None;
+    }
+    obj.get("columns")?
+        .as_array()?
+        .iter()
+        .map(|column| match column {
+            Value::String(name) => Some(name.clone()),
+            column => column["name"].as_str().map(str::to_string),
+        })
+        .collect()
+}
+
+/// Parses JSON text
+///
+/// Several documents written back to back are combined into one record set. Input that is
+/// not strict JSON is parsed again as JSON5 if enabled; when that fails too, the error of
+/// the strict parser is reported. Repeated keys are resolved and reported as set in the
+/// settings; JSON5 input is not checked for them.
+fn parse_json(content: &str, settings: &Settings) -> Result<(Value, DuplicateReport)> {
+    let strict = if settings.duplicate_keys == DuplicateKeys::Off {
+        serde_json::Deserializer::from_str(content)
+            .into_iter::<Value>()
+            .collect::<Result<Vec<Value>, _>>()
+            .map(|documents| (documents, DuplicateReport::default()))
+    } else {
+        serde_json::Deserializer::from_str(content)
+            .into_iter::<RawValue>()
+            .collect::<Result<Vec<RawValue>, _>>()
+            .map(|documents| {
+                let mut resolver = Resolver::new(settings.duplicate_keys);
+                let documents = documents
+                    .into_iter()
+                    .map(|document| resolver.document(document))
+                    .collect();
+                (documents, resolver.report)
+            })
+    };
+    match strict {
+        Ok((documents, duplicate_keys)) => {
+            let value = combine_documents(documents).context("JSON parsing error")?;
+            Ok((value, duplicate_keys))
+        }
+        Err(e) => {
+            if settings.json5_fallback {
+                if let Ok(value) = json5::from_str::<Value>(content) {
+                    return Ok((value, DuplicateReport::default()));
+                }
+            }
+            Err(e).context("JSON parsing error")
+        }
+    }
+}
+
+/// Parses YAML text; documents separated by `---` are combined like concatenated JSON
+fn parse_yaml(content: &str) -> Result<Value> {
+    let documents = serde_yaml::Deserializer::from_str(content)
+        .map(Value::deserialize)
+        .collect::<Result<Vec<Value>, _>>()
+        .context("YAML parsing error")?;
+    combine_documents(documents).context("YAML parsing error")
+}
+
+/// Parses TOML text
+///
+/// A TOML document is always a table. When its only entry is an array of tables, as in
+/// `[[records]]`, those tables are the records; otherwise the table is a single record.
+/// Also returns the JSON Pointer of the records, such as `/records`.
+fn parse_toml(content: &str) -> Result<(Value, String)> {
+    let table: toml::Table = content.parse().context("TOML parsing error")?;
+    let value = toml_to_json(toml::Value::Table(table));
+    if let Value::Object(obj) = &value {
+        if let [(key, Value::Array(items))] = obj.iter().collect::<Vec<_>>()[..] {
+            if items.iter().all(Value::is_object) {
+                return Ok((Value::Array(items.clone()), format!("/{}", pointer_token(key))));
+            }
+        }
+    }
+    Ok((value, String::new()))
+}
+
+/// Parses CSV text into array records named by its header row
+///
+/// The dialect is sniffed from the first rows where the settings do not choose it; without
+/// a header row, the columns are named by position. All values are kept as text, and a
+/// leading byte order mark is dropped.
+fn parse_csv(content: &str, settings: &Settings) -> Result<ParsedInput> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let dialect = dialect::sniff(content).with_overrides(&settings.csv_input)?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(dialect.delimiter)
+        .quote(dialect.quote)
+        .has_headers(dialect.has_header)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let field_names = if dialect.has_header {
+        reader
+            .headers()
+            .context("CSV parsing error")?
+            .iter()
+            .map(str::to_string)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let records = reader
+        .records()
+        .map(|record| {
+            record.map(|record| {
+                Value::Array(record.iter().map(|field| Value::String(field.to_string())).collect())
+            })
+        })
+        .collect::<Result<Vec<Value>, _>>()
+        .context("CSV parsing error")?;
+
+    Ok(ParsedInput {
+        value: Value::Array(records),
+        field_names,
+        duplicate_keys: DuplicateReport::default(),
+        pointers: RecordPointers::default(),
+    })
+}
+
+/// Whether a value maps keys to records, such as `{"user1": {...}, "user2": {...}}`: an
+/// object of at least two entries that are all objects
+///
+/// Always `false` without a key column, so such input stays a single record.
+pub fn is_keyed(value: &Value, key_column: &str) -> bool {
+    match value {
+        Value::Object(obj) => !key_column.is_empty() && obj.len() > 1 && obj.values().all(Value::is_object),
+        _ => false,
+    }
+}
+
+/// Arrays of records within a value, as JSON Pointers with the number of records, for
+/// choosing the records path
+///
+/// The value itself is listed first, with an empty pointer, when it is an array. Arrays
+/// are not searched further, as their elements are the records, and objects only down to
+/// a few levels.
+pub fn record_arrays(value: &Value) -> Vec<(String, usize)> {
+    const DEPTH: usize = 4;
+    fn walk(value: &Value, pointer: &str, depth: usize, arrays: &mut Vec<(String, usize)>) {
+        match value {
+            Value::Array(items) if !items.is_empty() => arrays.push((pointer.to_string(), items.len())),
+            Value::Object(fields) if depth < DEPTH => {
+                for (key, field) in fields {
+                    let pointer = format!("{}/{}", pointer, pointer_token(key));
+                    walk(field, &pointer, depth + 1, arrays);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut arrays = Vec::new();
+    walk(value, "", 0, &mut arrays);
+    arrays
+}
+
+/// Records of keyed input, each with its key in `key_column`; a field of the record with
+/// the same name takes precedence
+fn keyed_records(value: Value, key_column: &str) -> Value {
+    let Value::Object(obj) = value else {
+        return value;
+    };
+    let records = obj
+        .into_iter()
+        .map(|(key, record)| {
+            let mut fields = Map::new();
+            fields.insert(key_column.to_string(), Value::String(key));
+            if let Value::Object(record) = record {
+                fields.extend(record);
+            }
+            Value::Object(fields)
+        })
+        .collect();
+    Value::Array(records)
+}
+
+/// Converts a TOML value, writing dates and times as strings
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(text) => Value::String(text),
+        toml::Value::Integer(number) => Value::Number(number.into()),
+        toml::Value::Float(number) => Number::from_f64(number)
+            .map(Value::Number)
+            .unwrap_or_else(|| Value::String(number.to_string())),
+        toml::Value::Boolean(flag) => Value::Bool(flag),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect::<Map<String, Value>>(),
+        ),
+    }
+}
+
+/// Turns the documents of one input into a single value
+///
+/// A single document is used as it is; several documents are combined into one record set.
+fn combine_documents(mut documents: Vec<Value>) -> Result<Value> {
+    Ok(match documents.len() {
+        0 => bail!("the input is empty"),
+        1 => documents.remove(0),
+        _ => Value::Array(documents.into_iter().flat_map(document_records).collect()),
+    })
+}
+
+/// Records of one of several concatenated documents: the elements of an array, or the
+/// document itself
+pub fn document_records(document: Value) -> Vec<Value> {
+    match document {
+        Value::Array(items) => items,
+        other => vec![other],
+    }
+}
@@ -0,0 +1,331 @@
+//! Background job queue for conversions
+//!
+//! Every conversion is submitted as a job. Jobs wait in the queue until a worker slot is
+//! free, then run on their own thread; the UI only polls their progress each frame. Each
+//! job has a progress of its own, which is forwarded to the document that submitted it,
+//! so later jobs of the same document leave the state of earlier ones alone.
+
+use crate::conversion::{spawn_conversion, ConversionInput, ConversionProgress};
+use crate::settings::Settings;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Lifecycle state of a job
+#[derive(Clone, Copy, PartialEq)]
+pub enum JobState {
+    /// Waiting for a free worker slot
+    Queued,
+    /// Currently converting
+    Running,
+    /// Finished successfully
+    Completed,
+    /// Stopped with an error
+    Failed,
+    /// Cancelled by the user
+    Cancelled,
+}
+
+/// Input of a job that has not been started yet
+struct PendingInput {
+    input: ConversionInput,
+    settings: Settings,
+    selected_columns: Vec<String>,
+}
+
+/// A single conversion job
+pub struct Job {
+    /// Unique id of the job
+    pub id: u64,
+    /// Name shown in the jobs panel, usually the input file name
+    pub name: String,
+    /// Progress of this job, shared with its worker thread
+    progress: Arc<Mutex<ConversionProgress>>,
+    /// Progress of the owning document, which the progress of the job is forwarded to
+    document: Arc<Mutex<ConversionProgress>>,
+    /// Set to ask the worker to stop
+    cancel: Arc<AtomicBool>,
+    /// Input kept until the job is started
+    pending: Option<PendingInput>,
+    /// When the job was started
+    started: Option<Instant>,
+    /// Final state and status, recorded when the job ends
+    outcome: Option<(JobState, String)>,
+    /// Whether the end of the job has already been reported
+    reported: bool,
+}
+
+impl Job {
+    /// Current state of the job
+    pub fn state(&self) -> JobState {
+        match &self.outcome {
+            _ if self.pending.is_some() => JobState::Queued,
+            Some((state, _)) => *state,
+            None => JobState::Running,
+        }
+    }
+
+    /// Forwards the progress of a running job to its document, and records its outcome
+    /// and hands the result over once it ended
+    fn forward(&mut self) {
+        let mut progress = self.progress.lock().unwrap();
+        let mut document = self.document.lock().unwrap();
+        document.status = progress.status.clone();
+        document.progress = progress.progress;
+        document.started_at = progress.started_at;
+        document.rows_processed = progress.rows_processed;
+        document.total_rows = progress.total_rows;
+        document.bytes_processed = progress.bytes_processed;
+        document.bytes_read = progress.bytes_read;
+        document.input_bytes = progress.input_bytes;
+        if progress.is_converting {
+            return;
+        }
+        let state = if self.cancel.load(Ordering::Relaxed) {
+            JobState::Cancelled
+        } else if progress.failed {
+            JobState::Failed
+        } else {
+            JobState::Completed
+        };
+        document.failed = progress.failed;
+        document.result = progress.result.take();
+        document.is_converting = false;
+        self.outcome = Some((state, progress.status.clone()));
+    }
+
+    /// Status shown for the job: the final one once it ended
+    fn status(&self) -> String {
+        match &self.outcome {
+            Some((_, status)) => status.clone(),
+            None => self.progress.lock().unwrap().status.clone(),
+        }
+    }
+}
+
+/// Report about a job that has just ended
+pub struct FinishedJob {
+    /// Name of the job
+    pub name: String,
+    /// Final state of the job
+    pub state: JobState,
+    /// Final status message
+    pub status: String,
+    /// Time between starting and ending the job
+    pub duration: Duration,
+}
+
+/// Where the jobs panel is docked
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum JobsDock {
+    Right,
+    Bottom,
+}
+
+/// Queue of conversion jobs with a limited number of concurrently running workers
+pub struct JobQueue {
+    /// All jobs, oldest first
+    jobs: Vec<Job>,
+    /// Id given to the next submitted job
+    next_id: u64,
+    /// Maximum number of jobs running at the same time
+    max_running: usize,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self {
+            jobs: Vec::new(),
+            next_id: 1,
+            max_running: thread::available_parallelism().map_or(2, |n| n.get()),
+        }
+    }
+}
+
+impl JobQueue {
+    /// Queues a conversion whose progress and result are forwarded to `progress`
+    ///
+    /// Returns the id of the new job.
+    pub fn submit(
+        &mut self,
+        name: String,
+        input: ConversionInput,
+        settings: Settings,
+        selected_columns: Vec<String>,
+        progress: Arc<Mutex<ConversionProgress>>,
+    ) -> u64 {
+        let queued = || ConversionProgress {
+            is_converting: true,
+            status: "Waiting in queue...".to_string(),
+            ..Default::default()
+        };
+        *progress.lock().unwrap() = queued();
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs.push(Job {
+            id,
+            name,
+            progress: Arc::new(Mutex::new(queued())),
+            document: progress,
+            cancel: Arc::new(AtomicBool::new(false)),
+            pending: Some(PendingInput {
+                input,
+                settings,
+                selected_columns,
+            }),
+            started: None,
+            outcome: None,
+            reported: false,
+        });
+        self.poll();
+        id
+    }
+
+    /// Forwards the progress of running jobs and starts queued jobs while worker slots are
+    /// free
+    ///
+    /// A job with a thread limit in its settings only starts while fewer jobs than that
+    /// are running, as all of them share the worker threads.
+    pub fn poll(&mut self) {
+        for job in &mut self.jobs {
+            if job.state() == JobState::Running {
+                job.forward();
+            }
+        }
+        let mut running = self
+            .jobs
+            .iter()
+            .filter(|job| job.state() == JobState::Running)
+            .count();
+
+        for job in &mut self.jobs {
+            let Some(pending) = &job.pending else {
+                continue;
+            };
+            let limit = match pending.settings.worker_threads {
+                0 => self.max_running,
+                threads => threads.min(self.max_running),
+            };
+            if running >= limit {
+                break;
+            }
+            if let Some(input) = job.pending.take() {
+                spawn_conversion(
+                    input.input,
+                    input.settings,
+                    input.selected_columns,
+                    Arc::clone(&job.progress),
+                    Arc::clone(&job.cancel),
+                );
+                job.started = Some(Instant::now());
+                running += 1;
+            }
+        }
+    }
+
+    /// Returns the jobs that have ended since the last call
+    pub fn take_finished(&mut self) -> Vec<FinishedJob> {
+        let mut finished = Vec::new();
+        for job in &mut self.jobs {
+            let state = job.state();
+            if job.reported || matches!(state, JobState::Queued | JobState::Running) {
+                continue;
+            }
+            job.reported = true;
+            finished.push(FinishedJob {
+                name: job.name.clone(),
+                state,
+                status: job.status(),
+                duration: job.started.map(|started| started.elapsed()).unwrap_or_default(),
+            });
+        }
+        finished
+    }
+
+    /// Asks a job to stop; queued jobs are cancelled before they start
+    pub fn cancel(&mut self, id: u64) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            job.cancel.store(true, Ordering::Relaxed);
+            if job.pending.take().is_some() {
+                let mut progress = job.progress.lock().unwrap();
+                progress.is_converting = false;
+                progress.status = "Conversion cancelled".to_string();
+                drop(progress);
+                job.forward();
+            }
+        }
+    }
+
+    /// Whether any job is queued or running
+    pub fn is_busy(&self) -> bool {
+        self.jobs
+            .iter()
+            .any(|job| matches!(job.state(), JobState::Queued | JobState::Running))
+    }
+
+    /// Removes all jobs that are no longer queued or running
+    pub fn clear_finished(&mut self) {
+        self.jobs
+            .retain(|job| matches!(job.state(), JobState::Queued | JobState::Running));
+    }
+
+    /// Displays the list of jobs with their progress, result and a cancel button
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        let mut cancel = None;
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            if self.jobs.is_empty() {
+                ui.label("No conversions yet");
+            }
+
+            for job in self.jobs.iter().rev() {
+                let state = job.state();
+                let progress = job.progress.lock().unwrap();
+                let progress_value = progress.progress;
+                let throughput = progress.started_at.map(|_| progress.throughput_summary());
+                drop(progress);
+                let status = job.status();
+
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.strong(&job.name);
+                        if matches!(state, JobState::Queued | JobState::Running)
+                            && ui.small_button("Cancel").clicked()
+                        {
+                            cancel = Some(job.id);
+                        }
+                    });
+                    match state {
+                        JobState::Queued => {
+                            ui.label("Queued");
+                        }
+                        JobState::Running => {
+                            ui.add(egui::ProgressBar::new(progress_value).show_percentage());
+                            ui.label(status);
+                            if let Some(throughput) = throughput {
+                                ui.small(throughput);
+                            }
+                        }
+                        JobState::Completed => {
+                            ui.colored_label(egui::Color32::from_rgb(0, 150, 0), status);
+                        }
+                        JobState::Failed => {
+                            ui.colored_label(egui::Color32::RED, status);
+                        }
+                        JobState::Cancelled => {
+                            ui.colored_label(egui::Color32::GRAY, status);
+                        }
+                    }
+                });
+            }
+        });
+
+        if let Some(id) = cancel {
+            self.cancel(id);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Target locales of CSV output
+//!
+//! Spreadsheet applications read CSV files with the conventions of the region they are set
+//! up for: Excel in Germany expects semicolons, decimal commas, dates as `05.03.2024` and
+//! Windows-1252 text, while Excel in the US expects commas and `03/05/2024`. A target
+//! locale bundles the delimiter, decimal separator, date format, encoding and line endings
+//! of one such setup, so they are chosen as one item.
+//!
+//! The conventions are applied as the rows are written: cells of JSON numbers with a
+//! fraction, such as `1.5`, get the decimal separator from `decimal_comma`, and the CSV
+//! format gives cells holding an ISO 8601 date, with or without a time, the date format.
+//! Text that merely looks like a number, such as a version `"1.5"`, keeps its point. Other
+//! formats are not affected.
+
+use crate::lossy;
+use crate::settings::{DateFormat, LineEndings, Settings, TextEncoding};
+use regex::Regex;
+use std::borrow::Cow;
+
+/// Set of CSV output conventions of a spreadsheet application in a region
+#[derive(Clone, Copy, PartialEq)]
+pub enum TargetLocale {
+    /// The defaults: commas, decimal points, ISO dates, UTF-8 and `\n`
+    Standard,
+    ExcelUs,
+    ExcelUk,
+    ExcelGermany,
+    ExcelFrance,
+}
+
+impl TargetLocale {
+    pub const ALL: [TargetLocale; 5] = [
+        TargetLocale::Standard,
+        TargetLocale::ExcelUs,
+        TargetLocale::ExcelUk,
+        TargetLocale::ExcelGermany,
+        TargetLocale::ExcelFrance,
+    ];
+
+    /// Name shown in the settings
+    pub fn name(self) -> &'static str {
+        match self {
+            TargetLocale::Standard => "Standard (UTF-8, ISO dates)",
+            TargetLocale::ExcelUs => "Excel (US)",
+            TargetLocale::ExcelUk => "Excel (UK)",
+            TargetLocale::ExcelGermany => "Excel (Germany)",
+            TargetLocale::ExcelFrance => "Excel (France)",
+        }
+    }
+
+    /// Delimiter, decimal comma, date format, encoding and line endings of the locale
+    fn conventions(self) -> (&'static str, bool, DateFormat, TextEncoding, LineEndings) {
+        match self {
+            TargetLocale::Standard => (",", false, DateFormat::Iso, TextEncoding::Utf8, LineEndings::Lf),
+            TargetLocale::ExcelUs => (",", false, DateFormat::MonthDayYear, TextEncoding::Utf8Bom, LineEndings::CrLf),
+            TargetLocale::ExcelUk => (",", false, DateFormat::DayMonthYear, TextEncoding::Utf8Bom, LineEndings::CrLf),
+            TargetLocale::ExcelGermany => {
+                (";", true, DateFormat::DayMonthYearDots, TextEncoding::Windows1252, LineEndings::CrLf)
+            }
+            TargetLocale::ExcelFrance => {
+                (";", true, DateFormat::DayMonthYear, TextEncoding::Windows1252, LineEndings::CrLf)
+            }
+        }
+    }
+
+    /// Sets the conventions of the locale
+    pub fn apply(self, settings: &mut Settings) {
+        let (delimiter, decimal_comma, date_format, encoding, line_endings) = self.conventions();
+        settings.delimiter = delimiter.to_string();
+        settings.decimal_comma = decimal_comma;
+        settings.date_format = date_format;
+        settings.encoding = encoding;
+        settings.line_endings = line_endings;
+    }
+
+    /// Locale whose conventions the settings follow, if any does
+    pub fn of(settings: &Settings) -> Option<Self> {
+        let current = (
+            settings.delimiter.as_str(),
+            settings.decimal_comma,
+            settings.date_format,
+            settings.encoding,
+            settings.line_endings,
+        );
+        Self::ALL.into_iter().find(|locale| locale.conventions() == current)
+    }
+}
+
+/// Writes the cells of a row that hold a JSON number with a decimal comma
+///
+/// `kinds` are the kind flags of the cells from `lossy::classify`; cells of other values,
+/// text among them, and numbers changed into something else, such as by a value mapping,
+/// are left as they are.
+pub fn decimal_comma(cells: &mut [String], kinds: &[u8]) {
+    for (cell, &kind) in cells.iter_mut().zip(kinds) {
+        if lossy::is_number(kind) && cell.contains('.') && cell.parse::<f64>().is_ok() {
+            *cell = cell.replacen('.', ",", 1);
+        }
+    }
+}
+
+/// Rewrites cells with the date format of the settings
+pub struct Localizer {
+    date_format: DateFormat,
+    date: Regex,
+}
+
+impl Localizer {
+    /// Localizer for the settings; `None` when they write cells unchanged
+    pub fn new(settings: &Settings) -> Option<Self> {
+        if settings.date_format == DateFormat::Iso {
+            return None;
+        }
+        Some(Self {
+            date_format: settings.date_format,
+            date: Regex::new(r"^([0-9]{4})-([0-9]{2})-([0-9]{2})(?:[T ]([0-9]{2}:[0-9]{2}(?::[0-9]{2})?))?$")
+                .expect("valid date pattern"),
+        })
+    }
+
+    /// Cell with an ISO 8601 date written the local way; other cells are returned unchanged
+    ///
+    /// A date with a time keeps the time after a space, as in `05.03.2024 14:30`. Dates with
+    /// fractional seconds or a time zone are left as they are.
+    pub fn cell<'a>(&self, cell: &'a str) -> Cow<'a, str> {
+        let Some(captures) = self.date.captures(cell) else {
+            return Cow::Borrowed(cell);
+        };
+        let (year, month, day) = (&captures[1], &captures[2], &captures[3]);
+        let mut local = match self.date_format {
+            DateFormat::MonthDayYear => format!("{}/{}/{}", month, day, year),
+            DateFormat::DayMonthYear => format!("{}/{}/{}", day, month, year),
+            DateFormat::DayMonthYearDots | DateFormat::Iso => format!("{}.{}.{}", day, month, year),
+        };
+        if let Some(time) = captures.get(4) {
+            local.push(' ');
+            local.push_str(time.as_str());
+        }
+        Cow::Owned(local)
+    }
+}
+
+/// Characters of the Windows-1252 bytes 0x80 to 0x9F; the five bytes it leaves undefined
+/// stand for the C1 control characters, as in browsers
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+/// Byte order mark starting UTF-8 output with `TextEncoding::Utf8Bom`
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Number of bytes a character takes in an encoding
+pub fn char_len(c: char, encoding: TextEncoding) -> usize {
+    match encoding {
+        TextEncoding::Utf8 | TextEncoding::Utf8Bom => c.len_utf8(),
+        TextEncoding::Windows1252 => 1,
+    }
+}
+
+/// Appends text in an encoding; characters Windows-1252 lacks are written as `?`
+pub fn encode(text: &str, encoding: TextEncoding, output: &mut Vec<u8>) {
+    match encoding {
+        TextEncoding::Utf8 | TextEncoding::Utf8Bom => output.extend_from_slice(text.as_bytes()),
+        TextEncoding::Windows1252 => output.extend(text.chars().map(|c| match c as u32 {
+            code @ (0..=0x7F | 0xA0..=0xFF) => code as u8,
+            _ => WINDOWS_1252_HIGH
+                .iter()
+                .position(|&high| high == c)
+                .map_or(b'?', |index| 0x80 + index as u8),
+        })),
+    }
+}
+
+/// Text of output written in an encoding, without its byte order mark
+pub fn decode(bytes: &[u8], encoding: TextEncoding) -> Cow<'_, str> {
+    match encoding {
+        TextEncoding::Utf8 | TextEncoding::Utf8Bom => {
+            String::from_utf8_lossy(bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes))
+        }
+        TextEncoding::Windows1252 => Cow::Owned(
+            bytes
+                .iter()
+                .map(|&byte| match byte {
+                    0x80..=0x9F => WINDOWS_1252_HIGH[(byte - 0x80) as usize],
+                    _ => byte as char,
+                })
+                .collect(),
+        ),
+    }
+}
@@ -0,0 +1,252 @@
+//! Logging
+//!
+//! Loading, conversion and saving report what they do as `tracing` events. In the window,
+//! events are kept in memory for the log panel and, with "Write Log File" on, appended to
+//! `json_to_csv_converter.log` in the configuration directory, to attach to a support
+//! request. On the command line they go to standard error, filtered by `--quiet` and
+//! `--verbose`, and to the `--log-file` if one is given. Events of the libraries the app
+//! uses are only kept from warnings on.
+
+use anyhow::{Context as _, Result};
+use eframe::egui;
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Name of the log file written in the configuration directory
+pub const LOG_FILE_NAME: &str = "json_to_csv_converter.log";
+
+/// Number of events kept for the log panel; older ones are dropped
+const MAX_LINES: usize = 2000;
+
+/// Events of the app from `level` on, and of libraries from warnings on
+fn targets(level: Level) -> Targets {
+    Targets::new()
+        .with_target(env!("CARGO_CRATE_NAME"), level)
+        .with_default(Level::WARN)
+}
+
+/// One event shown in the log panel
+pub struct LogLine {
+    /// Seconds since the app started
+    pub seconds: f64,
+    pub level: Level,
+    /// Module that logged the event, e.g. `conversion`
+    pub module: String,
+    /// Message followed by the other fields as `name=value`
+    pub text: String,
+}
+
+/// Log file the window appends events to while it is open
+#[derive(Clone, Default)]
+pub struct LogFile(Arc<Mutex<Option<File>>>);
+
+impl LogFile {
+    /// Starts appending to the log file in a directory
+    pub fn open(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create config directory {}", dir.display()))?;
+        let path = dir.join(LOG_FILE_NAME);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open log file {}", path.display()))?;
+        *self.0.lock().unwrap() = Some(file);
+        Ok(())
+    }
+
+    pub fn close(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut *self.0.lock().unwrap() {
+            Some(file) => file.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut *self.0.lock().unwrap() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = LogFile;
+
+    fn make_writer(&'a self) -> LogFile {
+        self.clone()
+    }
+}
+
+/// Events kept for the log panel, shared with the layer collecting them
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<VecDeque<LogLine>>>);
+
+/// Layer adding every event to a `LogBuffer`
+struct BufferLayer {
+    buffer: LogBuffer,
+    started: Instant,
+}
+
+impl<S: Subscriber> Layer<S> for BufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut text = FieldText::default();
+        event.record(&mut text);
+        let metadata = event.metadata();
+        let module = metadata.target();
+        let module = module
+            .strip_prefix(concat!(env!("CARGO_CRATE_NAME"), "::"))
+            .unwrap_or(module);
+        let mut lines = self.buffer.0.lock().unwrap();
+        if lines.len() >= MAX_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(LogLine {
+            seconds: self.started.elapsed().as_secs_f64(),
+            level: *metadata.level(),
+            module: module.to_string(),
+            text: text.message + text.fields.as_str(),
+        });
+    }
+}
+
+/// Renders the fields of an event as its message and `name=value` pairs
+#[derive(Default)]
+struct FieldText {
+    message: String,
+    fields: String,
+}
+
+impl Visit for FieldText {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // Writing to a String cannot fail
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Sets up logging for the window: debug events and up go to the log panel and, once it
+/// is opened, to `file`
+pub fn init_window(file: LogFile) -> LogPanel {
+    let buffer = LogBuffer::default();
+    let layer = BufferLayer {
+        buffer: buffer.clone(),
+        started: Instant::now(),
+    };
+    let file_layer = tracing_subscriber::fmt::layer().with_ansi(false).with_writer(file);
+    let installed = tracing_subscriber::registry()
+        .with(layer.with_filter(targets(Level::DEBUG)))
+        .with(file_layer.with_filter(targets(Level::DEBUG)))
+        .try_init();
+    if let Err(e) = installed {
+        eprintln!("Logging is unavailable: {}", e);
+    }
+    LogPanel {
+        buffer,
+        level: Level::INFO,
+        follow: true,
+    }
+}
+
+/// Sets up logging for the command line: events from `level` on go to standard error,
+/// and debug events and up to `log_file`
+pub fn init_cli(level: Level, log_file: Option<File>) {
+    let stderr = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .without_time()
+        .with_target(false);
+    let file = log_file.map(|file| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(Mutex::new(file))
+            .with_filter(targets(Level::DEBUG))
+    });
+    let installed = tracing_subscriber::registry()
+        .with(stderr.with_filter(targets(level)))
+        .with(file)
+        .try_init();
+    if let Err(e) = installed {
+        eprintln!("Logging is unavailable: {}", e);
+    }
+}
+
+/// Log panel of the window
+pub struct LogPanel {
+    buffer: LogBuffer,
+    /// Least severe level shown
+    level: Level,
+    /// Whether the list scrolls to new events
+    follow: bool,
+}
+
+impl LogPanel {
+    /// Displays the events of the chosen level and up, newest at the bottom
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Show:");
+            for (level, label) in [
+                (Level::ERROR, "Errors"),
+                (Level::WARN, "Warnings"),
+                (Level::INFO, "Info"),
+                (Level::DEBUG, "Debug"),
+            ] {
+                ui.selectable_value(&mut self.level, level, label);
+            }
+            ui.checkbox(&mut self.follow, "Follow");
+            if ui.button("Clear").clicked() {
+                self.buffer.0.lock().unwrap().clear();
+            }
+        });
+        ui.separator();
+
+        let lines = self.buffer.0.lock().unwrap();
+        let visuals = ui.visuals().clone();
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .stick_to_bottom(self.follow)
+            .show(ui, |ui| {
+                for line in lines.iter().filter(|line| line.level <= self.level) {
+                    let colour = match line.level {
+                        Level::ERROR => visuals.error_fg_color,
+                        Level::WARN => visuals.warn_fg_color,
+                        Level::INFO => visuals.text_color(),
+                        _ => visuals.weak_text_color(),
+                    };
+                    let text = format!(
+                        "{:>9.3}s {:<5} {}: {}",
+                        line.seconds, line.level, line.module, line.text
+                    );
+                    ui.label(egui::RichText::new(text).monospace().color(colour));
+                }
+            });
+    }
+}
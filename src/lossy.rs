@@ -0,0 +1,223 @@
+//! Lossy conversion warnings
+//!
+//! Some values do not survive the trip to CSV intact: objects and arrays are written as
+//! JSON text, integers beyond 2^53 lose digits in any program reading them as
+//! floating-point numbers, spreadsheets among them, and a column mixing strings, numbers
+//! and booleans leaves a loader no single type to pick. `LossTracker` notes such cells
+//! while the rows are written, per column and with the first affected rows; none of them
+//! stops the conversion.
+
+use serde_json::{Number, Value};
+
+/// Number of affected rows listed per column and issue
+const MAX_EXAMPLES: usize = 5;
+
+/// Largest integer a 64-bit float holds exactly, along with all smaller ones
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Kind flags of a cell; missing cells and nulls have none
+const STRING: u8 = 1;
+const NUMBER: u8 = 2;
+const BOOL: u8 = 4;
+const OBJECT: u8 = 8;
+const ARRAY: u8 = 16;
+/// Set along with `NUMBER` for numbers beyond float precision
+const IMPRECISE: u8 = 32;
+
+/// Names of the kind flags, in the order they are listed
+const KIND_NAMES: [(u8, &str); 5] = [
+    (STRING, "string"),
+    (NUMBER, "number"),
+    (BOOL, "boolean"),
+    (OBJECT, "object"),
+    (ARRAY, "array"),
+];
+
+/// What was lost in the cells of a column
+#[derive(Clone)]
+pub enum Loss {
+    /// Objects or arrays written as JSON text
+    Nested,
+    /// Numbers beyond the precision of a 64-bit float
+    Imprecise,
+    /// Cells of another type than the first non-empty one; holds every type found
+    MixedTypes(Vec<&'static str>),
+}
+
+impl Loss {
+    pub fn describe(&self) -> String {
+        match self {
+            Loss::Nested => "objects or arrays written as JSON".to_string(),
+            Loss::Imprecise => "numbers beyond float precision".to_string(),
+            Loss::MixedTypes(kinds) => format!("mixed types ({})", kinds.join(", ")),
+        }
+    }
+}
+
+/// Lossy cells of one column
+#[derive(Clone)]
+pub struct ColumnLoss {
+    pub column: String,
+    pub loss: Loss,
+    /// Number of affected cells
+    pub cells: usize,
+    /// Numbers of the first affected data rows
+    pub rows: Vec<usize>,
+}
+
+/// Lossy cells found by a conversion, by column
+#[derive(Default)]
+pub struct LossReport {
+    pub columns: Vec<ColumnLoss>,
+}
+
+impl LossReport {
+    /// Warning naming the affected columns, if there are any
+    pub fn summary(&self) -> Option<String> {
+        let mut columns: Vec<&str> = Vec::new();
+        for loss in &self.columns {
+            if !columns.contains(&loss.column.as_str()) {
+                columns.push(&loss.column);
+            }
+        }
+        if columns.is_empty() {
+            return None;
+        }
+        Some(format!("lossy values in {} columns ({})", columns.len(), columns.join(", ")))
+    }
+}
+
+/// Kind flags of every cell of a row, given the values of the output columns
+pub fn classify<'v>(cells: impl Iterator<Item = Option<&'v Value>>) -> Vec<u8> {
+    cells
+        .map(|cell| match cell {
+            None | Some(Value::Null) => 0,
+            Some(Value::String(_)) => STRING,
+            Some(Value::Number(number)) if imprecise(number) => NUMBER | IMPRECISE,
+            Some(Value::Number(_)) => NUMBER,
+            Some(Value::Bool(_)) => BOOL,
+            Some(Value::Object(_)) => OBJECT,
+            Some(Value::Array(_)) => ARRAY,
+        })
+        .collect()
+}
+
+/// Whether the kind flags of a cell, as returned by `classify`, are those of a number
+pub fn is_number(kind: u8) -> bool {
+    kind & NUMBER != 0
+}
+
+/// Whether a number has more digits than the nearest float keeps
+///
+/// Numbers keep their source text, so this compares its digits with those of the float
+/// read from it; integers count from beyond 2^53, where floats start skipping some.
+fn imprecise(number: &Number) -> bool {
+    if let Some(value) = number.as_u64() {
+        value > MAX_SAFE_INTEGER
+    } else if let Some(value) = number.as_i64() {
+        value.unsigned_abs() > MAX_SAFE_INTEGER
+    } else {
+        let text = number.to_string();
+        number
+            .as_f64()
+            .is_none_or(|value| significant_digits(&value.to_string()) != significant_digits(&text))
+    }
+}
+
+/// Digits of a number without its sign, exponent, point and surrounding zeros
+fn significant_digits(text: &str) -> String {
+    let mantissa = text.split(['e', 'E']).next().unwrap_or_default();
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    digits.trim_matches('0').to_string()
+}
+
+/// Affected cells of one issue in one column
+#[derive(Default)]
+struct Tally {
+    cells: usize,
+    rows: Vec<usize>,
+}
+
+impl Tally {
+    fn add(&mut self, row: usize) {
+        self.cells += 1;
+        if self.rows.len() < MAX_EXAMPLES {
+            self.rows.push(row);
+        }
+    }
+}
+
+#[derive(Default)]
+struct ColumnTrack {
+    /// Kind of the first non-empty cell
+    first: u8,
+    /// Kinds of all cells
+    seen: u8,
+    nested: Tally,
+    imprecise: Tally,
+    mixed: Tally,
+}
+
+/// Collects the lossy cells of the rows written
+#[derive(Default)]
+pub struct LossTracker {
+    columns: Vec<ColumnTrack>,
+}
+
+impl LossTracker {
+    pub fn new(columns: usize) -> Self {
+        Self {
+            columns: (0..columns).map(|_| ColumnTrack::default()).collect(),
+        }
+    }
+
+    /// Notes the cells of data row `row`, classified by `classify`
+    pub fn add(&mut self, row: usize, kinds: &[u8]) {
+        for (track, &kind) in self.columns.iter_mut().zip(kinds) {
+            if kind & (OBJECT | ARRAY) != 0 {
+                track.nested.add(row);
+            }
+            if kind & IMPRECISE != 0 {
+                track.imprecise.add(row);
+            }
+            let kind = kind & !IMPRECISE;
+            if kind == 0 {
+                continue;
+            }
+            if track.first == 0 {
+                track.first = kind;
+            } else if kind != track.first {
+                track.mixed.add(row);
+            }
+            track.seen |= kind;
+        }
+    }
+
+    /// Report of the affected columns, named by `headers`
+    pub fn finish(self, headers: &[String]) -> LossReport {
+        let mut columns = Vec::new();
+        for (track, column) in self.columns.into_iter().zip(headers) {
+            let kinds = KIND_NAMES
+                .iter()
+                .filter(|(kind, _)| track.seen & kind != 0)
+                .map(|(_, name)| *name)
+                .collect();
+            let issues = [
+                (Loss::Nested, track.nested),
+                (Loss::Imprecise, track.imprecise),
+                (Loss::MixedTypes(kinds), track.mixed),
+            ];
+            for (loss, tally) in issues {
+                if tally.cells > 0 {
+                    columns.push(ColumnLoss {
+                        column: column.clone(),
+                        loss,
+                        cells: tally.cells,
+                        rows: tally.rows,
+                    });
+                }
+            }
+        }
+        LossReport { columns }
+    }
+}
@@ -1,307 +1,377 @@
 //! JSON to CSV Converter
-//! 
+//!
 //! A professional GUI application that converts JSON files to CSV format with advanced features.
 //! This application provides a user-friendly interface for converting JSON data to CSV format,
 //! with support for customization, preview, and various export options.
 
+mod benchmark;
+mod checkpoint;
+mod cli;
+mod cloud;
+mod config;
+mod conversion;
+mod database;
+mod dialect;
+mod dictionary;
+mod document;
+mod duplicates;
+mod formats;
+mod geojson;
+mod input;
+mod jobs;
+mod locale;
+mod logging;
+mod lossy;
+mod notifications;
+mod output;
+mod palette;
+mod preset;
+mod replace;
+mod schema;
+mod script;
+mod secrets;
+mod server;
+mod session;
+mod settings;
+mod sheets;
+mod snapshot;
+mod stats;
+mod summary;
+mod theme;
+mod transforms;
+mod update;
+mod validation;
+mod wizard;
+
+use config::PanelSizes;
+use document::Document;
+use jobs::{JobQueue, JobsDock};
+use logging::{LogFile, LogPanel};
 use eframe::egui;
 use rfd::FileDialog;
-use serde_json::Value;
 use std::path::PathBuf;
 use anyhow::Result;
+use std::collections::VecDeque;
+use palette::{Command, CommandPalette};
+use session::Session;
+use theme::{Theme, UiFont};
+use wizard::{Wizard, WizardRequest};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
 
 /// Maximum number of recent files to keep in history
 const MAX_RECENT_FILES: usize = 5;
 
-/// Tracks the progress and status of the conversion process
-#[derive(Default)]
-struct ConversionProgress {
-    /// Current status message
-    status: String,
-    /// Progress value between 0.0 and 1.0
-    progress: f32,
-    /// Whether a conversion is currently in progress
-    is_converting: bool,
-}
-
-/// Application settings and configuration
-#[derive(Default, Clone)]
-struct Settings {
-    /// Whether dark mode is enabled
-    dark_mode: bool,
-    /// CSV delimiter character
-    delimiter: String,
-    /// Whether to include headers in the CSV output
-    include_headers: bool,
-    /// Whether to quote fields in the CSV output
-    quote_fields: bool,
-    /// Maximum number of rows to show in preview
-    max_preview_rows: usize,
-}
-
 /// Main application state
 struct JsonToCsvApp {
-    /// Path to the currently loaded JSON file
-    json_path: Option<PathBuf>,
-    /// Path to the saved CSV file
-    csv_path: Option<PathBuf>,
-    /// Current application status message
-    status: String,
-    /// Content of the loaded JSON file
-    json_content: Option<String>,
-    /// Generated CSV content
-    csv_content: Option<String>,
-    /// Preview data for the grid view
-    preview_data: Option<Vec<Vec<String>>>,
-    /// Progress tracking for conversion
-    progress: Arc<Mutex<ConversionProgress>>,
-    /// Whether to show the preview panel
-    show_preview: bool,
-    /// Current error message if any
-    error_message: Option<String>,
-    /// Application settings
-    settings: Settings,
+    /// Open document tabs; there is always at least one
+    documents: Vec<Document>,
+    /// Index of the tab currently shown
+    active_tab: usize,
+    /// Whether dark mode is enabled
+    dark_mode: bool,
+    /// Colours and fonts on top of the dark or light visuals
+    theme: Theme,
+    /// Why the theme could not be loaded, saved or fully applied
+    theme_error: Option<String>,
     /// List of recently opened files
     recent_files: VecDeque<PathBuf>,
     /// Whether to show the settings panel
     show_settings: bool,
-    /// Current search query for preview
-    search_query: String,
-    /// Selected columns for export
-    selected_columns: Vec<String>,
-    /// All available columns from the JSON
-    all_columns: Vec<String>,
+    /// Queue of background conversion jobs
+    jobs: JobQueue,
+    /// Whether to show the jobs panel
+    show_jobs: bool,
+    /// Where the jobs panel is docked
+    jobs_dock: JobsDock,
+    /// Sizes of the settings, preview and jobs panels
+    panel_sizes: PanelSizes,
+    /// Whether long conversions announce their end with a desktop notification
+    notifications: bool,
+    /// Where the configuration is saved; `None` when there is no config directory
+    config_dir: Option<PathBuf>,
+    /// Session left behind by a crash, waiting for the user to restore or discard it
+    unfinished_session: Option<Session>,
+    /// Time of the latest session autosave
+    last_autosave: Instant,
+    /// Content of the latest session autosave, to skip saving an unchanged session
+    autosaved_session: String,
+    /// Whether to look for a newer release at startup
+    check_for_updates: bool,
+    /// Newer release found by the update check, filled in by its thread
+    available_update: Arc<Mutex<Option<update::Release>>>,
+    /// Whether the update banner was closed
+    update_dismissed: bool,
+    /// Ctrl+P command palette
+    palette: CommandPalette,
+    /// Step-by-step conversion window
+    wizard: Wizard,
+    /// Events logged since startup
+    log: LogPanel,
+    /// Whether to show the log window
+    show_log: bool,
+    /// Whether events are also written to the log file
+    log_to_file: bool,
+    /// Log file in the config directory, open while `log_to_file` is on
+    log_file: LogFile,
 }
 
-impl Default for JsonToCsvApp {
-    fn default() -> Self {
-        Self {
-            json_path: None,
-            csv_path: None,
-            status: "Ready".to_string(),
-            json_content: None,
-            csv_content: None,
-            preview_data: None,
-            progress: Arc::new(Mutex::new(ConversionProgress::default())),
-            show_preview: false,
-            error_message: None,
-            settings: Settings {
-                dark_mode: false,
-                delimiter: ",".to_string(),
-                include_headers: true,
-                quote_fields: true,
-                max_preview_rows: 100,
-            },
-            recent_files: VecDeque::new(),
+impl JsonToCsvApp {
+    /// Creates a new instance of the application from the saved configuration, with the
+    /// files given at startup opened, each in its own tab
+    fn new(cc: &eframe::CreationContext<'_>, startup: cli::Startup) -> Self {
+        let log_file = LogFile::default();
+        let log = logging::init_window(log_file.clone());
+        let config_dir = config::config_dir(startup.portable);
+        let config = config_dir.as_deref().map(config::load).unwrap_or_default();
+        let unfinished_session = config_dir.as_deref().and_then(session::load_unfinished);
+
+        let mut app = Self {
+            documents: vec![Document::new(config.settings)],
+            active_tab: 0,
+            dark_mode: config.dark_mode,
+            theme: config.theme,
+            theme_error: None,
+            recent_files: config.recent_files.into_iter().take(MAX_RECENT_FILES).collect(),
             show_settings: false,
-            search_query: String::new(),
-            selected_columns: Vec::new(),
-            all_columns: Vec::new(),
+            jobs: JobQueue::default(),
+            show_jobs: false,
+            jobs_dock: config.jobs_dock,
+            panel_sizes: config.panel_sizes,
+            notifications: config.notifications,
+            config_dir,
+            unfinished_session,
+            last_autosave: Instant::now(),
+            autosaved_session: String::new(),
+            check_for_updates: config.check_for_updates,
+            available_update: Arc::new(Mutex::new(None)),
+            update_dismissed: false,
+            palette: CommandPalette::default(),
+            wizard: Wizard::default(),
+            log,
+            show_log: false,
+            log_to_file: config.log_to_file,
+            log_file,
+        };
+        app.apply_theme(&cc.egui_ctx);
+        app.update_log_file();
+        info!(version = env!("CARGO_PKG_VERSION"), "Started");
+        if app.check_for_updates {
+            let available_update = Arc::clone(&app.available_update);
+            let ctx = cc.egui_ctx.clone();
+            thread::spawn(move || match update::check() {
+                Ok(release) => {
+                    *available_update.lock().unwrap() = release;
+                    ctx.request_repaint();
+                }
+                // Being offline is no reason to bother the user
+                Err(e) => warn!("{:#}", e),
+            });
+        }
+        let convert = startup.convert;
+        for path in startup.files {
+            app.open_file(path);
+            let document = &mut app.documents[app.active_tab];
+            // Already started when convert-on-load is on; not loaded while the large file
+            // dialog is open or after an error
+            let loaded = document.json_content.is_some() || document.stream_input;
+            if convert && loaded && document.job_id.is_none() {
+                document.convert_to_csv(&mut app.jobs);
+            }
         }
+        app
     }
-}
 
-impl JsonToCsvApp {
-    /// Creates a new instance of the application
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Default::default()
+    /// Saves the open tabs every few seconds, so they can be restored after a crash
+    ///
+    /// Waits while an unfinished session is offered for restoring, as it would be
+    /// overwritten otherwise.
+    fn autosave_session(&mut self) {
+        let Some(dir) = &self.config_dir else {
+            return;
+        };
+        let due = self.last_autosave.elapsed() >= session::AUTOSAVE_INTERVAL;
+        if self.unfinished_session.is_some() || !due {
+            return;
+        }
+        self.last_autosave = Instant::now();
+        let session = Session {
+            tabs: self.documents.iter().map(Document::tab_state).collect(),
+            active_tab: self.active_tab,
+        };
+        match session::autosave(dir, &session, &self.autosaved_session) {
+            Ok(content) => self.autosaved_session = content,
+            Err(e) => error!("{:#}", e),
+        }
     }
 
-    /// Opens a file dialog to select a JSON file and loads its contents
-    fn select_json_file(&mut self) {
-        if let Some(path) = FileDialog::new()
-            .add_filter("JSON", &["json"])
-            .pick_file() 
-        {
-            self.json_path = Some(path.clone());
-            match std::fs::read_to_string(&path) {
-                Ok(content) => {
-                    self.json_content = Some(content);
-                    self.status = "JSON file loaded successfully".to_string();
-                    self.error_message = None;
-                    self.preview_data = None;
-                    
-                    // Add to recent files
-                    if !self.recent_files.contains(&path) {
-                        if self.recent_files.len() >= MAX_RECENT_FILES {
-                            self.recent_files.pop_back();
-                        }
-                        self.recent_files.push_front(path);
+    /// Displays the offer to restore a session that ended unexpectedly
+    fn show_restore_dialog(&mut self, ctx: &egui::Context) {
+        let Some(unfinished) = &self.unfinished_session else {
+            return;
+        };
+
+        let mut restore = None;
+        egui::Window::new("Restore Session?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "The last session ended unexpectedly with {} tab(s) open.",
+                    unfinished.tabs.len()
+                ));
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Restore").clicked() {
+                        restore = Some(true);
                     }
-                }
-                Err(e) => {
-                    self.error_message = Some(format!("Failed to read JSON file: {}", e));
-                    self.status = "Error loading file".to_string();
-                }
+                    if ui.button("Discard").clicked() {
+                        restore = Some(false);
+                    }
+                });
+            });
+
+        let Some(restore) = restore else {
+            return;
+        };
+        let unfinished = self.unfinished_session.take().unwrap();
+        if let Some(dir) = &self.config_dir {
+            session::clear(dir);
+        }
+        if restore {
+            // An empty first tab is replaced rather than kept next to the restored ones
+            let keep_first = self.documents.len() > 1 || self.documents[0].json_path.is_some();
+            if !keep_first {
+                self.documents.clear();
+            }
+            let offset = self.documents.len();
+            for tab in unfinished.tabs {
+                self.documents.push(Document::restore(tab, &mut self.jobs));
             }
+            if self.documents.is_empty() {
+                self.documents.push(Document::new(Default::default()));
+            }
+            self.active_tab = (offset + unfinished.active_tab).min(self.documents.len() - 1);
         }
     }
 
-    /// Converts the loaded JSON content to CSV format
-    /// This function runs the conversion in a separate thread to keep the UI responsive
-    fn convert_to_csv(&mut self) {
-        let json_content = match &self.json_content {
-            Some(content) => content.clone(),
-            None => {
-                self.error_message = Some("No JSON content loaded".to_string());
-                return;
-            }
+    /// Saves the preferences, recent files and the settings of the active tab
+    fn save_config(&mut self) {
+        let Some(dir) = self.config_dir.clone() else {
+            return;
+        };
+        let config = config::Config {
+            dark_mode: self.dark_mode,
+            theme: self.theme.clone(),
+            notifications: self.notifications,
+            jobs_dock: self.jobs_dock,
+            check_for_updates: self.check_for_updates,
+            log_to_file: self.log_to_file,
+            panel_sizes: self.panel_sizes,
+            recent_files: self.recent_files.iter().cloned().collect(),
+            settings: self.active_document().settings.clone(),
         };
+        if let Err(e) = config::save(&dir, &config) {
+            error!("{:#}", e);
+        }
+    }
 
-        let progress = Arc::clone(&self.progress);
-        let mut progress_guard = progress.lock().unwrap();
-        progress_guard.is_converting = true;
-        progress_guard.progress = 0.0;
-        progress_guard.status = "Starting conversion...".to_string();
-        drop(progress_guard);
-
-        let settings = self.settings.clone();
-        let selected_columns = self.selected_columns.clone();
-
-        thread::spawn(move || {
-            let mut progress_guard = progress.lock().unwrap();
-            progress_guard.progress = 0.2;
-            progress_guard.status = "Parsing JSON...".to_string();
-            drop(progress_guard);
-
-            let json_value: Value = match serde_json::from_str(&json_content) {
-                Ok(value) => value,
-                Err(e) => {
-                    let mut progress_guard = progress.lock().unwrap();
-                    progress_guard.status = format!("JSON parsing error: {}", e);
-                    progress_guard.is_converting = false;
-                    return;
+    /// Opens or closes the log file to match `log_to_file`
+    fn update_log_file(&mut self) {
+        match &self.config_dir {
+            Some(dir) if self.log_to_file => {
+                if let Err(e) = self.log_file.open(dir) {
+                    error!("{:#}", e);
+                    self.log_to_file = false;
                 }
-            };
-
-            let mut progress_guard = progress.lock().unwrap();
-            progress_guard.progress = 0.4;
-            progress_guard.status = "Converting to CSV...".to_string();
-            drop(progress_guard);
-
-            // Configure CSV writer with user settings
-            let mut csv_writer = csv::WriterBuilder::new()
-                .delimiter(settings.delimiter.as_bytes()[0])
-                .quote_style(if settings.quote_fields {
-                    csv::QuoteStyle::Necessary
-                } else {
-                    csv::QuoteStyle::Never
-                })
-                .from_writer(vec![]);
-
-            let mut preview_data = Vec::new();
-
-            match json_value {
-                Value::Array(arr) => {
-                    if let Some(first) = arr.first() {
-                        if let Value::Object(obj) = first {
-                            // Get headers based on selection or all columns
-                            let headers: Vec<String> = if selected_columns.is_empty() {
-                                obj.keys().cloned().collect()
-                            } else {
-                                selected_columns
-                            };
-
-                            // Write headers if enabled
-                            if settings.include_headers {
-                                csv_writer.write_record(&headers).unwrap();
-                                preview_data.push(headers.clone());
-                            }
+            }
+            _ => self.log_file.close(),
+        }
+    }
 
-                            // Write data rows
-                            for (i, item) in arr.iter().enumerate() {
-                                if let Value::Object(obj) = item {
-                                    let values: Vec<String> = headers.iter()
-                                        .map(|key| obj.get(key)
-                                            .map(|v| v.to_string())
-                                            .unwrap_or_default())
-                                        .collect();
-                                    csv_writer.write_record(&values).unwrap();
-                                    if i < settings.max_preview_rows {
-                                        preview_data.push(values);
-                                    }
-                                }
-
-                                // Update progress
-                                let mut progress_guard = progress.lock().unwrap();
-                                progress_guard.progress = 0.4 + (i as f32 / arr.len() as f32) * 0.5;
-                                drop(progress_guard);
-                            }
-                        }
-                    }
-                }
-                Value::Object(obj) => {
-                    // Handle single object case
-                    let headers: Vec<String> = if selected_columns.is_empty() {
-                        obj.keys().cloned().collect()
-                    } else {
-                        selected_columns
-                    };
-
-                    if settings.include_headers {
-                        csv_writer.write_record(&headers).unwrap();
-                        preview_data.push(headers.clone());
-                    }
+    /// The tab currently shown
+    fn active_document(&mut self) -> &mut Document {
+        &mut self.documents[self.active_tab]
+    }
 
-                    let values: Vec<String> = headers.iter()
-                        .map(|key| obj.get(key)
-                            .map(|v| v.to_string())
-                            .unwrap_or_default())
-                        .collect();
-                    csv_writer.write_record(&values).unwrap();
-                    preview_data.push(values);
-                }
-                _ => {
-                    let mut progress_guard = progress.lock().unwrap();
-                    progress_guard.status = "Unsupported JSON structure".to_string();
-                    progress_guard.is_converting = false;
-                    return;
-                }
-            }
+    /// Opens a new empty tab with the settings of the active one and switches to it
+    fn new_tab(&mut self) {
+        let settings = self.active_document().settings.clone();
+        self.documents.push(Document::new(settings));
+        self.active_tab = self.documents.len() - 1;
+    }
 
-            let mut progress_guard = progress.lock().unwrap();
-            progress_guard.progress = 0.9;
-            progress_guard.status = "Finalizing...".to_string();
-            drop(progress_guard);
-
-            match String::from_utf8(csv_writer.into_inner().unwrap()) {
-                Ok(csv_data) => {
-                    let mut progress_guard = progress.lock().unwrap();
-                    progress_guard.progress = 1.0;
-                    progress_guard.status = "Conversion completed successfully".to_string();
-                    progress_guard.is_converting = false;
-                }
-                Err(e) => {
-                    let mut progress_guard = progress.lock().unwrap();
-                    progress_guard.status = format!("CSV generation error: {}", e);
-                    progress_guard.is_converting = false;
+    /// Closes a tab, keeping at least one empty tab open
+    fn close_tab(&mut self, index: usize) {
+        self.documents.remove(index);
+        if self.documents.is_empty() {
+            self.documents.push(Document::new(Default::default()));
+        }
+        if self.active_tab >= index && self.active_tab > 0 {
+            self.active_tab -= 1;
+        }
+    }
+
+    /// Opens a file dialog to select a JSON file and loads its contents
+    fn select_json_file(&mut self) {
+        if let Some(path) = FileDialog::new()
+            .add_filter("Supported files", input::INPUT_EXTENSIONS)
+            .add_filter("JSON", &["json", "json5"])
+            .add_filter("YAML", &["yaml", "yml"])
+            .add_filter("TOML", &["toml"])
+            .add_filter("CSV", &["csv", "tsv"])
+            .pick_file()
+        {
+            self.open_file(path);
+        }
+    }
+
+    /// Loads a file into the active tab, or into a new tab when the active one already
+    /// holds a file, and remembers it in the recent files list
+    fn open_file(&mut self, path: PathBuf) {
+        if self.active_document().json_path.is_some() {
+            self.new_tab();
+        }
+        let document = &mut self.documents[self.active_tab];
+        if document.load_json_file(path.clone(), &mut self.jobs) {
+            // Add to recent files
+            if !self.recent_files.contains(&path) {
+                if self.recent_files.len() >= MAX_RECENT_FILES {
+                    self.recent_files.pop_back();
                 }
+                self.recent_files.push_front(path);
+                self.save_config();
             }
-        });
+        }
     }
 
-    /// Saves the converted CSV content to a file
-    fn save_csv_file(&mut self) {
-        if let Some(content) = &self.csv_content {
-            if let Some(path) = FileDialog::new()
-                .add_filter("CSV", &["csv"])
-                .save_file() 
-            {
-                match std::fs::write(&path, content) {
-                    Ok(_) => {
-                        self.csv_path = Some(path);
-                        self.status = "CSV file saved successfully".to_string();
-                        self.error_message = None;
-                    }
-                    Err(e) => {
-                        self.error_message = Some(format!("Failed to save CSV file: {}", e));
-                        self.status = "Error saving file".to_string();
-                    }
+    /// Displays the tab bar with one entry per document
+    fn show_tab_bar(&mut self, ui: &mut egui::Ui) {
+        let mut close = None;
+        ui.horizontal_wrapped(|ui| {
+            for (index, document) in self.documents.iter().enumerate() {
+                let title = if document.is_converting() {
+                    format!("⏳ {}", document.title())
+                } else {
+                    document.title()
+                };
+                if ui.selectable_label(index == self.active_tab, title).clicked() {
+                    self.active_tab = index;
+                }
+                if ui.small_button("×").on_hover_text("Close tab").clicked() {
+                    close = Some(index);
                 }
+                ui.separator();
             }
+            if ui.button("➕").on_hover_text("New tab").clicked() {
+                self.new_tab();
+            }
+        });
+        if let Some(index) = close {
+            self.close_tab(index);
         }
     }
 
@@ -310,215 +380,386 @@ impl JsonToCsvApp {
         ui.heading("Settings");
         ui.add_space(10.0);
 
-        // Theme toggle
-        if ui.checkbox(&mut self.settings.dark_mode, "Dark Mode").changed() {
-            // Apply theme change
-            if self.settings.dark_mode {
-                ui.ctx().set_visuals(egui::Visuals::dark());
-            } else {
-                ui.ctx().set_visuals(egui::Visuals::light());
-            }
-        }
-
-        ui.add_space(10.0);
+        self.show_theme_editor(ui);
 
-        // CSV Settings
-        ui.heading("CSV Settings");
-        ui.add_space(5.0);
+        ui.checkbox(&mut self.notifications, "Desktop Notifications")
+            .on_hover_text("Notify when a long conversion finishes or fails");
+        ui.checkbox(&mut self.check_for_updates, "Check for Updates")
+            .on_hover_text("Look for a newer release on GitHub at startup");
+        let log_file = match &self.config_dir {
+            Some(dir) => format!("Append events to {}", dir.join(logging::LOG_FILE_NAME).display()),
+            None => "There is no config directory to write the log file to".to_string(),
+        };
+        let response = ui
+            .add_enabled(self.config_dir.is_some(), egui::Checkbox::new(&mut self.log_to_file, "Write Log File"))
+            .on_hover_text(log_file)
+            .on_disabled_hover_text("There is no config directory to write the log file to");
+        if response.changed() {
+            self.update_log_file();
+        }
 
-        // Delimiter selection
-        ui.horizontal(|ui| {
-            ui.label("Delimiter:");
-            egui::ComboBox::from_label("")
-                .selected_text(&self.settings.delimiter)
-                .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.settings.delimiter, ",".to_string(), "Comma (,)");
-                    ui.selectable_value(&mut self.settings.delimiter, ";".to_string(), "Semicolon (;)");
-                    ui.selectable_value(&mut self.settings.delimiter, "\t".to_string(), "Tab");
-                });
-        });
+        self.active_document().show_settings_panel(ui);
+    }
 
-        ui.checkbox(&mut self.settings.include_headers, "Include Headers");
-        ui.checkbox(&mut self.settings.quote_fields, "Quote Fields");
-        
-        ui.add_space(10.0);
-        ui.add(egui::Slider::new(&mut self.settings.max_preview_rows, 10..=1000)
-            .text("Max Preview Rows"));
+    /// Applies the theme on top of the selected dark or light visuals
+    fn apply_theme(&mut self, ctx: &egui::Context) {
+        self.theme_error = self
+            .theme
+            .apply(ctx, self.dark_mode)
+            .err()
+            .map(|e| format!("{:#}", e));
+    }
 
-        // Column Selection
-        if !self.all_columns.is_empty() {
-            ui.add_space(10.0);
-            ui.heading("Column Selection");
-            ui.add_space(5.0);
+    /// Displays the theme options: dark mode, colours, font and text size
+    fn show_theme_editor(&mut self, ui: &mut egui::Ui) {
+        let before = (self.dark_mode, self.theme.clone());
+        egui::CollapsingHeader::new("Theme").default_open(true).show(ui, |ui| {
+            ui.checkbox(&mut self.dark_mode, "Dark Mode");
+
+            egui::Grid::new("theme_colours").show(ui, |ui| {
+                for (label, colour) in [
+                    ("Accent:", &mut self.theme.accent),
+                    ("Striped Rows:", &mut self.theme.stripe),
+                ] {
+                    ui.label(label);
+                    let mut custom = colour.is_some();
+                    if ui.checkbox(&mut custom, "Custom").changed() {
+                        *colour = custom.then_some([128, 128, 128]);
+                    }
+                    if let Some(rgb) = colour {
+                        ui.color_edit_button_srgb(rgb);
+                    }
+                    ui.end_row();
+                }
+            });
 
-            egui::ScrollArea::vertical()
-                .max_height(200.0)
-                .show(ui, |ui| {
-                    for column in &self.all_columns {
-                        let mut is_selected = self.selected_columns.contains(column);
-                        if ui.checkbox(&mut is_selected, column).changed() {
-                            if is_selected {
-                                self.selected_columns.push(column.clone());
-                            } else {
-                                self.selected_columns.retain(|c| c != column);
+            ui.horizontal(|ui| {
+                ui.label("Font:");
+                egui::ComboBox::from_id_source("ui_font")
+                    .selected_text(match &self.theme.font {
+                        UiFont::Default => "Default".to_string(),
+                        UiFont::Monospace => "Monospace".to_string(),
+                        UiFont::File(path) => path
+                            .file_name()
+                            .map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned()),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.theme.font, UiFont::Default, "Default");
+                        ui.selectable_value(&mut self.theme.font, UiFont::Monospace, "Monospace");
+                        if ui.selectable_label(matches!(self.theme.font, UiFont::File(_)), "From File...").clicked() {
+                            if let Some(path) = FileDialog::new()
+                                .add_filter("Fonts", &["ttf", "otf", "ttc"])
+                                .pick_file()
+                            {
+                                self.theme.font = UiFont::File(path);
                             }
                         }
+                    });
+            });
+            ui.add(egui::Slider::new(&mut self.theme.text_scale, 0.75..=2.0).text("Text Size"));
+
+            ui.horizontal(|ui| {
+                if ui.button("Load Theme...").clicked() {
+                    if let Some(path) = FileDialog::new().add_filter("Theme", &["json"]).pick_file() {
+                        match theme::load(&path) {
+                            Ok(theme) => self.theme = theme,
+                            Err(e) => self.theme_error = Some(format!("{:#}", e)),
+                        }
                     }
-                });
+                }
+                if ui.button("Save Theme...").clicked() {
+                    if let Some(path) = FileDialog::new()
+                        .add_filter("Theme", &["json"])
+                        .set_file_name("theme.json")
+                        .save_file()
+                    {
+                        if let Err(e) = theme::save(&path, &self.theme) {
+                            self.theme_error = Some(format!("{:#}", e));
+                        }
+                    }
+                }
+                if ui.button("Reset").clicked() {
+                    self.theme = Theme::default();
+                }
+            });
+            if let Some(error) = &self.theme_error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+        if before != (self.dark_mode, self.theme.clone()) {
+            self.apply_theme(ui.ctx());
         }
     }
 
+    /// Runs a command picked in the command palette
+    fn run_command(&mut self, command: Command, ctx: &egui::Context) {
+        match command {
+            Command::OpenFile => self.select_json_file(),
+            Command::Convert => {
+                let document = &mut self.documents[self.active_tab];
+                document.convert_to_csv(&mut self.jobs);
+            }
+            Command::CancelConversion => {
+                if let Some(id) = self.active_document().job_id {
+                    self.jobs.cancel(id);
+                }
+            }
+            Command::Save => self.active_document().save_csv_file(),
+            Command::LoadPreset => self.active_document().load_preset(),
+            Command::SavePreset => self.active_document().save_preset(),
+            Command::FindReplace => self.active_document().find_replace.toggle(),
+            Command::Wizard => self.wizard.toggle(),
+            Command::TogglePreview => {
+                let document = self.active_document();
+                document.show_preview = !document.show_preview;
+            }
+            Command::ToggleSettings => self.show_settings = !self.show_settings,
+            Command::ToggleJobs => self.show_jobs = !self.show_jobs,
+            Command::ToggleLog => self.show_log = !self.show_log,
+            Command::ToggleDarkMode => {
+                self.dark_mode = !self.dark_mode;
+                self.apply_theme(ctx);
+            }
+            Command::NewTab => self.new_tab(),
+            Command::CloseTab => self.close_tab(self.active_tab),
+            Command::NextTab => self.active_tab = (self.active_tab + 1) % self.documents.len(),
+            Command::PreviousTab => {
+                self.active_tab = (self.active_tab + self.documents.len() - 1) % self.documents.len();
+            }
+        }
+    }
+
+    /// Displays the jobs panel header and the list of jobs
+    fn show_jobs_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Jobs");
+            if ui.button("Clear Finished").clicked() {
+                self.jobs.clear_finished();
+            }
+            ui.label("Dock:");
+            ui.selectable_value(&mut self.jobs_dock, JobsDock::Right, "Right");
+            ui.selectable_value(&mut self.jobs_dock, JobsDock::Bottom, "Bottom");
+        });
+        ui.separator();
+        self.jobs.show(ui);
+    }
+
+    /// Displays the banner announcing a newer release, until it is closed
+    fn show_update_banner(&mut self, ctx: &egui::Context) {
+        if self.update_dismissed {
+            return;
+        }
+        let available_update = self.available_update.lock().unwrap();
+        let Some(release) = available_update.as_ref() else {
+            return;
+        };
+
+        let mut dismissed = false;
+        egui::TopBottomPanel::top("update_banner").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "Version {} is available (you have {}).",
+                    release.version,
+                    env!("CARGO_PKG_VERSION")
+                ));
+                ui.hyperlink_to("Download", &release.url);
+                if ui.small_button("×").on_hover_text("Dismiss").clicked() {
+                    dismissed = true;
+                }
+            });
+        });
+        drop(available_update);
+        self.update_dismissed = dismissed;
+    }
+
     /// Displays the recent files panel
     fn show_recent_files(&mut self, ui: &mut egui::Ui) {
         if !self.recent_files.is_empty() {
             ui.heading("Recent Files");
             ui.add_space(5.0);
 
+            let mut clicked = None;
             for path in &self.recent_files {
                 if ui.button(path.display().to_string()).clicked() {
-                    self.json_path = Some(path.clone());
-                    if let Ok(content) = std::fs::read_to_string(path) {
-                        self.json_content = Some(content);
-                        self.status = "JSON file loaded successfully".to_string();
-                        self.error_message = None;
-                        self.preview_data = None;
-                    }
+                    clicked = Some(path.clone());
                 }
             }
+            if let Some(path) = clicked {
+                self.open_file(path);
+            }
         }
     }
 }
 
 impl eframe::App for JsonToCsvApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_config();
+        // Only a session that did not exit cleanly is offered for restoring
+        if let Some(dir) = &self.config_dir {
+            if self.unfinished_session.is_none() {
+                session::clear(dir);
+            }
+        }
+    }
+
     /// Main update function that handles the UI rendering and user interactions
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                // Main content
-                ui.vertical(|ui| {
-                    ui.heading("JSON to CSV Converter");
-                    ui.add_space(20.0);
-
-                    // File selection
-                    if ui.button("Select JSON File").clicked() {
-                        self.select_json_file();
-                    }
+        // Conversions run as background jobs, so start queued ones, pick up finished
+        // results and keep repainting while any of them is busy
+        self.jobs.poll();
+        for job in self.jobs.take_finished() {
+            if self.notifications {
+                notifications::notify_finished(&job);
+            }
+        }
+        for document in &mut self.documents {
+            document.poll_conversion();
+        }
+        let background_work = self
+            .documents
+            .iter()
+            .any(|document| {
+                document.uploading || document.exporting || document.analyzing || document.sampling
+            });
+        if self.jobs.is_busy() || background_work {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
 
-                    if let Some(path) = &self.json_path {
-                        ui.label(format!("Selected JSON file: {}", path.display()));
-                    }
+        self.autosave_session();
+        self.show_restore_dialog(ctx);
 
-                    ui.add_space(10.0);
+        let palette_shortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::P);
+        if ctx.input_mut(|i| i.consume_shortcut(&palette_shortcut)) {
+            self.palette.toggle();
+        }
+        let find_shortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::H);
+        if ctx.input_mut(|i| i.consume_shortcut(&find_shortcut)) {
+            self.active_document().find_replace.toggle();
+        }
+        if let Some(command) = self.palette.show(ctx) {
+            self.run_command(command, ctx);
+        }
+        match self.wizard.show(ctx, &mut self.documents[self.active_tab]) {
+            Some(WizardRequest::OpenFile) => self.select_json_file(),
+            Some(WizardRequest::Convert) => {
+                let document = &mut self.documents[self.active_tab];
+                document.convert_to_csv(&mut self.jobs);
+            }
+            Some(WizardRequest::Save) => self.active_document().save_csv_file(),
+            None => {}
+        }
 
-                    // Conversion button and progress
-                    let progress = self.progress.lock().unwrap();
-                    let is_converting = progress.is_converting;
-                    let progress_value = progress.progress;
-                    let status = progress.status.clone();
-                    drop(progress);
+        let document = &mut self.documents[self.active_tab];
+        document.show_dialogs(ctx, &mut self.jobs);
 
-                    if !is_converting {
-                        if ui.button("Convert to CSV").clicked() {
-                            self.convert_to_csv();
-                        }
-                    }
+        // Files dropped onto the window are loaded like picked ones
+        let dropped: Vec<PathBuf> = ctx.input(|i| {
+            i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect()
+        });
+        for path in dropped {
+            self.open_file(path);
+        }
 
-                    // Progress bar
-                    if is_converting {
-                        ui.add_space(10.0);
-                        let progress_bar = egui::ProgressBar::new(progress_value)
-                            .show_percentage()
-                            .animate(true);
-                        ui.add(progress_bar);
-                        ui.label(&status);
-                    }
+        // Settings toggle and tab bar in the top bar
+        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("🧭 Wizard").on_hover_text("Convert a file step by step").clicked() {
+                    self.wizard.toggle();
+                }
+                ui.checkbox(&mut self.show_settings, "⚙️ Settings");
+                ui.checkbox(&mut self.show_jobs, "Jobs");
+                ui.checkbox(&mut self.show_log, "Log");
+            });
+            self.show_tab_bar(ui);
+        });
 
-                    // Preview controls
-                    if let Some(_content) = &self.csv_content {
-                        ui.add_space(10.0);
-                        if ui.button("Save CSV File").clicked() {
-                            self.save_csv_file();
-                        }
+        self.show_update_banner(ctx);
 
-                        ui.horizontal(|ui| {
-                            ui.checkbox(&mut self.show_preview, "Show Preview");
-                            if self.show_preview {
-                                ui.text_edit_singleline(&mut self.search_query);
-                                if ui.button("🔍").clicked() {
-                                    // TODO: Implement search functionality
-                                }
-                            }
-                        });
-                    }
+        egui::Window::new("Log")
+            .open(&mut self.show_log)
+            .default_size([600.0, 300.0])
+            .show(ctx, |ui| self.log.show(ui));
 
-                    // Preview window
-                    if self.show_preview {
-                        if let Some(preview_data) = &self.preview_data {
-                            ui.add_space(10.0);
-                            egui::ScrollArea::vertical()
-                                .max_height(200.0)
-                                .show(ui, |ui| {
-                                    egui::Grid::new("preview_grid")
-                                        .striped(true)
-                                        .show(ui, |ui| {
-                                            for row in preview_data {
-                                                for cell in row {
-                                                    ui.label(cell);
-                                                }
-                                                ui.end_row();
-                                            }
-                                        });
-                                });
-                        }
-                    }
+        // Bottom panel for recent files
+        egui::TopBottomPanel::bottom("recent_files").show(ctx, |ui| {
+            self.show_recent_files(ui);
+        });
 
-                    // Error message
-                    if let Some(error) = &self.error_message {
-                        ui.add_space(10.0);
-                        ui.colored_label(egui::Color32::RED, error);
-                    }
+        // Jobs panel, docked on the side or at the bottom. The panels are resizable by
+        // dragging their edge, and their sizes are remembered in the configuration.
+        if self.show_jobs {
+            match self.jobs_dock {
+                JobsDock::Right => {
+                    let panel = egui::SidePanel::right("jobs_panel")
+                        .resizable(true)
+                        .default_width(self.panel_sizes.jobs_width)
+                        .show(ctx, |ui| self.show_jobs_panel(ui));
+                    self.panel_sizes.jobs_width = panel.response.rect.width();
+                }
+                JobsDock::Bottom => {
+                    let panel = egui::TopBottomPanel::bottom("jobs_panel")
+                        .resizable(true)
+                        .default_height(self.panel_sizes.jobs_height)
+                        .show(ctx, |ui| self.show_jobs_panel(ui));
+                    self.panel_sizes.jobs_height = panel.response.rect.height();
+                }
+            }
+        }
 
-                    ui.add_space(20.0);
-                    ui.label(format!("Status: {}", self.status));
+        if self.show_settings {
+            let panel = egui::SidePanel::right("settings_panel")
+                .resizable(true)
+                .default_width(self.panel_sizes.settings_width)
+                .show(ctx, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| self.show_settings_panel(ui));
                 });
+            self.panel_sizes.settings_width = panel.response.rect.width();
+        }
 
-                // Settings panel
-                if self.show_settings {
-                    ui.separator();
-                    ui.vertical(|ui| {
-                        self.show_settings_panel(ui);
-                    });
-                }
-            });
+        let document = &mut self.documents[self.active_tab];
+        document.refresh_preview();
+        if document.has_preview() {
+            let panel = egui::TopBottomPanel::bottom("preview_panel")
+                .resizable(true)
+                .default_height(self.panel_sizes.preview_height)
+                .show(ctx, |ui| document.show_preview(ui));
+            self.panel_sizes.preview_height = panel.response.rect.height();
+        }
 
-            // Bottom panel for recent files
-            egui::TopBottomPanel::bottom("recent_files").show(ctx, |ui| {
-                self.show_recent_files(ui);
-            });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.heading("JSON to CSV Converter");
+                ui.add_space(20.0);
 
-            // Settings toggle in the top bar
-            egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-                ui.horizontal(|ui| {
-                    ui.checkbox(&mut self.show_settings, "⚙️ Settings");
-                });
+                let document = &mut self.documents[self.active_tab];
+                if document.show(ui, &mut self.jobs) {
+                    self.select_json_file();
+                }
             });
         });
     }
 }
 
 /// Application entry point
+///
+/// Runs a single conversion in command-line mode when options are given, and opens the
+/// window otherwise, with any files given as arguments loaded.
 fn main() -> Result<(), eframe::Error> {
+    if cli::is_requested() {
+        std::process::exit(cli::run());
+    }
+    let startup = cli::startup();
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1000.0, 800.0])
             .with_title("JSON to CSV Converter"),
         ..Default::default()
     };
-    
+
     eframe::run_native(
         "JSON to CSV Converter",
         options,
-        Box::new(|cc| Box::new(JsonToCsvApp::new(cc))),
+        Box::new(move |cc| Box::new(JsonToCsvApp::new(cc, startup))),
     )
 }
-
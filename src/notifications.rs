@@ -0,0 +1,35 @@
+//! Desktop notifications for finished conversions
+
+use crate::jobs::{FinishedJob, JobState};
+use notify_rust::Notification;
+use std::thread;
+use std::time::Duration;
+
+/// Conversions shorter than this finish while the user is still watching, so they are not
+/// announced
+const MIN_NOTIFY_DURATION: Duration = Duration::from_secs(10);
+
+/// Shows an OS notification for a finished job if it ran long enough to be worth one
+///
+/// The notification is sent from a separate thread because talking to the notification
+/// service can block. Failures to notify are ignored.
+pub fn notify_finished(job: &FinishedJob) {
+    if job.duration < MIN_NOTIFY_DURATION {
+        return;
+    }
+
+    let summary = match job.state {
+        JobState::Completed => format!("Conversion finished: {}", job.name),
+        JobState::Failed => format!("Conversion failed: {}", job.name),
+        _ => return,
+    };
+    let body = job.status.clone();
+
+    thread::spawn(move || {
+        let _ = Notification::new()
+            .appname("JSON to CSV Converter")
+            .summary(&summary)
+            .body(&body)
+            .show();
+    });
+}
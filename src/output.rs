@@ -0,0 +1,126 @@
+//! Helpers for writing converted CSV content to disk
+//!
+//! Output files are only ever replaced as a whole, so downstream jobs watching a folder
+//! never pick up a truncated CSV after a crash or a failed write.
+
+use crate::locale;
+use crate::settings::{LineEndings, Settings};
+use anyhow::{anyhow, bail, Context, Result};
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes the output to `path` without ever leaving a truncated file there
+///
+/// The content goes to a hidden temporary file next to `path`, which is synced to disk and
+/// then renamed over it. A crash or failed write leaves the previous file, or none, in
+/// place; the temporary file is removed when writing fails.
+pub fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let temporary = temporary_path(path);
+    let written = File::create(&temporary)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temporary, path));
+    discard_on_error(written, &temporary, path)
+}
+
+/// Appends the data rows of a finished conversion to an existing CSV file
+///
+/// When headers are enabled, the first record of the existing file must match `columns`
+/// so rows never end up under a different header. An empty file receives the full content.
+/// The rows are appended to a copy that replaces the file once complete, so a failed
+/// append leaves the file as it was.
+pub fn append_to_csv_file(
+    path: &Path,
+    content: &[u8],
+    columns: &[String],
+    body_offset: usize,
+    settings: &Settings,
+) -> Result<()> {
+    let mut existing = File::open(path).context("Failed to open existing CSV file")?;
+    let existing_len = existing.metadata()?.len();
+
+    if existing_len > 0 && settings.include_headers {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(settings.delimiter.as_bytes()[0])
+            .from_reader(&existing);
+        let header = reader
+            .byte_records()
+            .next()
+            .transpose()
+            .context("Failed to read header of existing CSV file")?
+            .unwrap_or_default();
+        if !header
+            .iter()
+            .map(|name| locale::decode(name, settings.encoding))
+            .eq(columns.iter().map(|column| Cow::Borrowed(column.as_str())))
+        {
+            bail!(
+                "header of {} does not match the converted columns ({})",
+                path.display(),
+                columns.join(", ")
+            );
+        }
+    }
+
+    // Make sure the appended rows start on a fresh line
+    let mut last_byte = [b'\n'];
+    if existing_len > 0 {
+        existing.seek(SeekFrom::Start(existing_len - 1))?;
+        existing.read_exact(&mut last_byte)?;
+    }
+    drop(existing);
+    let rows = if existing_len == 0 { content } else { &content[body_offset..] };
+
+    let temporary = temporary_path(path);
+    let appended = fs::copy(path, &temporary)
+        .and_then(|_| {
+            let mut file = OpenOptions::new().append(true).open(&temporary)?;
+            if last_byte[0] != b'\n' {
+                file.write_all(match settings.line_endings {
+                    LineEndings::Lf => &b"\n"[..],
+                    LineEndings::CrLf => &b"\r\n"[..],
+                })?;
+            }
+            file.write_all(rows)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temporary, path));
+    discard_on_error(appended, &temporary, path)
+}
+
+/// Hidden file next to `path` that output is written to before it replaces `path`
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.partial", name))
+}
+
+/// Removes the temporary file of a failed write and explains what happened
+fn discard_on_error(outcome: io::Result<()>, temporary: &Path, path: &Path) -> Result<()> {
+    outcome.map_err(|e| {
+        // Nothing to remove when the temporary file was never created
+        let _ = fs::remove_file(temporary);
+        anyhow!(e).context(format!("Failed to save {}; partial output discarded", path.display()))
+    })
+}
+
+/// Returns the first free path of the form `name (2).ext`, `name (3).ext`, … next to `path`
+pub fn next_free_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    (2..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .expect("ran out of candidate file names")
+}
@@ -0,0 +1,181 @@
+//! Command palette
+//!
+//! Ctrl+P (Cmd+P on macOS) opens a list of all actions with a search field. Typing filters
+//! the list with fuzzy matching; the arrow keys move the selection and Enter runs it.
+
+use eframe::egui;
+
+/// Actions offered in the palette
+#[derive(Clone, Copy, PartialEq)]
+pub enum Command {
+    OpenFile,
+    Convert,
+    CancelConversion,
+    Save,
+    LoadPreset,
+    SavePreset,
+    FindReplace,
+    Wizard,
+    TogglePreview,
+    ToggleSettings,
+    ToggleJobs,
+    ToggleLog,
+    ToggleDarkMode,
+    NewTab,
+    CloseTab,
+    NextTab,
+    PreviousTab,
+}
+
+impl Command {
+    const ALL: [Command; 17] = [
+        Command::OpenFile,
+        Command::Convert,
+        Command::CancelConversion,
+        Command::Save,
+        Command::LoadPreset,
+        Command::SavePreset,
+        Command::FindReplace,
+        Command::Wizard,
+        Command::TogglePreview,
+        Command::ToggleSettings,
+        Command::ToggleJobs,
+        Command::ToggleLog,
+        Command::ToggleDarkMode,
+        Command::NewTab,
+        Command::CloseTab,
+        Command::NextTab,
+        Command::PreviousTab,
+    ];
+
+    /// Name shown in the palette
+    fn label(self) -> &'static str {
+        match self {
+            Command::OpenFile => "Open File…",
+            Command::Convert => "Convert",
+            Command::CancelConversion => "Cancel Conversion",
+            Command::Save => "Save Output…",
+            Command::LoadPreset => "Load Preset…",
+            Command::SavePreset => "Save Preset…",
+            Command::FindReplace => "Find and Replace…",
+            Command::Wizard => "Conversion Wizard…",
+            Command::TogglePreview => "Toggle Preview",
+            Command::ToggleSettings => "Toggle Settings Panel",
+            Command::ToggleJobs => "Toggle Jobs Panel",
+            Command::ToggleLog => "Toggle Log",
+            Command::ToggleDarkMode => "Toggle Dark Mode",
+            Command::NewTab => "New Tab",
+            Command::CloseTab => "Close Tab",
+            Command::NextTab => "Next Tab",
+            Command::PreviousTab => "Previous Tab",
+        }
+    }
+}
+
+/// State of the palette window
+#[derive(Default)]
+pub struct CommandPalette {
+    open: bool,
+    query: String,
+    /// Index of the highlighted entry among the matches
+    selected: usize,
+}
+
+impl CommandPalette {
+    /// Opens the palette with an empty search, or closes it when it is open
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    /// Displays the palette while it is open and returns the command the user picked
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<Command> {
+        if !self.open {
+            return None;
+        }
+
+        let mut matches: Vec<(i32, Command)> = Command::ALL
+            .iter()
+            .filter_map(|command| Some((fuzzy_score(&self.query, command.label())?, *command)))
+            .collect();
+        // Best matches first; the sort is stable, so equal scores keep the list order
+        matches.sort_by_key(|(score, _)| -score);
+
+        let (up, down, enter, escape) = ctx.input(|i| {
+            (
+                i.key_pressed(egui::Key::ArrowUp),
+                i.key_pressed(egui::Key::ArrowDown),
+                i.key_pressed(egui::Key::Enter),
+                i.key_pressed(egui::Key::Escape),
+            )
+        });
+        if down {
+            self.selected += 1;
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        self.selected = self.selected.min(matches.len().saturating_sub(1));
+
+        let mut picked = None;
+        egui::Window::new("Command Palette")
+            .title_bar(false)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 40.0])
+            .show(ctx, |ui| {
+                let search = ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("Type a command")
+                        .desired_width(300.0),
+                );
+                search.request_focus();
+                if search.changed() {
+                    self.selected = 0;
+                }
+                ui.separator();
+                for (index, (_, command)) in matches.iter().enumerate() {
+                    if ui.selectable_label(index == self.selected, command.label()).clicked() {
+                        picked = Some(*command);
+                    }
+                }
+                if matches.is_empty() {
+                    ui.weak("No matching command");
+                }
+            });
+
+        if enter {
+            picked = picked.or(matches.get(self.selected).map(|(_, command)| *command));
+        }
+        if picked.is_some() || escape {
+            self.open = false;
+        }
+        picked
+    }
+}
+
+/// Scores how well `query` matches `label`; `None` when it is not a match
+///
+/// Every character of the query has to appear in the label in order, ignoring case.
+/// Consecutive characters and characters at the start of a word score higher, so "tp"
+/// ranks "Toggle Preview" above "Toggle Jobs Panel".
+fn fuzzy_score(query: &str, label: &str) -> Option<i32> {
+    let label: Vec<char> = label.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous = None;
+    for query_char in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = position + label[position..].iter().position(|c| *c == query_char)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !label[found - 1].is_alphanumeric() {
+            score += 10;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
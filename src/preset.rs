@@ -0,0 +1,73 @@
+//! Conversion presets
+//!
+//! A preset is a JSON file holding the settings of a tab and its column selection: the
+//! output format and its templates, defaults, mappings, the transform script and everything
+//! else the settings panel shows. Loading it on another machine, or passing it to
+//! `--preset`, converts a feed exactly the same way. Settings tied to the user or the
+//! machine, the output folder, the S3 access key and the Google client, are left out, as
+//! are secrets.
+
+use crate::settings::Settings;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Contents of a preset file
+///
+/// Fields missing from a file keep their defaults, so presets saved by older versions
+/// still load.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub settings: Settings,
+    /// Columns to export, in order; empty for all
+    pub columns: Vec<String>,
+}
+
+impl Preset {
+    /// Preset of the settings and column selection of a tab
+    pub fn new(settings: &Settings, columns: &[String]) -> Self {
+        let mut settings = settings.clone();
+        settings.output_dir = None;
+        settings.worker_threads = 0;
+        settings.low_priority = false;
+        settings.s3_access_key.clear();
+        settings.s3_secret_key.clear();
+        settings.google_client_id.clear();
+        settings.google_client_secret.clear();
+        settings.keychain_secrets.clear();
+        Self {
+            settings,
+            columns: columns.to_vec(),
+        }
+    }
+
+    /// Settings of the preset, keeping the machine-specific ones of `current`
+    pub fn settings_over(&self, current: &Settings) -> Settings {
+        Settings {
+            output_dir: current.output_dir.clone(),
+            worker_threads: current.worker_threads,
+            low_priority: current.low_priority,
+            s3_access_key: current.s3_access_key.clone(),
+            s3_secret_key: current.s3_secret_key.clone(),
+            google_client_id: current.google_client_id.clone(),
+            google_client_secret: current.google_client_secret.clone(),
+            keychain_secrets: current.keychain_secrets.clone(),
+            ..self.settings.clone()
+        }
+    }
+}
+
+/// Loads a preset from a JSON file
+pub fn load(path: &Path) -> Result<Preset> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read preset {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Invalid preset {}", path.display()))
+}
+
+/// Saves a preset to a JSON file
+pub fn save(path: &Path, preset: &Preset) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(preset)?)
+        .with_context(|| format!("Failed to write preset {}", path.display()))
+}
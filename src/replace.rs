@@ -0,0 +1,192 @@
+//! Find and replace
+//!
+//! Ctrl+H opens a window that searches the cells of the converted data for plain text or a
+//! regular expression. "Replace All" rewrites the matching cells of the generated CSV, so
+//! the replacements are saved with it, and updates the preview to match. The header row
+//! is left alone.
+
+use crate::formats::{CsvFormat, OutputFormat};
+use crate::locale;
+use crate::settings::Settings;
+use anyhow::{bail, Context, Result};
+use eframe::egui;
+use regex::{NoExpand, Regex, RegexBuilder};
+
+/// Searches cells for plain text or a regular expression
+pub struct Finder {
+    regex: Regex,
+    /// Whether `$1` and the like in the replacement refer to groups of the pattern
+    expand: bool,
+}
+
+impl Finder {
+    /// Compiles the search; plain text is matched literally
+    pub fn new(find: &str, use_regex: bool, match_case: bool) -> Result<Self> {
+        let pattern = if use_regex {
+            find.to_string()
+        } else {
+            regex::escape(find)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!match_case)
+            .build()
+            .context("Invalid regular expression")?;
+        Ok(Self {
+            regex,
+            expand: use_regex,
+        })
+    }
+
+    /// Number of matches in a cell
+    pub fn count(&self, cell: &str) -> usize {
+        self.regex.find_iter(cell).count()
+    }
+
+    /// Replaces every match in a cell; `None` when nothing matches
+    pub fn replace(&self, cell: &str, replacement: &str) -> Option<(String, usize)> {
+        let count = self.count(cell);
+        if count == 0 {
+            return None;
+        }
+        let replaced = if self.expand {
+            self.regex.replace_all(cell, replacement)
+        } else {
+            self.regex.replace_all(cell, NoExpand(replacement))
+        };
+        Some((replaced.into_owned(), count))
+    }
+}
+
+/// Counts the matches in the data rows of generated CSV content
+pub fn count_in_csv(
+    content: &[u8],
+    body_offset: usize,
+    finder: &Finder,
+    settings: &Settings,
+) -> Result<usize> {
+    let mut count = 0;
+    let body = locale::decode(&content[body_offset..], settings.encoding);
+    for record in csv_reader(body.as_bytes(), settings)?.records() {
+        count += record?.iter().map(|cell| finder.count(cell)).sum::<usize>();
+    }
+    Ok(count)
+}
+
+/// Replaces the matches in the data rows of generated CSV content
+///
+/// The rows are read and written with the delimiter, quoting and encoding of the settings,
+/// so replacements that add delimiters or quotes are quoted as usual. Returns the new
+/// content and the number of replacements.
+pub fn replace_in_csv(
+    content: &[u8],
+    body_offset: usize,
+    finder: &Finder,
+    replacement: &str,
+    settings: &Settings,
+) -> Result<(Vec<u8>, usize)> {
+    let mut rows = CsvFormat::new(settings)?;
+    let mut count = 0;
+    let body = locale::decode(&content[body_offset..], settings.encoding);
+    for record in csv_reader(body.as_bytes(), settings)?.records() {
+        let values: Vec<String> = record?
+            .iter()
+            .map(|cell| match finder.replace(cell, replacement) {
+                Some((replaced, replacements)) => {
+                    count += replacements;
+                    replaced
+                }
+                None => cell.to_string(),
+            })
+            .collect();
+        rows.write_row(&values)?;
+    }
+    let mut output = content[..body_offset].to_vec();
+    output.extend(Box::new(rows).finish()?);
+    Ok((output, count))
+}
+
+/// Reader for CSV content written with the settings
+fn csv_reader<'a>(content: &'a [u8], settings: &Settings) -> Result<csv::Reader<&'a [u8]>> {
+    // The same check as for writing; the output could not have been generated otherwise
+    let &[delimiter] = settings.delimiter.as_bytes() else {
+        bail!("Invalid delimiter {:?}", settings.delimiter);
+    };
+    Ok(csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quoting(settings.quote_fields)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content))
+}
+
+/// What the user asked for in the find and replace window
+pub enum FindRequest {
+    Count,
+    ReplaceAll,
+}
+
+/// State of the find and replace window
+#[derive(Default)]
+pub struct FindReplace {
+    open: bool,
+    pub find: String,
+    pub replacement: String,
+    pub use_regex: bool,
+    pub match_case: bool,
+    /// Outcome of the latest count or replacement
+    pub message: Option<String>,
+}
+
+impl FindReplace {
+    /// Opens the window, or closes it when it is open
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.message = None;
+    }
+
+    /// Displays the window while it is open and returns the button the user clicked
+    ///
+    /// `unavailable` tells why searching is not possible, if it is not.
+    pub fn show(&mut self, ctx: &egui::Context, unavailable: Option<&str>) -> Option<FindRequest> {
+        if !self.open {
+            return None;
+        }
+        let mut request = None;
+        let mut open = self.open;
+        egui::Window::new("Find and Replace")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("find_replace").show(ui, |ui| {
+                    ui.label("Find:");
+                    ui.text_edit_singleline(&mut self.find);
+                    ui.end_row();
+                    ui.label("Replace:");
+                    ui.add(egui::TextEdit::singleline(&mut self.replacement)
+                        .hint_text(if self.use_regex { "$1 refers to a group" } else { "" }));
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.use_regex, "Regular Expression");
+                    ui.checkbox(&mut self.match_case, "Match Case");
+                });
+                ui.horizontal(|ui| {
+                    let enabled = unavailable.is_none() && !self.find.is_empty();
+                    if ui.add_enabled(enabled, egui::Button::new("Count")).clicked() {
+                        request = Some(FindRequest::Count);
+                    }
+                    if ui.add_enabled(enabled, egui::Button::new("Replace All")).clicked() {
+                        request = Some(FindRequest::ReplaceAll);
+                    }
+                });
+                if let Some(reason) = unavailable {
+                    ui.weak(reason);
+                } else if let Some(message) = &self.message {
+                    ui.label(message);
+                }
+            });
+        self.open = open;
+        request
+    }
+}
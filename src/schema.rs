@@ -0,0 +1,293 @@
+//! Column types
+//!
+//! Converted cells are text, but typed outputs such as database tables, Google Sheets,
+//! Arrow and Avro files need to know what a column holds. The type of a column is inferred
+//! from all of its values; Avro output can also use a schema edited by the user. A JSON
+//! Schema of the records declares the columns and their types instead, along with
+//! constraints every row is validated against.
+
+use crate::validation::ColumnRule;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Type of a column, inferred from the converted values
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Boolean,
+    Text,
+}
+
+impl ColumnType {
+    /// Every column type, in the order they are offered for editing
+    pub const ALL: [ColumnType; 4] = [
+        ColumnType::Integer,
+        ColumnType::Float,
+        ColumnType::Boolean,
+        ColumnType::Text,
+    ];
+
+    /// Name of the type shown to the user
+    pub fn label(self) -> &'static str {
+        match self {
+            ColumnType::Integer => "Integer",
+            ColumnType::Float => "Decimal",
+            ColumnType::Boolean => "Boolean",
+            ColumnType::Text => "Text",
+        }
+    }
+
+    /// Name of the type in an Avro schema
+    pub fn avro_name(self) -> &'static str {
+        match self {
+            ColumnType::Integer => "long",
+            ColumnType::Float => "double",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Text => "string",
+        }
+    }
+
+    /// Narrowest type that holds every non-empty value
+    ///
+    /// Empty cells and JSON `null`s, which are rendered as `null`, are left out and become
+    /// `NULL` in typed columns.
+    pub fn infer<'a>(values: impl Iterator<Item = &'a str>) -> Self {
+        let mut column_type = None;
+        for value in values {
+            column_type = Self::widen(column_type, value);
+            if column_type == Some(ColumnType::Text) {
+                break;
+            }
+        }
+        column_type.unwrap_or(ColumnType::Text)
+    }
+
+    /// Narrowest type holding the values of type `current` and `value`, for inferring the
+    /// type cell by cell; empty cells and `null`s leave it as it is
+    ///
+    /// Only finite numbers are floats: `NaN`, `inf` and `infinity`, which Rust parses as
+    /// numbers, are text, as neither databases nor spreadsheets store them as such.
+    pub fn widen(current: Option<Self>, value: &str) -> Option<Self> {
+        if value.is_empty() || value == "null" {
+            return current;
+        }
+        let value_type = if value.parse::<i64>().is_ok() {
+            ColumnType::Integer
+        } else if value.parse::<f64>().is_ok_and(f64::is_finite) {
+            ColumnType::Float
+        } else if value == "true" || value == "false" {
+            ColumnType::Boolean
+        } else {
+            ColumnType::Text
+        };
+        Some(match (current, value_type) {
+            (None, value_type) => value_type,
+            (Some(current), value_type) if current == value_type => current,
+            (Some(ColumnType::Integer | ColumnType::Float), ColumnType::Integer | ColumnType::Float) => {
+                ColumnType::Float
+            }
+            _ => ColumnType::Text,
+        })
+    }
+}
+
+/// Field of an Avro record schema; fields are matched to the converted columns by position
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AvroField {
+    pub name: String,
+    pub column_type: ColumnType,
+}
+
+impl AvroField {
+    /// Field for a column, with the column name turned into a valid Avro name
+    ///
+    /// Avro names only consist of ASCII letters, digits and underscores and do not start
+    /// with a digit; other characters become underscores.
+    pub fn for_column(column: &str, column_type: ColumnType) -> Self {
+        let mut name: String = column
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            name.insert(0, '_');
+        }
+        Self { name, column_type }
+    }
+}
+
+/// Avro record schema with the given fields, all of them nullable
+pub fn avro_schema(fields: &[AvroField]) -> Value {
+    let fields: Vec<Value> = fields
+        .iter()
+        .map(|field| {
+            json!({
+                "name": field.name,
+                "type": ["null", field.column_type.avro_name()],
+                "default": null,
+            })
+        })
+        .collect();
+    json!({ "type": "record", "name": "Row", "fields": fields })
+}
+
+/// Reads the fields of an Avro record schema, such as one written by `avro_schema`
+///
+/// Only the field names and types are used. A type is a primitive name or a union of
+/// `"null"` and one; `int` and `float` are read as `long` and `double`.
+pub fn parse_avro_fields(schema: &str) -> Result<Vec<AvroField>> {
+    let schema: Value = serde_json::from_str(schema).context("Invalid Avro schema")?;
+    let fields = schema["fields"]
+        .as_array()
+        .context("The Avro schema has no fields; expected a record schema")?;
+
+    fields
+        .iter()
+        .map(|field| {
+            let name = field["name"].as_str().context("Avro field without a name")?;
+            let type_name = match &field["type"] {
+                Value::String(type_name) => Some(type_name.as_str()),
+                Value::Array(types) => types
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .find(|type_name| *type_name != "null"),
+                _ => None,
+            };
+            let column_type = match type_name {
+                Some("long" | "int") => ColumnType::Integer,
+                Some("double" | "float") => ColumnType::Float,
+                Some("boolean") => ColumnType::Boolean,
+                Some("string") => ColumnType::Text,
+                _ => bail!(
+                    "Unsupported type of Avro field {}; use long, double, boolean or string",
+                    name
+                ),
+            };
+            Ok(AvroField {
+                name: name.to_string(),
+                column_type,
+            })
+        })
+        .collect()
+}
+
+/// Columns, types and constraints read from a JSON Schema of the records
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JsonSchema {
+    /// File name of the schema, shown in the settings
+    pub name: String,
+    /// Properties of the records with their declared types
+    pub columns: Vec<(String, ColumnType)>,
+    /// Rules checking the required properties and the constraints of the others
+    pub rules: Vec<ColumnRule>,
+}
+
+impl JsonSchema {
+    /// Reads the schema of a record, or of an array of records
+    ///
+    /// Every property becomes a column. `integer`, `number` and `boolean` properties, also
+    /// when `null` is allowed, become typed columns; everything else is text. `required`
+    /// properties must not be empty, and `minimum`, `maximum`, `pattern`, `enum`,
+    /// `minLength` and `maxLength` become validation rules. Other keywords are ignored.
+    pub fn parse(name: &str, schema: &str) -> Result<Self> {
+        let schema: Value = serde_json::from_str(schema).context("Invalid JSON Schema")?;
+        let record = match schema.get("items") {
+            Some(items) if schema["type"] == "array" => items,
+            _ => &schema,
+        };
+        let properties = record["properties"]
+            .as_object()
+            .context("The JSON Schema has no properties; expected the schema of an object")?;
+        let required: Vec<&str> = record["required"]
+            .as_array()
+            .map(|required| required.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+
+        let mut columns = Vec::new();
+        let mut rules = Vec::new();
+        for (column, property) in properties {
+            let column_type = declared_type(&property["type"]);
+            columns.push((column.clone(), column_type.unwrap_or(ColumnType::Text)));
+            let rule = ColumnRule {
+                column: column.clone(),
+                non_empty: required.contains(&column.as_str()),
+                column_type,
+                min: property["minimum"].as_f64(),
+                max: property["maximum"].as_f64(),
+                pattern: property["pattern"].as_str().unwrap_or_default().to_string(),
+                ..Default::default()
+            };
+            let constrained = rule.non_empty || rule.column_type.is_some() || !rule.pattern.is_empty();
+            if constrained || rule.min.is_some() || rule.max.is_some() {
+                rules.push(rule);
+            }
+            // Cells are matched as rendered, so strings are compared without quotes
+            if let Some(values) = property["enum"].as_array() {
+                let alternatives: Vec<String> = values
+                    .iter()
+                    .map(|value| match value {
+                        Value::String(text) => regex::escape(text),
+                        value => regex::escape(&value.to_string()),
+                    })
+                    .collect();
+                rules.push(ColumnRule {
+                    column: column.clone(),
+                    pattern: format!("^(?:{})$", alternatives.join("|")),
+                    ..Default::default()
+                });
+            }
+            let (min_length, max_length) = (property["minLength"].as_u64(), property["maxLength"].as_u64());
+            if min_length.is_some() || max_length.is_some() {
+                rules.push(ColumnRule {
+                    column: column.clone(),
+                    pattern: format!(
+                        "^(?s).{{{},{}}}$",
+                        min_length.unwrap_or_default(),
+                        max_length.map(|max| max.to_string()).unwrap_or_default()
+                    ),
+                    ..Default::default()
+                });
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            columns,
+            rules,
+        })
+    }
+
+    /// Names of the columns
+    pub fn column_names(&self) -> Vec<String> {
+        self.columns.iter().map(|(column, _)| column.clone()).collect()
+    }
+
+    /// Declared type of a column, if the schema has it
+    pub fn column_type(&self, column: &str) -> Option<ColumnType> {
+        self.columns
+            .iter()
+            .find(|(name, _)| name == column)
+            .map(|(_, column_type)| *column_type)
+    }
+}
+
+/// Column type of a JSON Schema `type`, a name or a list of names that may include `null`;
+/// `None` for text and for lists of several other types
+fn declared_type(type_names: &Value) -> Option<ColumnType> {
+    let names: Vec<&str> = match type_names {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(names) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .filter(|name| *name != "null")
+            .collect(),
+        _ => Vec::new(),
+    };
+    match names[..] {
+        ["integer"] => Some(ColumnType::Integer),
+        ["number"] => Some(ColumnType::Float),
+        ["boolean"] => Some(ColumnType::Boolean),
+        _ => None,
+    }
+}
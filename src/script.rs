@@ -0,0 +1,83 @@
+//! Per-record transform scripts
+//!
+//! A Rhai script runs once for every record before it is written. The record is available
+//! as the `record` variable and can be changed in place:
+//!
+//! ```text
+//! record.full_name = record.first + " " + record.last;
+//! record.remove("first");
+//! record.remove("last");
+//! ```
+//!
+//! Setting `record = ();` drops the record from the output.
+//!
+//! Numbers reach the script as integers where they fit in one and as floats otherwise, so
+//! a script that touches a column of numbers beyond float precision rounds them.
+
+use anyhow::{anyhow, Result};
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde_json::Value;
+
+/// Maximum number of operations a script may perform per record, so a runaway loop fails
+/// instead of hanging the conversion
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A compiled transform script
+pub struct RecordScript {
+    engine: Engine,
+    ast: AST,
+}
+
+impl RecordScript {
+    /// Compiles a script
+    pub fn compile(source: &str) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(source)
+            .map_err(|e| anyhow!("Script error: {}", e))?;
+        Ok(Self { engine, ast })
+    }
+
+    /// Runs the script on a record
+    ///
+    /// Returns the transformed record, or `None` if the script dropped it.
+    pub fn apply(&self, record: &Value) -> Result<Option<Value>> {
+        let mut scope = Scope::new();
+        scope.push("record", to_dynamic(record));
+        self.engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| anyhow!("{}", e))?;
+
+        let record: Dynamic = scope.get_value("record").unwrap_or_default();
+        if record.is_unit() {
+            return Ok(None);
+        }
+        rhai::serde::from_dynamic(&record)
+            .map(Some)
+            .map_err(|e| anyhow!("{}", e))
+    }
+}
+
+/// Converts a JSON value for a script
+///
+/// `rhai::serde::to_dynamic` would see the numbers of `serde_json`, which keep their source
+/// text, as maps holding that text.
+fn to_dynamic(value: &Value) -> Dynamic {
+    match value {
+        Value::Null => Dynamic::UNIT,
+        Value::Bool(value) => Dynamic::from(*value),
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => Dynamic::from(integer),
+            None => Dynamic::from(number.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(text) => Dynamic::from(text.clone()),
+        Value::Array(items) => Dynamic::from_array(items.iter().map(to_dynamic).collect()),
+        Value::Object(object) => Dynamic::from_map(
+            object
+                .iter()
+                .map(|(key, value)| (key.as_str().into(), to_dynamic(value)))
+                .collect(),
+        ),
+    }
+}
@@ -0,0 +1,170 @@
+//! HTTP server mode
+//!
+//! `json_to_csv_converter --serve` answers `POST /convert` requests: the request body is
+//! the input and the response body is the CSV output. Options are passed as query
+//! parameters, e.g. `POST /convert?delimiter=%3B&columns=name,age`.
+//!
+//! Every request is held in memory while it is converted, so bodies above a size limit
+//! are refused with 413, and requests beyond `MAX_REQUESTS` at once with 503.
+
+use crate::conversion::{convert, ConversionInput, ConversionProgress};
+use crate::input::InputFormat;
+use crate::settings::Settings;
+use anyhow::{anyhow, bail, Context, Result};
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{info, warn};
+
+/// Address used by `--serve` without a value
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// Largest request body accepted without `--max-body-mb`, in megabytes
+pub const DEFAULT_MAX_BODY_MB: u64 = 100;
+
+/// Number of requests handled at once; further ones are answered with 503 right away
+const MAX_REQUESTS: usize = 16;
+
+/// Request body larger than the limit, answered with 413
+#[derive(Debug)]
+struct BodyTooLarge(u64);
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Request body larger than {} bytes", self.0)
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+/// Serves conversions until the process is stopped, handling each request on its own thread
+///
+/// `max_body` is the largest request body accepted, in bytes.
+pub fn serve(addr: &str, max_body: u64) -> Result<()> {
+    let server = Server::http(addr).map_err(|e| anyhow!("Failed to listen on {}: {}", addr, e))?;
+    info!("Listening on http://{}/convert", addr);
+
+    let running = Arc::new(AtomicUsize::new(0));
+    for request in server.incoming_requests() {
+        if running.fetch_add(1, Ordering::SeqCst) >= MAX_REQUESTS {
+            running.fetch_sub(1, Ordering::SeqCst);
+            warn!("Refused {}: {} requests are running", request.url(), MAX_REQUESTS);
+            let _ = request.respond(text_response(503, "Too many requests; try again later".to_string()));
+            continue;
+        }
+        let running = Arc::clone(&running);
+        thread::spawn(move || {
+            handle(request, max_body);
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+/// Answers a single request
+fn handle(mut request: Request, max_body: u64) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    let response = if path != "/convert" {
+        text_response(404, "Not found; use POST /convert".to_string())
+    } else if *request.method() != Method::Post {
+        text_response(405, "Method not allowed; use POST".to_string())
+    } else {
+        match convert_request(&mut request, query, max_body) {
+            Ok(csv) => Response::from_data(csv).with_header(
+                Header::from_bytes("Content-Type", "text/csv; charset=utf-8").unwrap(),
+            ),
+            Err(e) => {
+                warn!("Request {} failed: {:#}", url, e);
+                let status = if e.is::<BodyTooLarge>() { 413 } else { 400 };
+                text_response(status, format!("{:#}", e))
+            }
+        }
+    };
+
+    // The client may already be gone; there is nobody left to report that to
+    let _ = request.respond(response);
+}
+
+/// Converts the body of a request with the options of its query string
+///
+/// Bodies over `max_body` bytes fail with `BodyTooLarge`, by their `Content-Length` before
+/// anything is read, or once the limit is passed while reading chunked ones.
+fn convert_request(request: &mut Request, query: &str, max_body: u64) -> Result<Vec<u8>> {
+    let mut settings = Settings::default();
+    let mut columns = Vec::new();
+    let mut format = InputFormat::Json;
+
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match key.as_ref() {
+            "delimiter" => settings.delimiter = value.into_owned(),
+            "columns" => {
+                columns = value
+                    .split(',')
+                    .filter(|column| !column.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "headers" => settings.include_headers = parse_flag(&key, &value)?,
+            "quote" => settings.quote_fields = parse_flag(&key, &value)?,
+            "strict" => settings.json5_fallback = !parse_flag(&key, &value)?,
+            "max_cell_length" => {
+                settings.max_cell_length = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid max_cell_length {:?}", value))?,
+                );
+            }
+            "format" => {
+                format = match value.as_ref() {
+                    "json" => InputFormat::Json,
+                    "yaml" => InputFormat::Yaml,
+                    "toml" => InputFormat::Toml,
+                    "csv" => InputFormat::Csv,
+                    other => bail!("Unknown format {:?}", other),
+                };
+            }
+            other => bail!("Unknown query parameter {:?}", other),
+        }
+    }
+
+    if request.body_length().is_some_and(|length| length as u64 > max_body) {
+        bail!(BodyTooLarge(max_body));
+    }
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(max_body.saturating_add(1))
+        .read_to_string(&mut body)
+        .context("Failed to read request body")?;
+    if body.len() as u64 > max_body {
+        bail!(BodyTooLarge(max_body));
+    }
+
+    let progress = Mutex::new(ConversionProgress::default());
+    let input = ConversionInput::Text(body, format, String::new());
+    match convert(&input, &settings, columns, &progress, &AtomicBool::new(false))? {
+        Some(result) => Ok(result.csv_content),
+        None => bail!("Conversion cancelled"),
+    }
+}
+
+/// Parses a boolean query parameter
+fn parse_flag(key: &str, value: &str) -> Result<bool> {
+    match value {
+        "true" | "1" | "yes" => Ok(true),
+        "false" | "0" | "no" => Ok(false),
+        _ => bail!("Invalid value {:?} for {}; use true or false", value, key),
+    }
+}
+
+/// Plain text response with the given status code
+fn text_response(status: u16, message: String) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(message)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "text/plain; charset=utf-8").unwrap())
+}
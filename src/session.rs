@@ -0,0 +1,68 @@
+//! Session autosave
+//!
+//! While the window is open, the open tabs with their inputs, settings and column
+//! selections are saved to `session.json` in the config directory every few seconds. A
+//! clean exit removes the file, so finding it at startup means the last session ended
+//! unexpectedly, and the app offers to restore it.
+
+use crate::settings::Settings;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often the session is saved while it changes
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+const SESSION_FILE_NAME: &str = "session.json";
+
+/// Open tabs of a session
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub tabs: Vec<TabState>,
+    pub active_tab: usize,
+}
+
+/// What is needed to bring a tab back; the input is loaded again from its file or URI
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TabState {
+    pub json_path: Option<PathBuf>,
+    pub source_uri: Option<String>,
+    pub settings: Settings,
+    pub all_columns: Vec<String>,
+    pub selected_columns: Vec<String>,
+    pub s3_output: String,
+    /// Database URL without a password typed into it, see `secrets::without_password`
+    pub db_url: String,
+    pub db_table: String,
+    pub sheet_title: String,
+}
+
+/// Session left behind by a session that did not exit cleanly, if any
+pub fn load_unfinished(dir: &Path) -> Option<Session> {
+    let content = fs::read_to_string(dir.join(SESSION_FILE_NAME)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Saves the session when it differs from `last_saved`, and returns what was written
+pub fn autosave(dir: &Path, session: &Session, last_saved: &str) -> Result<String> {
+    let content = serde_json::to_string(session)?;
+    if content != last_saved {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create config directory {}", dir.display()))?;
+        let path = dir.join(SESSION_FILE_NAME);
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, &content)
+            .and_then(|_| fs::rename(&temporary, &path))
+            .with_context(|| format!("Failed to autosave session to {}", path.display()))?;
+    }
+    Ok(content)
+}
+
+/// Removes the saved session, at a clean exit or when the user declines to restore it
+pub fn clear(dir: &Path) {
+    // Nothing to remove when the session was never saved
+    let _ = fs::remove_file(dir.join(SESSION_FILE_NAME));
+}
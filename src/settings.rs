@@ -0,0 +1,492 @@
+//! Conversion and export settings
+//!
+//! Every document tab carries its own copy of these settings, so the same file can be
+//! exported with different options side by side.
+
+use crate::schema::{AvroField, JsonSchema};
+use crate::secrets;
+use crate::validation::{ColumnRule, InvalidRows};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// How line breaks inside field values are written to the CSV output
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NewlineHandling {
+    /// Keep line breaks, producing quoted multi-line fields
+    #[default]
+    Keep,
+    /// Replace each line break with a single space
+    Space,
+    /// Replace each line break with the two characters `\n`
+    Escape,
+    /// Replace each line break with a user-defined token
+    Custom,
+}
+
+/// How ISO 8601 dates such as `2024-03-05` are written to the CSV output, see `locale`
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DateFormat {
+    /// Unchanged, as `2024-03-05`
+    #[default]
+    Iso,
+    /// As `03/05/2024`
+    MonthDayYear,
+    /// As `05/03/2024`
+    DayMonthYear,
+    /// As `05.03.2024`
+    DayMonthYearDots,
+}
+
+/// Character encoding of the CSV output
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    /// UTF-8 starting with a byte order mark, which Excel needs to read UTF-8
+    Utf8Bom,
+    /// Windows-1252 (Western European); characters it lacks are written as `?`
+    Windows1252,
+}
+
+/// Line breaks ending the rows of the CSV output
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LineEndings {
+    /// `\n`, as on Linux and macOS
+    #[default]
+    Lf,
+    /// `\r\n`, as on Windows
+    CrLf,
+}
+
+/// What happens when a file above the large file threshold is opened
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LargeFileAction {
+    /// Ask whether to stream the file or load it anyway
+    #[default]
+    Warn,
+    /// Stream the file during conversion without asking
+    Stream,
+}
+
+/// How objects that repeat a key are read
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DuplicateKeys {
+    /// No check; the last value silently wins
+    #[default]
+    Off,
+    /// Report repeated keys and keep their first value
+    KeepFirst,
+    /// Report repeated keys and keep their last value
+    KeepLast,
+    /// Report repeated keys and keep every value, in columns named `key_2`, `key_3`, …
+    Suffix,
+}
+
+/// How the geometry of GeoJSON features is written, see `geojson`
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GeometryColumns {
+    /// Well-Known Text in a `geometry` column
+    #[default]
+    Wkt,
+    /// `longitude` and `latitude` columns of the point, or the center of other geometries
+    LonLat,
+}
+
+/// How CSV input is read; what is not set is sniffed from the file, see `dialect`
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvDialect {
+    pub delimiter: Option<char>,
+    pub quote: Option<char>,
+    /// Whether the first row names the columns
+    pub has_header: Option<bool>,
+}
+
+/// What happens to array records with more or fewer fields than there are columns, e.g.
+/// ragged lines of CSV input
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RaggedRows {
+    /// Pad short rows with empty cells or defaults and cut long ones, and report them
+    #[default]
+    Fix,
+    /// Leave them out and report them as skipped records
+    Skip,
+    /// Fail the conversion at the first one
+    Fail,
+}
+
+/// Value written for a column when a record lacks its key
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnDefault {
+    pub column: String,
+    pub value: String,
+}
+
+/// One entry of a value mapping
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MappedValue {
+    /// Cell as rendered, e.g. `1`
+    pub from: String,
+    /// Cell written instead, e.g. `active`
+    pub to: String,
+}
+
+/// Lookup table replacing the cells of a column, e.g. status codes with their names
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValueMapping {
+    pub column: String,
+    pub values: Vec<MappedValue>,
+}
+
+/// How the cells of a sensitive column are hidden
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Anonymization {
+    /// Replace them with their SHA-256 hash, which keeps equal values equal
+    #[default]
+    Hash,
+    /// Replace them with the mask text
+    Mask,
+    /// Leave the column out of the output
+    Drop,
+}
+
+/// Anonymization of the columns matching a pattern
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnAnonymization {
+    /// Column name, with `*` and `?` as wildcards
+    pub column: String,
+    pub method: Anonymization,
+}
+
+/// What happens to control and zero-width characters in cells
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ControlCharacters {
+    #[default]
+    Keep,
+    /// Remove them
+    Strip,
+    /// Write them as escapes such as `\u001B`
+    Escape,
+}
+
+/// Cleanup of the text of cells taken from the input
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextCleanup {
+    /// Whether text is brought to Unicode normalization form C
+    pub normalize: bool,
+    /// Control characters other than tab and line breaks, and zero-width characters
+    pub control_characters: ControlCharacters,
+}
+
+/// Companion file describing the output columns, see `dictionary`
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DictionaryFormat {
+    #[default]
+    Off,
+    /// `<name>_dictionary.csv`
+    Csv,
+    /// `<name>_dictionary.md`, a Markdown table
+    Markdown,
+}
+
+impl DictionaryFormat {
+    /// File extension of the dictionary
+    pub fn extension(self) -> &'static str {
+        match self {
+            DictionaryFormat::Off | DictionaryFormat::Csv => "csv",
+            DictionaryFormat::Markdown => "md",
+        }
+    }
+}
+
+/// Synthetic columns written before the columns of the records, for tracing rows back to
+/// their input
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetadataColumns {
+    /// `_row_number`: number of the data row, from 1
+    pub row_number: bool,
+    /// `_source_file`: path or URI of the input
+    pub source_file: bool,
+    /// `_json_pointer`: JSON Pointer of the input record the row came from, e.g. `/41` or
+    /// `/data/items/41`
+    pub json_pointer: bool,
+}
+
+/// Width and alignment of a column of fixed-width output
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnWidth {
+    pub column: String,
+    /// Number of characters; `None` for the length of the longest value
+    pub width: Option<usize>,
+    /// Whether values are padded on the left, as numbers usually are
+    pub align_right: bool,
+}
+
+/// Format of the converted output
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OutputKind {
+    /// Delimited text
+    #[default]
+    Csv,
+    /// Text rendered from user-defined header, row and footer templates
+    Template,
+    /// Arrow IPC file (Feather v2) with typed columns
+    Arrow,
+    /// Avro object container file with a generated or edited schema
+    Avro,
+    /// OpenDocument spreadsheet with typed cells
+    Ods,
+    /// Text with every column padded to a fixed width
+    FixedWidth,
+    /// Excel workbook with typed cells, optionally one sheet per value of a column
+    Xlsx,
+}
+
+impl OutputKind {
+    /// File extension suggested when saving
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputKind::Csv => "csv",
+            OutputKind::Template => "txt",
+            OutputKind::Arrow => "feather",
+            OutputKind::Avro => "avro",
+            OutputKind::Ods => "ods",
+            OutputKind::FixedWidth => "txt",
+            OutputKind::Xlsx => "xlsx",
+        }
+    }
+
+    /// MIME type used when uploading
+    pub fn content_type(self) -> &'static str {
+        match self {
+            OutputKind::Csv => "text/csv; charset=utf-8",
+            OutputKind::Template => "text/plain; charset=utf-8",
+            OutputKind::Arrow => "application/vnd.apache.arrow.file",
+            OutputKind::Avro => "application/avro",
+            OutputKind::Ods => "application/vnd.oasis.opendocument.spreadsheet",
+            OutputKind::FixedWidth => "text/plain; charset=utf-8",
+            OutputKind::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+}
+
+/// Conversion and export settings of a document
+///
+/// Saved with the configuration as the settings of new tabs; fields missing from a saved
+/// configuration keep their defaults, and secrets are never saved.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// CSV delimiter character
+    pub delimiter: String,
+    /// Whether to include headers in the CSV output
+    pub include_headers: bool,
+    /// Whether to quote fields in the CSV output
+    pub quote_fields: bool,
+    /// Whether decimal numbers in the CSV output are written with a comma, as `1,5`
+    pub decimal_comma: bool,
+    /// How ISO 8601 dates in the CSV output are written
+    pub date_format: DateFormat,
+    /// Character encoding of the CSV and fixed-width output
+    pub encoding: TextEncoding,
+    /// Line breaks ending the rows of the CSV and fixed-width output
+    pub line_endings: LineEndings,
+    /// Maximum number of rows to show in preview
+    pub max_preview_rows: usize,
+    /// Maximum number of characters per cell; longer values are truncated
+    pub max_cell_length: Option<usize>,
+    /// How line breaks inside field values are handled
+    pub newline_handling: NewlineHandling,
+    /// Replacement token used with `NewlineHandling::Custom`
+    pub newline_token: String,
+    /// Normalization and control character cleanup of cells
+    pub text_cleanup: TextCleanup,
+    /// Whether saving to an existing file appends rows instead of overwriting it
+    pub append_mode: bool,
+    /// Whether existing files are kept by saving under a numbered name instead
+    pub auto_rename: bool,
+    /// Directory the save dialog starts in
+    pub output_dir: Option<PathBuf>,
+    /// Whether loading a file immediately starts the conversion
+    pub convert_on_load: bool,
+    /// Whether a summary window opens when a conversion finishes
+    pub show_summary: bool,
+    /// Whether input that is not strict JSON is parsed again as JSON5
+    pub json5_fallback: bool,
+    /// How JSON objects with repeated keys are read
+    pub duplicate_keys: DuplicateKeys,
+    /// JSON Pointer of the array holding the records, such as `/data/items`; empty for the
+    /// whole input
+    pub records_path: String,
+    /// Column holding the keys of input that maps keys to records, such as
+    /// `{"user1": {...}, "user2": {...}}`; empty to read such input as a single record
+    pub key_column: String,
+    /// How GeoJSON geometries become columns
+    pub geometry_columns: GeometryColumns,
+    /// Delimiter, quote and header of CSV input, where they are not sniffed
+    pub csv_input: CsvDialect,
+    /// What happens to array records whose number of fields differs from the columns
+    pub ragged_rows: RaggedRows,
+    /// Whether a first pass over all records collects the columns before any row is
+    /// written, instead of taking them from the first record
+    pub two_pass: bool,
+    /// Files larger than this many megabytes are not loaded into memory as a whole
+    pub large_file_threshold_mb: u64,
+    /// What to do with files above the threshold
+    pub large_file_action: LargeFileAction,
+    /// Whether streamed conversions to CSV save checkpoints to resume from, see `checkpoint`
+    pub checkpoints: bool,
+    /// Number of threads conversions run on, shared by all running jobs; 0 for one per core
+    pub worker_threads: usize,
+    /// Whether the conversion threads run at the lowest priority of the operating system
+    pub low_priority: bool,
+    /// Rhai script run on every record before it is written; empty for none
+    pub transform_script: String,
+    /// Values of columns whose key a record lacks, instead of an empty cell
+    pub column_defaults: Vec<ColumnDefault>,
+    /// Patterns of columns left out unless selected, with `*` and `?` as wildcards
+    pub exclude_columns: Vec<String>,
+    /// Whether columns that are empty or `null` in every row are left out
+    pub drop_empty_columns: bool,
+    /// Patterns of columns whose text is stripped down to the number it holds, e.g.
+    /// `$1,234.50` to `1234.50`
+    pub number_columns: Vec<String>,
+    /// Patterns of columns whose values are written as their JSON text, strings in quotes
+    /// and without any cleanup
+    pub json_columns: Vec<String>,
+    /// Whether the JSON text of those columns is indented over several lines
+    pub pretty_json: bool,
+    /// Synthetic columns added to the output
+    pub metadata_columns: MetadataColumns,
+    /// Lookup tables replacing cells of columns; cells they lack are kept
+    pub value_mappings: Vec<ValueMapping>,
+    /// Columns whose cells are hidden, e.g. emails
+    pub anonymized_columns: Vec<ColumnAnonymization>,
+    /// Text replacing the cells of masked columns
+    pub anonymization_mask: String,
+    /// Text hashed along with every cell, so hashes cannot be looked up in a table of
+    /// common values; empty for none
+    pub anonymization_salt: String,
+    /// Constraints the rendered rows are checked against
+    pub validation_rules: Vec<ColumnRule>,
+    /// What happens to rows that break a validation rule
+    pub invalid_rows: InvalidRows,
+    /// Loaded JSON Schema of the records, declaring the columns written when none are
+    /// selected, their types in typed outputs and further validation rules
+    pub json_schema: Option<JsonSchema>,
+    /// Format of the converted output
+    pub output_kind: OutputKind,
+    /// Companion file documenting the output columns, saved next to the output
+    pub data_dictionary: DictionaryFormat,
+    /// Handlebars template written once before the rows, with `columns` available
+    pub template_header: String,
+    /// Handlebars template written for every row, with the columns as variables
+    pub template_row: String,
+    /// Handlebars template written once after the rows, with `row_count` available
+    pub template_footer: String,
+    /// Edited Avro schema fields; empty to generate them from the converted values
+    pub avro_fields: Vec<AvroField>,
+    /// Widths of fixed-width output columns; columns not listed are as wide as their
+    /// longest value
+    pub fixed_widths: Vec<ColumnWidth>,
+    /// Column whose values split XLSX output into sheets; empty for a single sheet
+    pub sheet_column: String,
+    /// AWS region of S3 URIs, or the region name sent to a custom endpoint
+    pub s3_region: String,
+    /// Endpoint of an S3-compatible service; empty for AWS
+    pub s3_endpoint: String,
+    /// S3 access key; empty to use the credentials of the environment
+    pub s3_access_key: String,
+    /// S3 secret key belonging to `s3_access_key`; only saved when it consists of
+    /// references, see `secrets`
+    #[serde(skip_serializing_if = "secrets::is_plain_secret")]
+    pub s3_secret_key: String,
+    /// Client ID of the Google OAuth client used for Sheets export
+    pub google_client_id: String,
+    /// Client secret belonging to `google_client_id`; only saved when it consists of
+    /// references
+    #[serde(skip_serializing_if = "secrets::is_plain_secret")]
+    pub google_client_secret: String,
+    /// Names of the secrets saved in the keychain from the credentials manager
+    pub keychain_secrets: Vec<String>,
+}
+
+impl Settings {
+    /// Whether saving to an existing file appends to it; only CSV output can be appended
+    pub fn appends_to_existing(&self) -> bool {
+        self.append_mode && self.output_kind == OutputKind::Csv
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            delimiter: ",".to_string(),
+            include_headers: true,
+            quote_fields: true,
+            decimal_comma: false,
+            date_format: DateFormat::Iso,
+            encoding: TextEncoding::Utf8,
+            line_endings: LineEndings::Lf,
+            max_preview_rows: 100,
+            max_cell_length: None,
+            newline_handling: NewlineHandling::Keep,
+            newline_token: " | ".to_string(),
+            text_cleanup: TextCleanup::default(),
+            append_mode: false,
+            auto_rename: false,
+            output_dir: None,
+            convert_on_load: false,
+            show_summary: true,
+            json5_fallback: true,
+            duplicate_keys: DuplicateKeys::Off,
+            records_path: String::new(),
+            key_column: "id".to_string(),
+            geometry_columns: GeometryColumns::Wkt,
+            csv_input: CsvDialect::default(),
+            ragged_rows: RaggedRows::Fix,
+            two_pass: false,
+            large_file_threshold_mb: 256,
+            large_file_action: LargeFileAction::Warn,
+            checkpoints: false,
+            worker_threads: 0,
+            low_priority: false,
+            transform_script: String::new(),
+            column_defaults: Vec::new(),
+            exclude_columns: Vec::new(),
+            drop_empty_columns: false,
+            number_columns: Vec::new(),
+            json_columns: Vec::new(),
+            pretty_json: false,
+            metadata_columns: MetadataColumns::default(),
+            value_mappings: Vec::new(),
+            anonymized_columns: Vec::new(),
+            anonymization_mask: "****".to_string(),
+            anonymization_salt: String::new(),
+            validation_rules: Vec::new(),
+            invalid_rows: InvalidRows::Report,
+            json_schema: None,
+            output_kind: OutputKind::Csv,
+            data_dictionary: DictionaryFormat::Off,
+            template_header: String::new(),
+            template_row: String::new(),
+            template_footer: String::new(),
+            avro_fields: Vec::new(),
+            fixed_widths: Vec::new(),
+            sheet_column: String::new(),
+            s3_region: "us-east-1".to_string(),
+            s3_endpoint: String::new(),
+            s3_access_key: String::new(),
+            s3_secret_key: String::new(),
+            google_client_id: String::new(),
+            google_client_secret: String::new(),
+            keychain_secrets: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Google Sheets export
+//!
+//! Converted rows can be pushed to a new spreadsheet. Access is granted with Google's OAuth
+//! flow for installed apps: the consent page opens in the browser and redirects back to a
+//! short-lived server on the loopback interface, which receives the authorization code.
+//! The client ID and secret come from an OAuth client of type "Desktop app" created in the
+//! Google Cloud console.
+
+use crate::database::Table;
+use crate::schema::ColumnType;
+use crate::secrets;
+use crate::settings::Settings;
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Response, Server};
+use tracing::info;
+
+const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const SHEETS_URL: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+
+/// Lets the app create spreadsheets and access only the files it created
+const SCOPE: &str = "https://www.googleapis.com/auth/drive.file";
+
+/// How long to wait for the user to finish the consent page
+const AUTH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Asks the user for access in the browser and returns an access token
+///
+/// The client ID and secret may be references to environment variables or keychain
+/// secrets, see `secrets`.
+pub fn authorize(settings: &Settings) -> Result<String> {
+    let client_id = secrets::resolve(&settings.google_client_id).context("Failed to read the Google client ID")?;
+    if client_id.is_empty() {
+        bail!("No Google OAuth client ID set");
+    }
+    let client_secret =
+        secrets::resolve(&settings.google_client_secret).context("Failed to read the Google client secret")?;
+
+    let server =
+        Server::http("127.0.0.1:0").map_err(|e| anyhow!("Failed to start OAuth listener: {}", e))?;
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .context("OAuth listener has no port")?;
+    let redirect_uri = format!("http://127.0.0.1:{}", port);
+    let state = format!("{:016x}", rand::random::<u64>());
+
+    let auth_url = format!(
+        "{}?{}",
+        AUTH_URL,
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair("client_id", &client_id)
+            .append_pair("redirect_uri", &redirect_uri)
+            .append_pair("response_type", "code")
+            .append_pair("scope", SCOPE)
+            .append_pair("state", &state)
+            .finish()
+    );
+    info!("Opening {}", auth_url);
+    webbrowser::open(&auth_url).context("Failed to open the browser for Google sign-in")?;
+
+    let code = wait_for_code(&server, &state)?;
+
+    let response = ureq::post(TOKEN_URL)
+        .send_form(&[
+            ("code", code.as_str()),
+            ("client_id", &client_id),
+            ("client_secret", &client_secret),
+            ("redirect_uri", &redirect_uri),
+            ("grant_type", "authorization_code"),
+        ])
+        .map_err(api_error)
+        .context("Failed to get a Google access token")?;
+    let token: Value = response.into_json()?;
+    token["access_token"]
+        .as_str()
+        .map(str::to_string)
+        .context("Google returned no access token")
+}
+
+/// Waits for the browser to be redirected back with the authorization code
+fn wait_for_code(server: &Server, state: &str) -> Result<String> {
+    let deadline = Instant::now() + AUTH_TIMEOUT;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let Some(request) = server.recv_timeout(remaining)? else {
+            bail!("Google sign-in timed out");
+        };
+
+        let url = request.url().to_string();
+        let Some(("/", query)) = url.split_once('?') else {
+            // Browsers also ask for a favicon and the like
+            let _ = request.respond(Response::empty(404));
+            continue;
+        };
+
+        let mut code = None;
+        let mut error = None;
+        let mut state_matches = false;
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "code" => code = Some(value.into_owned()),
+                "error" => error = Some(value.into_owned()),
+                "state" => state_matches = value == state,
+                _ => {}
+            }
+        }
+
+        let message = if error.is_some() || !state_matches {
+            "Google sign-in failed. You can close this window."
+        } else {
+            "Signed in. You can close this window and return to the converter."
+        };
+        let _ = request.respond(Response::from_string(message).with_header(
+            Header::from_bytes("Content-Type", "text/plain; charset=utf-8").unwrap(),
+        ));
+
+        if let Some(error) = error {
+            bail!("Google sign-in failed: {}", error);
+        }
+        if !state_matches {
+            bail!("Google sign-in failed: the response does not belong to this request");
+        }
+        return code.context("Google sign-in returned no authorization code");
+    }
+}
+
+/// Creates a spreadsheet with the rows of a table and returns its URL
+///
+/// Numbers and booleans are sent as such, so the sheet can calculate with them; a header
+/// row with the column names is written when headers are enabled.
+pub fn export(token: &str, title: &str, table: &Table, settings: &Settings) -> Result<String> {
+    let response = ureq::post(SHEETS_URL)
+        .set("Authorization", &format!("Bearer {}", token))
+        .send_json(json!({ "properties": { "title": title } }))
+        .map_err(api_error)
+        .context("Failed to create the spreadsheet")?;
+    let spreadsheet: Value = response.into_json()?;
+    let id = spreadsheet["spreadsheetId"]
+        .as_str()
+        .context("Google returned no spreadsheet ID")?;
+
+    let mut values = Vec::with_capacity(table.rows.len() + 1);
+    if settings.include_headers {
+        values.push(json!(table.columns));
+    }
+    for row in &table.rows {
+        let cells: Vec<Value> = table
+            .types
+            .iter()
+            .enumerate()
+            .map(|(index, column_type)| {
+                cell_value(row.get(index).map_or("", String::as_str), *column_type)
+            })
+            .collect();
+        values.push(Value::Array(cells));
+    }
+
+    // A range without a sheet name refers to the first sheet
+    ureq::put(&format!("{}/{}/values/A1", SHEETS_URL, id))
+        .set("Authorization", &format!("Bearer {}", token))
+        .query("valueInputOption", "RAW")
+        .send_json(json!({ "values": values }))
+        .map_err(api_error)
+        .context("Failed to write the rows to the spreadsheet")?;
+
+    Ok(spreadsheet["spreadsheetUrl"]
+        .as_str()
+        .map_or_else(|| format!("https://docs.google.com/spreadsheets/d/{}", id), str::to_string))
+}
+
+/// Value of a cell in the type of its column; empty cells and nulls of typed columns stay
+/// empty
+fn cell_value(cell: &str, column_type: ColumnType) -> Value {
+    let empty = || Value::String(String::new());
+    match column_type {
+        ColumnType::Integer => cell.parse::<i64>().map_or_else(|_| empty(), Value::from),
+        ColumnType::Float => cell.parse::<f64>().map_or_else(|_| empty(), Value::from),
+        ColumnType::Boolean => cell.parse::<bool>().map_or_else(|_| empty(), Value::Bool),
+        ColumnType::Text => Value::String(cell.to_string()),
+    }
+}
+
+/// Turns an HTTP error into one that carries Google's error message
+fn api_error(error: ureq::Error) -> anyhow::Error {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let body: Value = response.into_json().unwrap_or_default();
+            let message = body["error"]["message"]
+                .as_str()
+                .or_else(|| body["error_description"].as_str())
+                .unwrap_or("no details");
+            anyhow!("HTTP {}: {}", status, message)
+        }
+        error => anyhow!(error),
+    }
+}
@@ -0,0 +1,54 @@
+//! Preview snapshots
+//!
+//! The first rows of the preview can be exported on their own, to drop into a ticket or an
+//! email without the full dataset: as an HTML table with inline styles, which keeps its
+//! look when pasted into a mail client, or as a PNG image of the preview as shown.
+
+use anyhow::{Context, Result};
+use eframe::egui::ColorImage;
+
+/// Standalone HTML table of preview rows; the first row is the header when `has_header`
+/// is set
+pub fn preview_html(rows: &[Vec<String>], has_header: bool) -> String {
+    const CELL: &str = "border: 1px solid #ccc; padding: 4px 8px; text-align: left; white-space: nowrap";
+    let mut html = String::from(
+        "<table style=\"border-collapse: collapse; font-family: sans-serif; font-size: 13px\">\n",
+    );
+    for (index, row) in rows.iter().enumerate() {
+        let header = has_header && index == 0;
+        let (tag, style) = if header {
+            ("th", format!("{}; background: #f0f0f0", CELL))
+        } else {
+            ("td", CELL.to_string())
+        };
+        html.push_str("  <tr>");
+        for cell in row {
+            html.push_str(&format!("<{} style=\"{}\">{}</{}>", tag, style, escape(cell), tag));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// Text with the characters that are special in HTML replaced by entities
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// PNG file of a screenshot region
+pub fn encode_png(image: &ColorImage) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, image.width() as u32, image.height() as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().context("Failed to encode the image")?;
+    writer
+        .write_image_data(image.as_raw())
+        .context("Failed to encode the image")?;
+    writer.finish().context("Failed to encode the image")?;
+    Ok(bytes)
+}
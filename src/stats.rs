@@ -0,0 +1,66 @@
+//! Column statistics
+//!
+//! Hovering a column header in the preview shows a summary of that column over the
+//! preview rows, computed the first time it is asked for.
+
+use crate::schema::ColumnType;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// Summary of the values of one column
+pub struct ColumnStats {
+    pub column_type: ColumnType,
+    pub rows: usize,
+    /// Empty cells and JSON `null`s
+    pub nulls: usize,
+    /// Number of different non-null values
+    pub distinct: usize,
+    /// Smallest and largest non-null value, compared as numbers in numeric columns
+    pub min: Option<String>,
+    pub max: Option<String>,
+}
+
+impl ColumnStats {
+    /// Computes the statistics of a column from its cells
+    pub fn compute<'a>(values: impl Iterator<Item = &'a str> + Clone) -> Self {
+        let column_type = ColumnType::infer(values.clone());
+        let numeric = matches!(column_type, ColumnType::Integer | ColumnType::Float);
+        let compare = |a: &&str, b: &&str| -> Ordering {
+            if numeric {
+                let (a, b) = (a.parse::<f64>().unwrap_or(0.0), b.parse::<f64>().unwrap_or(0.0));
+                a.total_cmp(&b)
+            } else {
+                a.cmp(b)
+            }
+        };
+
+        let mut rows = 0;
+        let mut nulls = 0;
+        let mut distinct = HashSet::new();
+        for value in values {
+            rows += 1;
+            if value.is_empty() || value == "null" {
+                nulls += 1;
+            } else {
+                distinct.insert(value);
+            }
+        }
+        Self {
+            column_type,
+            rows,
+            nulls,
+            min: distinct.iter().copied().min_by(compare).map(|value| value.to_string()),
+            max: distinct.iter().copied().max_by(compare).map(|value| value.to_string()),
+            distinct: distinct.len(),
+        }
+    }
+
+    /// Share of null cells in percent
+    pub fn null_percent(&self) -> f64 {
+        if self.rows == 0 {
+            0.0
+        } else {
+            self.nulls as f64 * 100.0 / self.rows as f64
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Conversion summary
+//!
+//! When a conversion finishes, a window sums up what it produced: the rows and columns
+//! written, the records skipped and rows found invalid, how long it took, the size of the
+//! output and any warnings, such as truncated cells or repeated keys, with a table of the
+//! columns whose values were not converted without loss.
+
+use crate::conversion::{format_bytes, format_duration, ConversionResult};
+use crate::lossy::ColumnLoss;
+use eframe::egui;
+use std::time::Duration;
+
+/// What a finished conversion produced
+pub struct ConversionSummary {
+    pub records: usize,
+    pub rows: usize,
+    pub columns: Vec<String>,
+    /// Records of another shape than the first
+    pub skipped_records: usize,
+    /// Rows that broke a validation rule, and whether they were left out
+    pub invalid_rows: usize,
+    pub rejected: bool,
+    /// Cells changed by the text cleanup
+    pub cleaned_cells: usize,
+    pub duration: Duration,
+    pub output_bytes: usize,
+    /// Notes on the conversion, in the order they are listed
+    pub warnings: Vec<String>,
+    /// Columns with lossy cells, listed in their own table
+    pub losses: Vec<ColumnLoss>,
+}
+
+impl ConversionSummary {
+    /// Sums up a result before the document takes it over
+    pub fn new(result: &ConversionResult) -> Self {
+        let mut warnings = Vec::new();
+        if let Some(rows) = result.resumed_rows {
+            warnings.push(format!("resumed from a checkpoint after {} rows", rows));
+        }
+        if result.truncated_cells > 0 {
+            warnings.push(format!("{} cells truncated", result.truncated_cells));
+        }
+        warnings.extend(result.duplicate_keys.summary());
+        warnings.extend(result.ragged.summary());
+        warnings.extend(result.validation.summary());
+        warnings.extend(result.losses.summary());
+        let timings = &result.timings;
+        Self {
+            records: result.records,
+            rows: result.rows,
+            columns: result.columns.clone(),
+            skipped_records: result.skipped_records,
+            invalid_rows: result.validation.invalid_rows,
+            rejected: result.validation.rejected,
+            cleaned_cells: result.cleaned_cells,
+            duration: timings.parse + timings.transform + timings.write,
+            output_bytes: result.csv_content.len(),
+            warnings,
+            losses: result.losses.columns.clone(),
+        }
+    }
+
+    /// Status line of the conversion, with the warnings
+    pub fn status(&self) -> String {
+        if self.warnings.is_empty() {
+            "Conversion completed successfully".to_string()
+        } else {
+            format!("Conversion completed successfully ({})", self.warnings.join("; "))
+        }
+    }
+
+    /// Displays the summary; `show_after_conversion` is the setting that opens it
+    pub fn show(&self, ui: &mut egui::Ui, show_after_conversion: &mut bool) {
+        egui::Grid::new("conversion_summary").show(ui, |ui| {
+            ui.label("Rows written:");
+            ui.label(self.rows.to_string());
+            ui.end_row();
+            ui.label("Records read:");
+            ui.label(self.records.to_string());
+            ui.end_row();
+            ui.label("Columns:");
+            ui.label(self.columns.len().to_string())
+                .on_hover_text(self.columns.join(", "));
+            ui.end_row();
+            if self.skipped_records > 0 {
+                ui.label("Skipped records:");
+                ui.label(self.skipped_records.to_string())
+                    .on_hover_text("Records of another shape than the first, e.g. scalars among objects");
+                ui.end_row();
+            }
+            if self.invalid_rows > 0 {
+                ui.label(if self.rejected { "Rejected rows:" } else { "Invalid rows:" });
+                ui.label(self.invalid_rows.to_string());
+                ui.end_row();
+            }
+            if self.cleaned_cells > 0 {
+                ui.label("Cleaned cells:");
+                ui.label(self.cleaned_cells.to_string())
+                    .on_hover_text("Cells changed by the Unicode normalization or control character cleanup");
+                ui.end_row();
+            }
+            ui.label("Duration:");
+            // Most conversions take seconds, which `mm:ss` would round away
+            ui.label(if self.duration.as_secs() < 60 {
+                format!("{:.2} s", self.duration.as_secs_f64())
+            } else {
+                format_duration(self.duration)
+            });
+            ui.end_row();
+            ui.label("Output size:");
+            ui.label(format_bytes(self.output_bytes));
+            ui.end_row();
+        });
+        if !self.warnings.is_empty() {
+            ui.add_space(6.0);
+            ui.strong("Warnings");
+            for warning in &self.warnings {
+                ui.colored_label(ui.visuals().warn_fg_color, format!("• {}", warning));
+            }
+        }
+        if !self.losses.is_empty() {
+            ui.add_space(6.0);
+            ui.strong("Lossy values");
+            egui::ScrollArea::vertical().max_height(160.0).show(ui, |ui| {
+                egui::Grid::new("conversion_losses").striped(true).show(ui, |ui| {
+                    ui.label("Column");
+                    ui.label("Issue");
+                    ui.label("Cells");
+                    ui.label("Rows");
+                    ui.end_row();
+                    for loss in &self.losses {
+                        let rows: Vec<String> = loss.rows.iter().map(usize::to_string).collect();
+                        let more = if loss.cells > loss.rows.len() { ", …" } else { "" };
+                        ui.label(&loss.column);
+                        ui.label(loss.loss.describe());
+                        ui.label(loss.cells.to_string());
+                        ui.label(format!("{}{}", rows.join(", "), more));
+                        ui.end_row();
+                    }
+                });
+            });
+        }
+        ui.add_space(6.0);
+        ui.checkbox(show_after_conversion, "Show after every conversion");
+    }
+}
@@ -0,0 +1,126 @@
+//! Custom themes
+//!
+//! A theme adjusts the dark or light visuals with an accent colour, the colour of the
+//! striped rows in grids, the interface font and the text size. It is saved with the
+//! configuration and can be shared as a JSON file.
+
+use anyhow::{bail, Context, Result};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Font used for the interface text
+#[derive(Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum UiFont {
+    /// egui's built-in proportional font
+    #[default]
+    Default,
+    /// The built-in monospace font, also for labels and buttons
+    Monospace,
+    /// A TrueType or OpenType font file
+    File(PathBuf),
+}
+
+/// Colours and fonts on top of the dark or light visuals
+///
+/// `None` colours keep those of the visuals.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Colour of selections, hyperlinks and the active tab, as RGB
+    pub accent: Option<[u8; 3]>,
+    /// Background of every other row in the preview and other striped grids
+    pub stripe: Option<[u8; 3]>,
+    pub font: UiFont,
+    /// Factor applied to all text sizes
+    pub text_scale: f32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            accent: None,
+            stripe: None,
+            font: UiFont::Default,
+            text_scale: 1.0,
+        }
+    }
+}
+
+impl Theme {
+    /// Applies the theme on top of the dark or light visuals
+    ///
+    /// The colours and sizes are applied even when the font file cannot be used; the
+    /// error tells why the built-in font is shown instead.
+    pub fn apply(&self, ctx: &egui::Context, dark_mode: bool) -> Result<()> {
+        let mut visuals = if dark_mode {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        };
+        if let Some([r, g, b]) = self.accent {
+            let accent = egui::Color32::from_rgb(r, g, b);
+            visuals.selection.bg_fill = accent;
+            visuals.hyperlink_color = accent;
+        }
+        if let Some([r, g, b]) = self.stripe {
+            visuals.faint_bg_color = egui::Color32::from_rgb(r, g, b);
+        }
+
+        let mut style = egui::Style {
+            visuals,
+            ..Default::default()
+        };
+        for font_id in style.text_styles.values_mut() {
+            font_id.size *= self.text_scale;
+        }
+        ctx.set_style(style);
+
+        let mut fonts = egui::FontDefinitions::default();
+        let result = match &self.font {
+            UiFont::Default => Ok(()),
+            UiFont::Monospace => {
+                let monospace = fonts.families[&egui::FontFamily::Monospace].clone();
+                fonts.families.insert(egui::FontFamily::Proportional, monospace);
+                Ok(())
+            }
+            UiFont::File(path) => read_font(path).map(|data| {
+                fonts.font_data.insert("custom".to_string(), egui::FontData::from_owned(data));
+                // Built-in fonts stay behind it for characters the file lacks
+                fonts
+                    .families
+                    .entry(egui::FontFamily::Proportional)
+                    .or_default()
+                    .insert(0, "custom".to_string());
+            }),
+        };
+        ctx.set_fonts(fonts);
+        result
+    }
+}
+
+/// Reads a font file, checking that it is a TrueType or OpenType font
+///
+/// egui gives up on fonts it cannot parse, so anything else is refused up front.
+fn read_font(path: &Path) -> Result<Vec<u8>> {
+    let data = fs::read(path).with_context(|| format!("Failed to read font {}", path.display()))?;
+    let known = [&[0, 1, 0, 0][..], b"OTTO", b"true", b"ttcf"];
+    if !known.iter().any(|magic| data.starts_with(magic)) {
+        bail!("{} is not a TrueType or OpenType font", path.display());
+    }
+    Ok(data)
+}
+
+/// Loads a theme from a JSON file
+pub fn load(path: &Path) -> Result<Theme> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read theme {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Invalid theme {}", path.display()))
+}
+
+/// Saves a theme to a JSON file
+pub fn save(path: &Path, theme: &Theme) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(theme)?)
+        .with_context(|| format!("Failed to write theme {}", path.display()))
+}
@@ -0,0 +1,120 @@
+//! Built-in cell transforms
+//!
+//! Transforms that need no script, applied to the cells of chosen columns while rows are
+//! rendered.
+
+use crate::settings::{Anonymization, ControlCharacters, Settings, TextCleanup};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::fmt::Write;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// Zero-width spaces, joiners and the byte order mark, invisible in most fonts
+const ZERO_WIDTH: [char; 5] = ['\u{200b}', '\u{200c}', '\u{200d}', '\u{2060}', '\u{feff}'];
+
+/// Characters grouping the digits of a number, e.g. the commas of `1,234,567`
+const GROUP_SEPARATORS: [char; 5] = [',', '\'', ' ', '\u{a0}', '\u{202f}'];
+
+/// Strips currency symbols, thousands separators and units from a number in text
+///
+/// `"$1,234.50"` becomes `1234.50`, `"12 kg"` becomes `12` and `"(45.00 €)"` becomes
+/// `-45.00`, as accounting notation marks negative amounts with parentheses. The decimal
+/// separator is a point. Returns `None` for text that is not a single decorated number,
+/// such as `"1.2.3"` or `"from 3 to 5"`, which is then written as it is.
+pub fn strip_number(text: &str) -> Option<String> {
+    let text = text.trim();
+    let (text, parenthesized) = match text.strip_prefix('(').and_then(|inner| inner.strip_suffix(')')) {
+        Some(inner) => (inner.trim(), true),
+        None => (text, false),
+    };
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let end = text.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let (prefix, digits) = (&text[..start], &text[start..end]);
+    // A leading decimal point belongs to the number, as in `$.50`
+    let (prefix, leading_point) = match prefix.strip_suffix('.') {
+        Some(prefix) => (prefix, true),
+        None => (prefix, false),
+    };
+
+    let mut number = String::with_capacity(digits.len() + 2);
+    if parenthesized || prefix.contains(['-', '\u{2212}']) {
+        number.push('-');
+    }
+    if leading_point {
+        number.push_str("0.");
+    }
+    let mut has_point = leading_point;
+    for c in digits.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            '.' if !has_point => {
+                has_point = true;
+                number.push(c);
+            }
+            c if GROUP_SEPARATORS.contains(&c) => {}
+            _ => return None,
+        }
+    }
+    Some(number)
+}
+
+/// Hides a rendered cell of an anonymized column
+///
+/// Empty cells stay empty. Hashes are the lowercase hex SHA-256 of the salt followed by
+/// the cell. Dropped columns never reach this point and keep their cell.
+pub fn anonymize(cell: &str, method: Anonymization, settings: &Settings) -> String {
+    if cell.is_empty() {
+        return String::new();
+    }
+    match method {
+        Anonymization::Hash => {
+            let digest = Sha256::new()
+                .chain_update(settings.anonymization_salt.as_bytes())
+                .chain_update(cell.as_bytes())
+                .finalize();
+            digest.iter().fold(String::with_capacity(64), |mut hex, byte| {
+                // Writing to a String cannot fail
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            })
+        }
+        Anonymization::Mask => settings.anonymization_mask.clone(),
+        Anonymization::Drop => cell.to_string(),
+    }
+}
+
+/// Cleans up the text of a cell; `None` when it needs no change
+///
+/// Text is brought to Unicode normalization form C, so an `e` followed by a combining
+/// accent becomes a single `é`. Control characters and zero-width characters are then
+/// removed or escaped as `\u001B`, `\u200B` and so on; tabs and line breaks are left to
+/// the newline setting.
+pub fn clean_text(text: &str, cleanup: &TextCleanup) -> Option<String> {
+    let text = if cleanup.normalize && !is_nfc(text) {
+        Cow::Owned(text.nfc().collect())
+    } else {
+        Cow::Borrowed(text)
+    };
+    let unwanted = |c: char| ZERO_WIDTH.contains(&c) || (c.is_control() && !matches!(c, '\t' | '\n' | '\r'));
+    let text = match cleanup.control_characters {
+        ControlCharacters::Keep => text,
+        _ if !text.contains(unwanted) => text,
+        ControlCharacters::Strip => Cow::Owned(text.chars().filter(|&c| !unwanted(c)).collect()),
+        ControlCharacters::Escape => {
+            let mut escaped = String::with_capacity(text.len() + 8);
+            for c in text.chars() {
+                if unwanted(c) {
+                    // Writing to a String cannot fail
+                    let _ = write!(escaped, "\\u{:04X}", c as u32);
+                } else {
+                    escaped.push(c);
+                }
+            }
+            Cow::Owned(escaped)
+        }
+    };
+    match text {
+        Cow::Owned(text) => Some(text),
+        Cow::Borrowed(_) => None,
+    }
+}
@@ -0,0 +1,61 @@
+//! Update check
+//!
+//! When enabled in the settings, the latest release is looked up on GitHub at startup, and
+//! a banner links to its download page if it is newer than the running version.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/moeinghaeini/json_to_csv_converter/releases/latest";
+
+/// A release newer than the running version
+pub struct Release {
+    pub version: String,
+    /// Release page with the downloads
+    pub url: String,
+}
+
+/// Looks up the latest release; `None` when the running version is up to date
+pub fn check() -> Result<Option<Release>> {
+    let release: Value = ureq::get(LATEST_RELEASE_URL)
+        // GitHub rejects API requests without a user agent
+        .set("User-Agent", concat!("json_to_csv_converter/", env!("CARGO_PKG_VERSION")))
+        .set("Accept", "application/vnd.github+json")
+        .call()
+        .context("Failed to check for updates")?
+        .into_json()?;
+
+    let tag = release["tag_name"]
+        .as_str()
+        .context("Release without a tag")?;
+    let version = tag.trim_start_matches('v');
+    if !is_newer(version, env!("CARGO_PKG_VERSION")) {
+        return Ok(None);
+    }
+    Ok(Some(Release {
+        version: version.to_string(),
+        url: release["html_url"].as_str().unwrap_or_default().to_string(),
+    }))
+}
+
+/// Whether `version` is newer than `current`, comparing the dotted numbers one by one
+///
+/// Pre-release suffixes such as `-beta` are ignored.
+fn is_newer(version: &str, current: &str) -> bool {
+    let numbers = |version: &str| -> Vec<u64> {
+        let mut numbers: Vec<u64> = version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect();
+        // `1.2` and `1.2.0` are the same version
+        while numbers.last() == Some(&0) {
+            numbers.pop();
+        }
+        numbers
+    };
+    numbers(version) > numbers(current)
+}
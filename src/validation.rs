@@ -0,0 +1,251 @@
+//! Validation rules
+//!
+//! Rules name a column and the constraints its cells must meet: present in the output,
+//! non-empty, of a type, within a numeric range or matching a regular expression. Every
+//! rendered row is checked before it is written; invalid rows are either reported and
+//! written anyway, or rejected and collected in a separate CSV with the reasons, saved as
+//! `rejected.csv` next to the output. Reasons name the record, the JSON Pointer of the
+//! offending value and the value as read, so it can be found in a large input. Rules are
+//! edited in the settings, or derived from a JSON Schema, see `schema::JsonSchema`.
+
+use crate::output::write_atomically;
+use crate::schema::ColumnType;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Number of invalid rows listed in the summary
+const MAX_EXAMPLES: usize = 5;
+
+/// File name of the rejected rows, saved next to the output
+pub const REJECTED_FILE_NAME: &str = "rejected.csv";
+
+/// Constraints on the cells of one column
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnRule {
+    pub column: String,
+    /// The conversion fails when the column is not in the output
+    pub required: bool,
+    pub non_empty: bool,
+    /// Type non-empty cells other than `null` must have; `None` for any
+    pub column_type: Option<ColumnType>,
+    /// Smallest and largest allowed number; non-empty cells must then be numbers
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Regular expression non-empty cells must match; empty for none
+    pub pattern: String,
+}
+
+/// What happens to rows that break a rule
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InvalidRows {
+    /// Count and list them, but write them like valid rows
+    #[default]
+    Report,
+    /// Leave them out of the output and collect them in `rejected.csv`
+    Reject,
+}
+
+/// Invalid rows found by a conversion
+#[derive(Default)]
+pub struct ValidationReport {
+    pub invalid_rows: usize,
+    /// Whether the invalid rows were left out of the output
+    pub rejected: bool,
+    /// Row number, record number and reasons of the first invalid rows
+    pub examples: Vec<(usize, usize, String)>,
+    /// Rejected rows as CSV, with `record` and `reason` columns; `None` unless rows were
+    /// rejected
+    pub rejected_csv: Option<Vec<u8>>,
+}
+
+impl ValidationReport {
+    /// Warning listing the invalid rows, if there are any
+    pub fn summary(&self) -> Option<String> {
+        if self.invalid_rows == 0 {
+            return None;
+        }
+        let examples: Vec<String> = self
+            .examples
+            .iter()
+            .map(|(row, record, reason)| format!("row {} (record {}): {}", row, record, reason))
+            .collect();
+        let more = if self.invalid_rows > self.examples.len() { "; ..." } else { "" };
+        let verb = if self.rejected { "rejected" } else { "invalid" };
+        Some(format!(
+            "{} rows {} ({}{})",
+            self.invalid_rows,
+            verb,
+            examples.join("; "),
+            more
+        ))
+    }
+}
+
+/// Saves rejected rows as `rejected.csv` in the directory of the output and returns its
+/// path
+pub fn save_rejected(output: &Path, rejected_csv: &[u8]) -> Result<PathBuf> {
+    let path = output.with_file_name(REJECTED_FILE_NAME);
+    write_atomically(&path, rejected_csv).context("Failed to save rejected rows")?;
+    Ok(path)
+}
+
+/// A rule bound to the position of its column in the output
+struct Check {
+    index: usize,
+    rule: ColumnRule,
+    pattern: Option<Regex>,
+}
+
+/// Checks rendered rows against the rules
+pub struct Validator {
+    checks: Vec<Check>,
+    mode: InvalidRows,
+    rejected: Option<csv::Writer<Vec<u8>>>,
+    report: ValidationReport,
+}
+
+impl Validator {
+    /// Binds the rules to the output columns; `None` when there are no rules
+    ///
+    /// Fails when a required column is missing or a pattern is not a valid regular
+    /// expression. Rules for other missing columns are ignored.
+    pub fn new(rules: &[ColumnRule], columns: &[String], mode: InvalidRows) -> Result<Option<Self>> {
+        let mut checks = Vec::new();
+        for rule in rules.iter().filter(|rule| !rule.column.is_empty()) {
+            let Some(index) = columns.iter().position(|column| *column == rule.column) else {
+                if rule.required {
+                    bail!("Required column {:?} is missing", rule.column);
+                }
+                continue;
+            };
+            let pattern = if rule.pattern.is_empty() {
+                None
+            } else {
+                Some(Regex::new(&rule.pattern).with_context(|| {
+                    format!("Invalid pattern for column {:?}", rule.column)
+                })?)
+            };
+            checks.push(Check {
+                index,
+                rule: rule.clone(),
+                pattern,
+            });
+        }
+        if checks.is_empty() {
+            return Ok(None);
+        }
+
+        let rejected = match mode {
+            InvalidRows::Report => None,
+            InvalidRows::Reject => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                writer
+                    .write_record(columns.iter().map(String::as_str).chain(["record", "reason"]))
+                    .context("Failed to write rejected rows")?;
+                Some(writer)
+            }
+        };
+        Ok(Some(Self {
+            checks,
+            mode,
+            rejected,
+            report: ValidationReport {
+                rejected: mode == InvalidRows::Reject,
+                ..Default::default()
+            },
+        }))
+    }
+
+    /// Checks a row and returns whether it is to be written
+    ///
+    /// `row` is the number of the row among all rendered rows and `record` the number of
+    /// the input record it came from, used in the report. `origin` describes where the cell
+    /// of a column came from, see `conversion::cell_origin`; it is only asked for invalid
+    /// cells.
+    pub fn check(
+        &mut self,
+        row: usize,
+        record: usize,
+        values: &[String],
+        origin: impl Fn(usize) -> String,
+    ) -> Result<bool> {
+        let reasons: Vec<String> = self
+            .checks
+            .iter()
+            .filter_map(|check| {
+                let cell = values.get(check.index).map_or("", String::as_str);
+                violation(check, cell)
+                    .map(|reason| format!("{}: {} ({})", check.rule.column, reason, origin(check.index)))
+            })
+            .collect();
+        if reasons.is_empty() {
+            return Ok(true);
+        }
+
+        let reason = reasons.join("; ");
+        self.report.invalid_rows += 1;
+        if let Some(writer) = &mut self.rejected {
+            let record_number = record.to_string();
+            writer
+                .write_record(values.iter().map(String::as_str).chain([record_number.as_str(), reason.as_str()]))
+                .context("Failed to write rejected rows")?;
+        }
+        if self.report.examples.len() < MAX_EXAMPLES {
+            self.report.examples.push((row, record, reason));
+        }
+        Ok(self.mode == InvalidRows::Report)
+    }
+
+    /// Completes the report with the rejected rows
+    pub fn finish(mut self) -> Result<ValidationReport> {
+        if let Some(writer) = self.rejected {
+            if self.report.invalid_rows > 0 {
+                let rejected = writer
+                    .into_inner()
+                    .map_err(|e| e.into_error())
+                    .context("Failed to write rejected rows")?;
+                self.report.rejected_csv = Some(rejected);
+            }
+        }
+        Ok(self.report)
+    }
+}
+
+/// Why a cell breaks a rule, if it does
+fn violation(check: &Check, cell: &str) -> Option<String> {
+    let rule = &check.rule;
+    if cell.is_empty() {
+        return rule.non_empty.then(|| "is empty".to_string());
+    }
+    let matches_type = match rule.column_type {
+        _ if cell == "null" => true,
+        Some(ColumnType::Integer) => cell.parse::<i64>().is_ok(),
+        Some(ColumnType::Float) => cell.parse::<f64>().is_ok(),
+        Some(ColumnType::Boolean) => cell == "true" || cell == "false",
+        Some(ColumnType::Text) | None => true,
+    };
+    if !matches_type {
+        let label = rule.column_type.map_or("", ColumnType::label);
+        return Some(format!("{:?} is not of type {}", cell, label));
+    }
+    if rule.min.is_some() || rule.max.is_some() {
+        let Ok(number) = cell.parse::<f64>() else {
+            return Some(format!("{:?} is not a number", cell));
+        };
+        if let Some(min) = rule.min.filter(|min| number < *min) {
+            return Some(format!("{} is below the minimum {}", cell, min));
+        }
+        if let Some(max) = rule.max.filter(|max| number > *max) {
+            return Some(format!("{} is above the maximum {}", cell, max));
+        }
+    }
+    match &check.pattern {
+        Some(pattern) if !pattern.is_match(cell) => {
+            Some(format!("{:?} does not match {}", cell, pattern.as_str()))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,273 @@
+//! Conversion wizard
+//!
+//! A window leading through a conversion one step at a time, for users who find the
+//! settings panel overwhelming: pick the file, choose the array holding the records, tick
+//! the columns, choose the output format and convert. Every step edits the active tab as
+//! the main window does, so the wizard can be closed at any point and the conversion
+//! continued with the full settings.
+
+use crate::document::{show_column_tree, Document};
+use crate::input::{self, InputFormat};
+use crate::locale::TargetLocale;
+use crate::settings::OutputKind;
+use eframe::egui;
+
+/// Steps of the wizard, in order
+#[derive(Default, Clone, Copy, PartialEq)]
+enum Step {
+    #[default]
+    File,
+    Records,
+    Columns,
+    Output,
+    Convert,
+}
+
+impl Step {
+    const ALL: [Step; 5] = [Step::File, Step::Records, Step::Columns, Step::Output, Step::Convert];
+
+    /// Heading of the step
+    fn title(self) -> &'static str {
+        match self {
+            Step::File => "Select File",
+            Step::Records => "Choose Records",
+            Step::Columns => "Pick Columns",
+            Step::Output => "Output Options",
+            Step::Convert => "Convert",
+        }
+    }
+
+    /// Position of the step, counted from 0
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|step| *step == self).unwrap_or_default()
+    }
+}
+
+/// What the user asked for in the wizard that the application carries out
+pub enum WizardRequest {
+    OpenFile,
+    Convert,
+    Save,
+}
+
+/// State of the wizard window
+#[derive(Default)]
+pub struct Wizard {
+    open: bool,
+    step: Step,
+    /// Arrays of records of the loaded input as JSON Pointers with their number of
+    /// records, listed when the records step is entered
+    arrays: Vec<(String, usize)>,
+    /// Why the arrays could not be listed, if they could not
+    arrays_note: Option<String>,
+}
+
+impl Wizard {
+    /// Opens the wizard at its first step, or closes it when it is open
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.step = Step::File;
+    }
+
+    /// Displays the wizard while it is open for the active tab and returns what the user
+    /// asked for
+    pub fn show(&mut self, ctx: &egui::Context, document: &mut Document) -> Option<WizardRequest> {
+        if !self.open {
+            return None;
+        }
+        let mut request = None;
+        let mut open = self.open;
+        egui::Window::new("Conversion Wizard")
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                ui.weak(format!("Step {} of {}", self.step.index() + 1, Step::ALL.len()));
+                ui.heading(self.step.title());
+                ui.add_space(8.0);
+
+                match self.step {
+                    Step::File => request = show_file(ui, document),
+                    Step::Records => self.show_records(ui, document),
+                    Step::Columns => show_columns(ui, document),
+                    Step::Output => show_output(ui, document),
+                    Step::Convert => request = self.show_convert(ui, document),
+                }
+
+                if let Some(error) = &document.error_message {
+                    ui.add_space(8.0);
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
+                ui.add_space(8.0);
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let index = self.step.index();
+                    if ui.add_enabled(index > 0, egui::Button::new("< Back")).clicked() {
+                        self.step = Step::ALL[index - 1];
+                    }
+                    if index + 1 < Step::ALL.len() {
+                        let has_input = document.json_path.is_some() || document.source_uri.is_some();
+                        let enabled = self.step != Step::File || has_input;
+                        if ui.add_enabled(enabled, egui::Button::new("Next >")).clicked() {
+                            self.step = Step::ALL[index + 1];
+                            if self.step == Step::Records {
+                                self.list_arrays(document);
+                            }
+                        }
+                    }
+                });
+            });
+        // "Finish" closes the wizard from within the window
+        self.open &= open;
+        request
+    }
+
+    /// Finds the arrays of records of the loaded input
+    ///
+    /// The input is parsed as a whole, ignoring the records path, which is fine as inputs
+    /// too large for it are streamed instead of loaded.
+    fn list_arrays(&mut self, document: &Document) {
+        self.arrays.clear();
+        self.arrays_note = None;
+        let format = document.json_path.as_deref().map_or(InputFormat::Json, InputFormat::from_path);
+        let Some(content) = document.json_content.as_deref().filter(|_| format != InputFormat::Csv) else {
+            self.arrays_note = Some(if format == InputFormat::Csv {
+                "Every line of a CSV file is a record".to_string()
+            } else {
+                "The file is too large to look into; enter the path of the records below".to_string()
+            });
+            return;
+        };
+        let mut settings = document.settings.clone();
+        settings.records_path.clear();
+        match input::parse(content, format, &settings) {
+            Ok(parsed) => self.arrays = input::record_arrays(&parsed.value),
+            Err(e) => self.arrays_note = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Offers the whole input and every array found in it as the records
+    fn show_records(&mut self, ui: &mut egui::Ui, document: &mut Document) {
+        ui.label("Which part of the file holds the rows of the output?");
+        ui.add_space(4.0);
+        let before = document.settings.records_path.clone();
+        let path = &mut document.settings.records_path;
+        let whole = match self.arrays.first() {
+            Some((pointer, count)) if pointer.is_empty() => format!("The whole file ({} records)", count),
+            _ => "The whole file".to_string(),
+        };
+        ui.radio_value(path, String::new(), whole);
+        egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+            for (pointer, count) in self.arrays.iter().filter(|(pointer, _)| !pointer.is_empty()) {
+                ui.radio_value(path, pointer.clone(), format!("{} ({} records)", pointer, count));
+            }
+        });
+        if let Some(note) = &self.arrays_note {
+            ui.weak(note);
+        }
+        ui.horizontal(|ui| {
+            ui.label("Records At:");
+            ui.add(egui::TextEdit::singleline(path).hint_text("/data/items"));
+        });
+        // Columns of other records are read again, see `Document::refresh_preview`
+        if document.settings.records_path != before {
+            document.reset_columns();
+        }
+    }
+
+    /// Starts the conversion, shows its progress and offers to save the output
+    fn show_convert(&mut self, ui: &mut egui::Ui, document: &mut Document) -> Option<WizardRequest> {
+        let mut request = None;
+        let progress = document.progress.lock().unwrap();
+        let (converting, fraction, status) = (progress.is_converting, progress.progress, progress.status.clone());
+        drop(progress);
+
+        if converting {
+            ui.add(egui::ProgressBar::new(fraction).show_percentage().animate(true));
+            ui.label(status);
+            return None;
+        }
+        let label = if document.csv_content.is_some() { "Convert Again" } else { "Convert" };
+        if ui.button(label).clicked() {
+            request = Some(WizardRequest::Convert);
+        }
+        if document.csv_content.is_some() {
+            ui.add_space(4.0);
+            ui.label(&document.status);
+            ui.horizontal(|ui| {
+                if ui.button("Save...").clicked() {
+                    request = Some(WizardRequest::Save);
+                }
+                if ui.button("Finish").clicked() {
+                    self.open = false;
+                }
+            });
+        }
+        request
+    }
+}
+
+/// Names the loaded file and offers to pick another
+fn show_file(ui: &mut egui::Ui, document: &Document) -> Option<WizardRequest> {
+    ui.label("Pick the file to convert: JSON, JSON5, NDJSON, GeoJSON, YAML, TOML or CSV.");
+    ui.add_space(4.0);
+    let picked = ui.button("Choose File...").clicked();
+    if document.json_path.is_some() || document.source_uri.is_some() {
+        ui.label(format!("Loaded: {}", document.title()));
+    }
+    picked.then_some(WizardRequest::OpenFile)
+}
+
+/// Column checkboxes, read from the first records
+fn show_columns(ui: &mut egui::Ui, document: &mut Document) {
+    if document.all_columns.is_empty() {
+        if document.sampling {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Reading the columns...");
+            });
+        } else {
+            ui.weak("No columns found in the first records");
+        }
+        return;
+    }
+    ui.label("Tick the columns to export; with none ticked, all of them are exported.");
+    ui.horizontal(|ui| {
+        if ui.button("All").clicked() {
+            document.selected_columns = document.all_columns.clone();
+        }
+        if ui.button("None").clicked() {
+            document.selected_columns.clear();
+        }
+    });
+    egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+        let columns: Vec<&String> = document.all_columns.iter().collect();
+        show_column_tree(ui, &columns, "", &mut document.selected_columns);
+    });
+}
+
+/// Output format, and for CSV the target locale and header row
+fn show_output(ui: &mut egui::Ui, document: &mut Document) {
+    let settings = &mut document.settings;
+    ui.label("Which kind of file should be written?");
+    ui.radio_value(&mut settings.output_kind, OutputKind::Csv, "CSV");
+    ui.radio_value(&mut settings.output_kind, OutputKind::Xlsx, "Excel (XLSX)");
+    ui.radio_value(&mut settings.output_kind, OutputKind::Ods, "OpenDocument (ODS)");
+    if settings.output_kind == OutputKind::Csv {
+        ui.add_space(4.0);
+        ui.label("Where will the file be opened?");
+        let current = TargetLocale::of(settings);
+        for locale in TargetLocale::ALL {
+            if ui.radio(current == Some(locale), locale.name()).clicked() {
+                locale.apply(settings);
+            }
+        }
+        if current.is_none() {
+            ui.weak("Custom conventions are set in the settings panel");
+        }
+    }
+    ui.checkbox(&mut settings.include_headers, "Column names in the first row");
+    ui.add_space(4.0);
+    ui.weak("Everything else can be changed in the settings panel.");
+}